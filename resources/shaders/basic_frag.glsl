@@ -1,10 +1,18 @@
 #version 330 core
 out vec4 FragColor;
 
+#ifdef INSTANCED
+in vec3 instanceColor;
+#else
 uniform vec3 color;
+#endif
 
 void main() 
 {
+#ifdef INSTANCED
+    FragColor = vec4(instanceColor, 1.0);
+#else
     FragColor = vec4(color, 1.0);
+#endif
 }
 
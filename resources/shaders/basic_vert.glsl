@@ -1,13 +1,32 @@
 #version 330 core
 layout (location = 0) in vec3 aPos;
-layout (location = 0) in vec3 aNormal;
+layout (location = 1) in vec3 aNormal;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "common/transforms.glsl"
+
+// SKINNED and INSTANCED both use the attributes from 3 on, so they don't mix
+#ifdef SKINNED
+#include "common/skinning.glsl"
+#endif
+
+#ifdef INSTANCED
+// per instance, the matrix takes locations 3 to 6
+layout (location = 3) in mat4 aModel;
+layout (location = 7) in vec3 aColor;
+out vec3 instanceColor;
+#endif
 
 void main()
 {
-    gl_Position = projection * view * model * vec4(aPos, 1.0);
+#ifdef SKINNED
+    vec4 position = skinMatrix() * vec4(aPos, 1.0);
+#else
+    vec4 position = vec4(aPos, 1.0);
+#endif
+#ifdef INSTANCED
+    instanceColor = aColor;
+    gl_Position = projection * view * aModel * position;
+#else
+    gl_Position = projection * view * model * position;
+#endif
 }
-
@@ -0,0 +1,26 @@
+// per frame data, uploaded once by FrameData in uniform_buffer.rs
+#ifndef FRAME_GLSL
+#define FRAME_GLSL
+
+// must match MAX_LIGHTS in uniform_buffer.rs
+#define MAX_LIGHTS 8
+
+struct Light
+{
+    // w is 1 for point lights, 0 for directional ones where xyz is the direction
+    vec4 position;
+    // intensity in w
+    vec4 color;
+};
+
+layout (std140) uniform Frame
+{
+    mat4 view;
+    mat4 projection;
+    vec3 cameraPosition;
+    float time;
+    int lightCount;
+    Light lights[MAX_LIGHTS];
+};
+
+#endif
@@ -0,0 +1,20 @@
+// lambert term for a point light
+#ifndef LIGHTING_GLSL
+#define LIGHTING_GLSL
+
+float diffuseLight(vec3 normal, vec3 fragPos, vec3 lightPos)
+{
+    vec3 lightDir = normalize(lightPos - fragPos);
+    return max(dot(normalize(normal), lightDir), 0.0);
+}
+
+// phong term for a point light seen from viewPos
+float specularLight(vec3 normal, vec3 fragPos, vec3 lightPos, vec3 viewPos, float shininess)
+{
+    vec3 lightDir = normalize(lightPos - fragPos);
+    vec3 viewDir = normalize(viewPos - fragPos);
+    vec3 reflectDir = reflect(-lightDir, normalize(normal));
+    return pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+}
+
+#endif
@@ -0,0 +1,19 @@
+// the joint attributes of Mesh::new_skinned meshes, MAX_JOINTS is defined by
+// the renderer
+#ifndef SKINNING_GLSL
+#define SKINNING_GLSL
+
+layout (location = 3) in vec4 aJoints;
+layout (location = 4) in vec4 aWeights;
+
+uniform mat4 joints[MAX_JOINTS];
+
+mat4 skinMatrix()
+{
+    return aWeights.x * joints[int(aJoints.x)]
+         + aWeights.y * joints[int(aJoints.y)]
+         + aWeights.z * joints[int(aJoints.z)]
+         + aWeights.w * joints[int(aJoints.w)];
+}
+
+#endif
@@ -0,0 +1,9 @@
+// view and projection come from the Frame block, model is set per draw
+#ifndef TRANSFORMS_GLSL
+#define TRANSFORMS_GLSL
+
+#include "frame.glsl"
+
+uniform mat4 model;
+
+#endif
@@ -8,16 +8,14 @@ in vec2 TexCoord;
 in vec3 Normal;
 in vec3 FragPos;
 
+#include "common/lighting.glsl"
+
 void main() 
 {
-    vec3 ambient = 0.25 * texture(texture0, TexCoord).rgb;
-
-    vec3 norm = normalize(Normal);
-    vec3 lightDir = normalize(lightPos - FragPos);
-    float diff = max(dot(norm, lightDir), 0.0);
-    vec3 diffuse = vec3(1.0, 1.0, 1.0) * diff * texture(texture0, TexCoord).rgb;
+    vec3 color = texture(texture0, TexCoord).rgb;
+    vec3 ambient = 0.25 * color;
+    vec3 diffuse = diffuseLight(Normal, FragPos, lightPos) * color;
 
     FragColor = vec4(ambient + diffuse, 1.0);
 } 
 
-
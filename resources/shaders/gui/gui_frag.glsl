@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec2 texCoord;
+in vec4 color;
+
+uniform sampler2D guiTexture;
+
+out vec4 FragColor;
+
+void main()
+{
+    FragColor = color * texture(guiTexture, texCoord);
+}
@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec2 aTexCoord;
+layout (location = 2) in vec4 aColor;
+
+// maps imgui's display rectangle, in points, to clip space
+uniform mat4 guiProjection;
+
+out vec2 texCoord;
+out vec4 color;
+
+void main()
+{
+    texCoord = aTexCoord;
+    color = aColor;
+    gl_Position = guiProjection * vec4(aPos, 0.0, 1.0);
+}
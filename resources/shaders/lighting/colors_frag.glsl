@@ -7,25 +7,21 @@ uniform vec3 lightPos;
 
 in vec3 Normal;
 in vec3 FragPos;
-in vec3 viewPos;
+
+#include "../common/frame.glsl"
+#include "../common/lighting.glsl"
 
 void main()
 {
     float ambientStrength = 0.25;
     vec3 ambient = ambientStrength * lightColor;
 
-    vec3 norm = normalize(Normal);
-    vec3 lightDir = normalize(lightPos - FragPos);
-    float diff = max(dot(norm, lightDir), 0.0);
-    vec3 diffuse = diff * lightColor;
+    vec3 diffuse = diffuseLight(Normal, FragPos, lightPos) * lightColor;
 
     float specularStrength = 0.5;
-    vec3 viewDir = normalize(viewPos - FragPos);
-    vec3 reflectDir = reflect(lightDir, norm);  
-    float spec = pow(max(dot(viewDir, reflectDir), 0.0), 32);
+    float spec = specularLight(Normal, FragPos, lightPos, cameraPosition, 32.0);
     vec3 specular = specularStrength * spec * lightColor;  
         
     vec3 result = (ambient + diffuse + specular) * objectColor;
     FragColor = vec4(result, 1.0);
 }
-
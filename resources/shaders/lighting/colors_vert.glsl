@@ -1,10 +1,8 @@
 #version 330 core 
 layout (location = 0) in vec3 aPos;
-layout (location = 0) in vec3 aNormal;
+layout (location = 1) in vec3 aNormal;
 
-uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+#include "../common/transforms.glsl"
 
 out vec3 Normal;
 out vec3 FragPos;
@@ -16,4 +14,3 @@ void main()
 
     gl_Position = projection * view * vec4(FragPos, 1.0);
 }
-
@@ -0,0 +1,596 @@
+use glm::{Mat4, Vec3};
+
+use crate::{components::Transform, physics::Quat};
+
+// the length of the joint matrix array, passed to the skinning shader as a
+// define
+pub const MAX_JOINTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation
+{
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+// a value that can be keyframed
+pub trait Keyframe: Copy
+{
+    fn lerp(
+        self,
+        other: Self,
+        t: f32,
+    ) -> Self;
+
+    // the weighted sum of four values, for the hermite basis
+    fn mix(
+        values: [Self; 4],
+        weights: [f32; 4],
+    ) -> Self;
+}
+
+impl Keyframe for Vec3
+{
+    fn lerp(
+        self,
+        other: Self,
+        t: f32,
+    ) -> Self
+    {
+        self + (other - self) * t
+    }
+
+    fn mix(
+        values: [Self; 4],
+        weights: [f32; 4],
+    ) -> Self
+    {
+        values[0] * weights[0]
+            + values[1] * weights[1]
+            + values[2] * weights[2]
+            + values[3] * weights[3]
+    }
+}
+
+impl Keyframe for Quat
+{
+    fn lerp(
+        self,
+        other: Self,
+        t: f32,
+    ) -> Self
+    {
+        self.slerp(other, t)
+    }
+
+    // component wise, then back onto the unit sphere
+    fn mix(
+        values: [Self; 4],
+        weights: [f32; 4],
+    ) -> Self
+    {
+        let sum = |component: fn(&Quat) -> f32| {
+            values
+                .iter()
+                .zip(weights)
+                .map(|(q, weight)| component(q) * weight)
+                .sum()
+        };
+        Quat {
+            w: sum(|q| q.w),
+            x: sum(|q| q.x),
+            y: sum(|q| q.y),
+            z: sum(|q| q.z),
+        }
+        .normalize()
+    }
+}
+
+// times are in seconds and never decrease, cubic spline tracks store an in
+// tangent, the value and an out tangent for every key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track<T>
+{
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Keyframe> Track<T>
+{
+    fn value(
+        &self,
+        key: usize,
+    ) -> T
+    {
+        match self.interpolation
+        {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+
+    // holds the first and last keys outside of the track
+    pub fn sample(
+        &self,
+        time: f32,
+    ) -> T
+    {
+        let last = self.times.len() - 1;
+        if time <= self.times[0]
+        {
+            return self.value(0);
+        }
+        if time >= self.times[last]
+        {
+            return self.value(last);
+        }
+        let next = self.times.partition_point(|key| *key <= time);
+        let previous = next - 1;
+        let span = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / span;
+        match self.interpolation
+        {
+            Interpolation::Step => self.value(previous),
+            Interpolation::Linear => self.value(previous).lerp(self.value(next), t),
+            Interpolation::CubicSpline =>
+            {
+                // the tangents are per second, the basis wants them per span
+                let (t2, t3) = (t * t, t * t * t);
+                T::mix(
+                    [
+                        self.value(previous),
+                        self.values[previous * 3 + 2],
+                        self.value(next),
+                        self.values[next * 3],
+                    ],
+                    [
+                        2.0 * t3 - 3.0 * t2 + 1.0,
+                        (t3 - 2.0 * t2 + t) * span,
+                        -2.0 * t3 + 3.0 * t2,
+                        (t3 - t2) * span,
+                    ],
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Keyframes
+{
+    Translation(Track<Vec3>),
+    Rotation(Track<Quat>),
+    Scale(Track<Vec3>),
+}
+
+// target is a joint index, or a node index while a clip is still attached to
+// the gltf document it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel
+{
+    pub target: usize,
+    pub keyframes: Keyframes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip
+{
+    pub name: Option<String>,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip
+{
+    // only the animated properties change, the rest of the pose is kept
+    pub fn sample(
+        &self,
+        time: f32,
+        pose: &mut Pose,
+    )
+    {
+        for channel in &self.channels
+        {
+            let Some(local) = pose.local.get_mut(channel.target)
+            else
+            {
+                continue;
+            };
+            match &channel.keyframes
+            {
+                Keyframes::Translation(track) => local.position = track.sample(time),
+                Keyframes::Rotation(track) => local.orientation = track.sample(time),
+                Keyframes::Scale(track) => local.scale = track.sample(time),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joint
+{
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    // the local transform when no clip animates the joint
+    pub rest: Transform,
+    // from the mesh's space to the joint's at bind time
+    pub inverse_bind: Mat4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skeleton
+{
+    pub joints: Vec<Joint>,
+    // where root joints hang, for nodes above the skin in the source file
+    pub root: Mat4,
+}
+
+impl Skeleton
+{
+    // each joint relative to the skeleton's root, parents can come after
+    // their children.
+    pub fn global_transforms(
+        &self,
+        pose: &Pose,
+    ) -> Vec<Mat4>
+    {
+        (0..self.joints.len())
+            .map(|joint| {
+                let mut matrix = pose.local[joint].matrix();
+                let mut parent = self.joints[joint].parent;
+                while let Some(index) = parent
+                {
+                    matrix = pose.local[index].matrix() * matrix;
+                    parent = self.joints[index].parent;
+                }
+                self.root * matrix
+            })
+            .collect()
+    }
+
+    // what the skinning shader multiplies vertices by
+    pub fn joint_matrices(
+        &self,
+        pose: &Pose,
+    ) -> Vec<Mat4>
+    {
+        self.global_transforms(pose)
+            .into_iter()
+            .zip(&self.joints)
+            .map(|(global, joint)| global * joint.inverse_bind)
+            .collect()
+    }
+}
+
+// a local transform per joint
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose
+{
+    pub local: Vec<Transform>,
+}
+
+impl Pose
+{
+    pub fn rest(skeleton: &Skeleton) -> Self
+    {
+        Self {
+            local: skeleton.joints.iter().map(|joint| joint.rest).collect(),
+        }
+    }
+
+    pub fn blend(
+        &self,
+        other: &Pose,
+        weight: f32,
+    ) -> Pose
+    {
+        Pose {
+            local: self
+                .local
+                .iter()
+                .zip(&other.local)
+                .map(|(a, b)| a.interpolate(b, weight))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Playback
+{
+    clip: usize,
+    time: f32,
+}
+
+// loops one clip at a time, crossfading from the last one when a new clip is
+// played. clips are indices into the slice handed to advance and pose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationPlayer
+{
+    current: Playback,
+    previous: Option<Playback>,
+    fade: f32,
+    fade_duration: f32,
+}
+
+impl AnimationPlayer
+{
+    pub fn new(clip: usize) -> Self
+    {
+        Self {
+            current: Playback { clip, time: 0.0 },
+            previous: None,
+            fade: 0.0,
+            fade_duration: 0.0,
+        }
+    }
+
+    pub fn clip(&self) -> usize
+    {
+        self.current.clip
+    }
+
+    pub fn play(
+        &mut self,
+        clip: usize,
+        fade_duration: f32,
+    )
+    {
+        if clip == self.current.clip
+        {
+            return;
+        }
+        self.previous = (fade_duration > 0.0).then_some(self.current);
+        self.current = Playback { clip, time: 0.0 };
+        self.fade = 0.0;
+        self.fade_duration = fade_duration;
+    }
+
+    pub fn advance(
+        &mut self,
+        delta: f32,
+        clips: &[AnimationClip],
+    )
+    {
+        for playback in std::iter::once(&mut self.current).chain(self.previous.as_mut())
+        {
+            let duration = clips[playback.clip].duration;
+            playback.time = match duration > 0.0
+            {
+                true => (playback.time + delta).rem_euclid(duration),
+                false => 0.0,
+            };
+        }
+        self.fade += delta;
+        if self.fade >= self.fade_duration
+        {
+            self.previous = None;
+        }
+    }
+
+    pub fn pose(
+        &self,
+        skeleton: &Skeleton,
+        clips: &[AnimationClip],
+    ) -> Pose
+    {
+        let sample = |playback: Playback| {
+            let mut pose = Pose::rest(skeleton);
+            clips[playback.clip].sample(playback.time, &mut pose);
+            pose
+        };
+        let current = sample(self.current);
+        match self.previous
+        {
+            Some(previous) => sample(previous).blend(&current, self.fade / self.fade_duration),
+            None => current,
+        }
+    }
+}
+
+// joint indices and weights for every vertex, the indices are floats so they
+// go through the same float attributes as everything else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkinWeights
+{
+    pub joints: Vec<[f32; 4]>,
+    pub weights: Vec<[f32; 4]>,
+}
+
+// what the skinning shader does to a position, for checking poses on the cpu
+pub fn skin_position(
+    joint_matrices: &[Mat4],
+    joints: [f32; 4],
+    weights: [f32; 4],
+    position: Vec3,
+) -> Vec3
+{
+    let point = glm::vec4(position.x, position.y, position.z, 1.0);
+    let skinned = joints
+        .iter()
+        .zip(weights)
+        .fold(glm::vec4(0.0, 0.0, 0.0, 0.0), |sum, (joint, weight)| {
+            sum + joint_matrices[*joint as usize] * point * weight
+        });
+    glm::vec3(skinned.x, skinned.y, skinned.z)
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::{vec3, Vec3};
+
+    use super::{
+        skin_position, AnimationClip, AnimationPlayer, Channel, Interpolation, Joint, Keyframes,
+        Pose, Skeleton, Track,
+    };
+    use crate::{components::Transform, physics::Quat};
+
+    fn close(
+        a: Vec3,
+        b: Vec3,
+    ) -> bool
+    {
+        glm::length(a - b) < 1e-4
+    }
+
+    fn track(interpolation: Interpolation) -> Track<Vec3>
+    {
+        Track {
+            times: vec![1.0, 2.0],
+            values: vec![vec3(0.0, 0.0, 0.0), vec3(10.0, 0.0, 0.0)],
+            interpolation,
+        }
+    }
+
+    fn rest(position: Vec3) -> Transform
+    {
+        Transform {
+            position,
+            orientation: Quat::identity(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+
+    // a two joint arm along +x, the second joint one unit from the first
+    fn arm() -> Skeleton
+    {
+        let identity = Quat::identity().to_mat4();
+        let mut inverse_bind = identity;
+        inverse_bind.c3.x = -1.0;
+        Skeleton {
+            joints: vec![
+                Joint {
+                    name: Some("shoulder".to_string()),
+                    parent: None,
+                    rest: rest(vec3(0.0, 0.0, 0.0)),
+                    inverse_bind: identity,
+                },
+                Joint {
+                    name: Some("elbow".to_string()),
+                    parent: Some(0),
+                    rest: rest(vec3(1.0, 0.0, 0.0)),
+                    inverse_bind,
+                },
+            ],
+            root: identity,
+        }
+    }
+
+    // bends the elbow a quarter turn around z over one second
+    fn bend() -> AnimationClip
+    {
+        AnimationClip {
+            name: None,
+            duration: 1.0,
+            channels: vec![Channel {
+                target: 1,
+                keyframes: Keyframes::Rotation(Track {
+                    times: vec![0.0, 1.0],
+                    values: vec![
+                        Quat::identity(),
+                        Quat::from_axis_angle(vec3(0.0, 0.0, 1.0), std::f32::consts::FRAC_PI_2),
+                    ],
+                    interpolation: Interpolation::Linear,
+                }),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_track_interpolation()
+    {
+        assert!(close(
+            track(Interpolation::Linear).sample(1.25),
+            vec3(2.5, 0.0, 0.0)
+        ));
+        assert!(close(
+            track(Interpolation::Step).sample(1.99),
+            vec3(0.0, 0.0, 0.0)
+        ));
+        // clamped outside the keys
+        assert!(close(
+            track(Interpolation::Linear).sample(0.0),
+            vec3(0.0, 0.0, 0.0)
+        ));
+        assert!(close(
+            track(Interpolation::Linear).sample(5.0),
+            vec3(10.0, 0.0, 0.0)
+        ));
+
+        // zero tangents ease in and out, the midpoint is still halfway
+        let zero = vec3(0.0, 0.0, 0.0);
+        let mut cubic = track(Interpolation::CubicSpline);
+        cubic.values = vec![zero, zero, zero, zero, vec3(10.0, 0.0, 0.0), zero];
+        assert!(close(cubic.sample(1.5), vec3(5.0, 0.0, 0.0)));
+        assert!(cubic.sample(1.1).x < 1.0);
+
+        // tangents matching a straight line reproduce it
+        let slope = vec3(10.0, 0.0, 0.0);
+        cubic.values = vec![slope, zero, slope, slope, vec3(10.0, 0.0, 0.0), slope];
+        assert!(close(cubic.sample(1.1), vec3(1.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_pose_joint_matrices()
+    {
+        let skeleton = arm();
+        let mut pose = Pose::rest(&skeleton);
+        // the rest pose is the bind pose, so skinning changes nothing
+        let matrices = skeleton.joint_matrices(&pose);
+        let hand = vec3(2.0, 0.0, 0.0);
+        assert!(close(
+            skin_position(&matrices, [1.0, 0.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], hand),
+            hand
+        ));
+
+        bend().sample(1.0, &mut pose);
+        let matrices = skeleton.joint_matrices(&pose);
+        assert!(close(
+            skin_position(&matrices, [1.0, 0.0, 0.0, 0.0], [1.0, 0.0, 0.0, 0.0], hand),
+            vec3(1.0, 1.0, 0.0)
+        ));
+        // half weighted between the joints lands between the two results
+        assert!(close(
+            skin_position(&matrices, [0.0, 1.0, 0.0, 0.0], [0.5, 0.5, 0.0, 0.0], hand),
+            vec3(1.5, 0.5, 0.0)
+        ));
+    }
+
+    #[test]
+    fn test_player_crossfades_and_loops()
+    {
+        let skeleton = arm();
+        let clips = [
+            AnimationClip {
+                name: None,
+                duration: 1.0,
+                channels: vec![],
+            },
+            bend(),
+        ];
+        let elbow = |pose: &Pose| pose.local[1].orientation.rotate(vec3(1.0, 0.0, 0.0));
+
+        let mut player = AnimationPlayer::new(0);
+        player.advance(0.5, &clips);
+        player.play(1, 1.0);
+        player.advance(0.5, &clips);
+        // half way through the fade from rest to a bend that is half done
+        let half = Quat::from_axis_angle(vec3(0.0, 0.0, 1.0), std::f32::consts::FRAC_PI_8);
+        assert!(close(
+            elbow(&player.pose(&skeleton, &clips)),
+            half.rotate(vec3(1.0, 0.0, 0.0))
+        ));
+
+        // the fade is over and the clip wrapped back to a quarter
+        player.advance(0.75, &clips);
+        let quarter = Quat::from_axis_angle(vec3(0.0, 0.0, 1.0), std::f32::consts::FRAC_PI_8);
+        assert!(close(
+            elbow(&player.pose(&skeleton, &clips)),
+            quarter.rotate(vec3(1.0, 0.0, 0.0))
+        ));
+        assert_eq!(player.clip(), 1);
+    }
+}
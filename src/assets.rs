@@ -0,0 +1,673 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::Hash,
+    ops::Deref,
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
+    time::{Duration, SystemTime},
+};
+
+use gl::types::GLenum;
+use image::{DynamicImage, ImageError};
+use soloud::{AudioExt, LoadExt, SoloudError, Wav};
+use thiserror::Error;
+
+use crate::{
+    gltf::{GltfDocument, GltfError},
+    mesh::{Mesh, MeshData, MeshError},
+    program::Program,
+    shader::{Shader, ShaderError},
+    texture::{ColorSpace, Texture, TextureError, TextureOptions},
+};
+
+pub const ASSET_ROOT: &str = "./resources";
+// how often the main loop looks for edited shaders
+pub const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Error)]
+pub enum AssetError
+{
+    #[error("{0}")]
+    Texture(#[from] TextureError),
+    #[error("{path}: {source}")]
+    Shader
+    {
+        path: String, source: ShaderError
+    },
+    #[error("{0}")]
+    Mesh(#[from] MeshError),
+    #[error("can't load image {path}: {source}")]
+    Image
+    {
+        path: String, source: ImageError
+    },
+    #[error("can't load sound {path}: {source}")]
+    Sound
+    {
+        path: String, source: SoloudError
+    },
+    #[error("{path}: {source}")]
+    Gltf
+    {
+        path: String, source: GltfError
+    },
+}
+
+// a shared asset. the cache only holds weak references, so whatever the asset
+// owns, gl objects included, is freed when the last handle is dropped.
+pub struct Handle<T>(Rc<T>);
+
+impl<T> Handle<T>
+{
+    pub fn ptr_eq(
+        a: &Handle<T>,
+        b: &Handle<T>,
+    ) -> bool
+    {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> Clone for Handle<T>
+{
+    fn clone(&self) -> Self
+    {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        &self.0
+    }
+}
+
+struct Cache<K, T>
+{
+    entries: HashMap<K, Weak<T>>,
+}
+
+impl<K: Hash + Eq, T> Cache<K, T>
+{
+    fn new() -> Self
+    {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(
+        &self,
+        key: &K,
+    ) -> Option<Handle<T>>
+    {
+        self.entries.get(key)?.upgrade().map(Handle)
+    }
+
+    fn insert(
+        &mut self,
+        key: K,
+        asset: T,
+    ) -> Handle<T>
+    {
+        self.entries.retain(|_, asset| asset.strong_count() > 0);
+        let asset = Rc::new(asset);
+        self.entries.insert(key, Rc::downgrade(&asset));
+        Handle(asset)
+    }
+
+    fn live(&self) -> usize
+    {
+        self.entries
+            .values()
+            .filter(|asset| asset.strong_count() > 0)
+            .count()
+    }
+
+    fn handles(&self) -> Vec<(K, Handle<T>)>
+    where
+        K: Clone,
+    {
+        self.entries
+            .iter()
+            .filter_map(|(key, asset)| Some((key.clone(), Handle(asset.upgrade()?))))
+            .collect()
+    }
+}
+
+// modification times of source files, to notice when they are edited
+#[derive(Default)]
+struct Sources
+{
+    times: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Sources
+{
+    fn watch(
+        &mut self,
+        path: &Path,
+    )
+    {
+        self.times.insert(path.to_path_buf(), modified(path));
+    }
+
+    // true once for each change, files that aren't watched never change
+    fn changed(
+        &mut self,
+        path: &Path,
+    ) -> bool
+    {
+        let now = modified(path);
+        match self.times.get_mut(path)
+        {
+            Some(before) if *before != now =>
+            {
+                *before = now;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime>
+{
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// a program is one permutation of its two shader files
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ProgramKey
+{
+    vertex: PathBuf,
+    fragment: PathBuf,
+    defines: Vec<(String, String)>,
+}
+
+// the same image with other options is another texture
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct TextureKey
+{
+    path: PathBuf,
+    wrap: (GLenum, GLenum),
+    filters: (GLenum, GLenum),
+    // f32 isn't Hash, its bits are
+    anisotropy: u32,
+    mipmaps: bool,
+    color_space: ColorSpace,
+}
+
+impl TextureKey
+{
+    fn new(
+        path: PathBuf,
+        options: &TextureOptions,
+    ) -> Self
+    {
+        Self {
+            path,
+            wrap: (options.wrap_s, options.wrap_t),
+            filters: (options.min_filter, options.mag_filter),
+            anisotropy: options.anisotropy.to_bits(),
+            mipmaps: options.mipmaps,
+            color_space: options.color_space,
+        }
+    }
+}
+
+// loads everything by a path relative to the asset root, and hands the same
+// asset back while anything still holds a handle to it.
+pub struct Assets
+{
+    root: PathBuf,
+    images: Cache<PathBuf, DynamicImage>,
+    textures: Cache<TextureKey, Texture>,
+    meshes: Cache<PathBuf, Mesh>,
+    documents: Cache<PathBuf, GltfDocument>,
+    programs: Cache<ProgramKey, Program>,
+    sounds: Cache<PathBuf, Wav>,
+    shader_sources: Sources,
+    // every file each program was built from, includes too
+    program_files: HashMap<ProgramKey, Vec<PathBuf>>,
+    // the last failed load of each path, cleared once it loads
+    errors: HashMap<PathBuf, String>,
+    // programs are tracked apart since each set of defines can fail on its own
+    program_errors: HashMap<ProgramKey, String>,
+}
+
+impl Assets
+{
+    pub fn new(root: impl Into<PathBuf>) -> Self
+    {
+        Self {
+            root: root.into(),
+            images: Cache::new(),
+            textures: Cache::new(),
+            meshes: Cache::new(),
+            documents: Cache::new(),
+            programs: Cache::new(),
+            sounds: Cache::new(),
+            shader_sources: Sources::default(),
+            program_files: HashMap::new(),
+            errors: HashMap::new(),
+            program_errors: HashMap::new(),
+        }
+    }
+
+    // absolute paths are left alone. paths that exist are canonicalized so two
+    // spellings of one file share a cache entry.
+    pub fn resolve(
+        &self,
+        path: &str,
+    ) -> PathBuf
+    {
+        let path = self.root.join(path);
+        fs::canonicalize(&path).unwrap_or(path)
+    }
+
+    // cpu side images, such as height maps
+    pub fn image(
+        &mut self,
+        path: &str,
+    ) -> Result<Handle<DynamicImage>, AssetError>
+    {
+        let path = self.resolve(path);
+        load(
+            &mut self.images,
+            &mut self.errors,
+            path.clone(),
+            &path,
+            || {
+                image::open(&path).map_err(|source| AssetError::Image {
+                    path: path.display().to_string(),
+                    source,
+                })
+            },
+        )
+    }
+
+    // shared between loads with the same options
+    pub unsafe fn texture(
+        &mut self,
+        path: &str,
+        options: &TextureOptions,
+    ) -> Result<Handle<Texture>, AssetError>
+    {
+        let path = self.resolve(path);
+        load(
+            &mut self.textures,
+            &mut self.errors,
+            TextureKey::new(path.clone(), options),
+            &path,
+            || Ok(Texture::from_path(&path.to_string_lossy(), options)?),
+        )
+    }
+
+    pub unsafe fn mesh(
+        &mut self,
+        path: &str,
+    ) -> Result<Handle<Mesh>, AssetError>
+    {
+        let path = self.resolve(path);
+        load(
+            &mut self.meshes,
+            &mut self.errors,
+            path.clone(),
+            &path,
+            || Ok(Mesh::new(&MeshData::load_obj(&path.to_string_lossy())?)),
+        )
+    }
+
+    // .gltf or .glb, parsed but not uploaded
+    pub fn gltf(
+        &mut self,
+        path: &str,
+    ) -> Result<Handle<GltfDocument>, AssetError>
+    {
+        let path = self.resolve(path);
+        load(
+            &mut self.documents,
+            &mut self.errors,
+            path.clone(),
+            &path,
+            || {
+                let display = path.display().to_string();
+                GltfDocument::load(&display).map_err(|source| AssetError::Gltf {
+                    path: display,
+                    source,
+                })
+            },
+        )
+    }
+
+    // wav, ogg, mp3 or flac, decoded whole
+    pub fn sound(
+        &mut self,
+        path: &str,
+    ) -> Result<Handle<Wav>, AssetError>
+    {
+        let path = self.resolve(path);
+        load(
+            &mut self.sounds,
+            &mut self.errors,
+            path.clone(),
+            &path,
+            || {
+                let mut sound = Wav::default();
+                sound.load(&path).map_err(|source| AssetError::Sound {
+                    path: path.display().to_string(),
+                    source,
+                })?;
+                Ok(sound)
+            },
+        )
+    }
+
+    pub unsafe fn program(
+        &mut self,
+        vertex: &str,
+        fragment: &str,
+    ) -> Result<Handle<Program>, AssetError>
+    {
+        self.program_with_defines(vertex, fragment, &[])
+    }
+
+    // each set of defines is its own program, see Shader::with_defines
+    pub unsafe fn program_with_defines(
+        &mut self,
+        vertex: &str,
+        fragment: &str,
+        defines: &[(&str, &str)],
+    ) -> Result<Handle<Program>, AssetError>
+    {
+        let key = ProgramKey {
+            vertex: self.resolve(vertex),
+            fragment: self.resolve(fragment),
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+        if let Some(program) = self.programs.get(&key)
+        {
+            return Ok(program);
+        }
+        for path in [&key.vertex, &key.fragment]
+        {
+            self.shader_sources.watch(path);
+        }
+        self.program_files
+            .insert(key.clone(), vec![key.vertex.clone(), key.fragment.clone()]);
+        let program = self.link(&key, |shaders| Program::new(shaders))?;
+        Ok(self.programs.insert(key, program))
+    }
+
+    // recompiles and relinks the live programs whose shader files, or files
+    // they include, changed since the last call, returning each one's vertex
+    // shader path. a program that fails keeps running as it was.
+    pub unsafe fn reload_shaders(&mut self) -> Vec<Result<PathBuf, AssetError>>
+    {
+        let programs = self.programs.handles();
+        let mut changed = HashSet::new();
+        for (key, _) in &programs
+        {
+            for path in &self.program_files[key]
+            {
+                if self.shader_sources.changed(path)
+                {
+                    changed.insert(path.clone());
+                }
+            }
+        }
+        let programs: Vec<_> = programs
+            .into_iter()
+            .filter(|(key, _)| {
+                self.program_files[key]
+                    .iter()
+                    .any(|path| changed.contains(path))
+            })
+            .collect();
+        programs
+            .into_iter()
+            .map(|(key, program)| {
+                self.link(&key, |shaders| program.relink(shaders))
+                    .map(|()| key.vertex)
+            })
+            .collect()
+    }
+
+    // compile and link errors are recorded against the program
+    unsafe fn link<T>(
+        &mut self,
+        key: &ProgramKey,
+        link: impl FnOnce(&[Shader]) -> Result<T, ShaderError>,
+    ) -> Result<T, AssetError>
+    {
+        let defines: Vec<_> = key
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        let vertex_shader = self.compile(&key.vertex, gl::VERTEX_SHADER, &defines);
+        let fragment_shader = self.compile(&key.fragment, gl::FRAGMENT_SHADER, &defines);
+        // includes found now are watched from here on
+        let files = self.program_files.entry(key.clone()).or_default();
+        for shader in [&vertex_shader, &fragment_shader].into_iter().flatten()
+        {
+            for file in &shader.files
+            {
+                if !files.contains(file)
+                {
+                    files.push(file.clone());
+                    self.shader_sources.watch(file);
+                }
+            }
+        }
+        let result = vertex_shader.and_then(|vertex_shader| {
+            link(&[vertex_shader, fragment_shader?]).map_err(|source| AssetError::Shader {
+                path: key.vertex.display().to_string(),
+                source,
+            })
+        });
+        record(&mut self.program_errors, key, &result);
+        result
+    }
+
+    unsafe fn compile(
+        &mut self,
+        path: &Path,
+        kind: GLenum,
+        defines: &[(&str, &str)],
+    ) -> Result<Shader, AssetError>
+    {
+        Shader::with_defines(&path.to_string_lossy(), kind, defines).map_err(|source| {
+            AssetError::Shader {
+                path: path.display().to_string(),
+                source,
+            }
+        })
+    }
+
+    pub fn error(
+        &self,
+        path: &str,
+    ) -> Option<&str>
+    {
+        let path = self.resolve(path);
+        self.errors
+            .get(&path)
+            .or_else(|| {
+                self.program_errors
+                    .iter()
+                    .find_map(|(key, error)| (key.vertex == path).then_some(error))
+            })
+            .map(String::as_str)
+    }
+
+    // programs that fail show up under their vertex shader
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &str)>
+    {
+        let programs = self
+            .program_errors
+            .iter()
+            .map(|(key, error)| (key.vertex.as_path(), error.as_str()));
+        self.errors
+            .iter()
+            .map(|(path, error)| (path.as_path(), error.as_str()))
+            .chain(programs)
+    }
+
+    // assets that still have handles
+    pub fn loaded(&self) -> usize
+    {
+        self.images.live()
+            + self.textures.live()
+            + self.meshes.live()
+            + self.documents.live()
+            + self.programs.live()
+            + self.sounds.live()
+    }
+}
+
+fn record<K: Hash + Eq + Clone, T>(
+    errors: &mut HashMap<K, String>,
+    key: &K,
+    result: &Result<T, AssetError>,
+)
+{
+    match result
+    {
+        Ok(_) => errors.remove(key),
+        Err(error) => errors.insert(key.clone(), error.to_string()),
+    };
+}
+
+fn load<K: Hash + Eq, T>(
+    cache: &mut Cache<K, T>,
+    errors: &mut HashMap<PathBuf, String>,
+    key: K,
+    path: &Path,
+    load: impl FnOnce() -> Result<T, AssetError>,
+) -> Result<Handle<T>, AssetError>
+{
+    if let Some(asset) = cache.get(&key)
+    {
+        return Ok(asset);
+    }
+    let result = load();
+    record(errors, &path.to_path_buf(), &result);
+    Ok(cache.insert(key, result?))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::{
+        fs::{self, File},
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use image::{DynamicImage, GrayImage};
+
+    use super::{Assets, Handle, Sources, TextureKey};
+    use crate::texture::{ColorSpace, TextureOptions};
+
+    fn asset_root(name: &str) -> PathBuf
+    {
+        let root =
+            std::env::temp_dir().join(format!("rustgl-assets-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        DynamicImage::ImageLuma8(GrayImage::new(2, 2))
+            .save(root.join("height.png"))
+            .unwrap();
+        root
+    }
+
+    #[test]
+    fn test_texture_options_are_part_of_the_key()
+    {
+        let path = PathBuf::from("brick.png");
+        let linear = TextureOptions::default();
+        let srgb = TextureOptions {
+            color_space: ColorSpace::Srgb,
+            ..TextureOptions::default()
+        };
+        let clamped = TextureOptions {
+            wrap_s: gl::CLAMP_TO_EDGE,
+            ..TextureOptions::default()
+        };
+        let key = |options| TextureKey::new(path.clone(), options);
+        let default = TextureOptions::default();
+        assert_eq!(key(&linear), key(&default));
+        assert_ne!(key(&linear), key(&srgb));
+        assert_ne!(key(&linear), key(&clamped));
+    }
+
+    #[test]
+    fn test_loads_are_shared_until_dropped()
+    {
+        let mut assets = Assets::new(asset_root("shared"));
+        let first = assets.image("height.png").unwrap();
+        let second = assets.image("./height.png").unwrap();
+        assert!(Handle::ptr_eq(&first, &second));
+        assert_eq!(first.width(), 2);
+        assert_eq!(assets.loaded(), 1);
+
+        drop(first);
+        assert_eq!(assets.loaded(), 1);
+        drop(second);
+        assert_eq!(assets.loaded(), 0);
+    }
+
+    #[test]
+    fn test_errors_are_tracked_per_asset()
+    {
+        let root = asset_root("errors");
+        let mut assets = Assets::new(&root);
+        assert!(assets.image("missing.png").is_err());
+        let error = assets.error("missing.png").unwrap();
+        assert!(error.starts_with("can't load image"), "{}", error);
+        assert_eq!(assets.errors().count(), 1);
+        assert!(assets.error("height.png").is_none());
+
+        // a fixed file loads and clears its error
+        fs::copy(root.join("height.png"), root.join("missing.png")).unwrap();
+        assert!(assets.image("missing.png").is_ok());
+        assert_eq!(assets.errors().count(), 0);
+    }
+
+    #[test]
+    fn test_sources_notice_each_change_once()
+    {
+        let root = asset_root("sources");
+        let path = root.join("basic_frag.glsl");
+        fs::write(&path, "void main() {}").unwrap();
+        let mut sources = Sources::default();
+        sources.watch(&path);
+        assert!(!sources.changed(&path));
+        assert!(!sources.changed(&root.join("height.png")));
+
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(sources.changed(&path));
+        assert!(!sources.changed(&path));
+
+        // a deleted file counts as a change too
+        fs::remove_file(&path).unwrap();
+        assert!(sources.changed(&path));
+    }
+}
@@ -0,0 +1,251 @@
+use glm::Vec3;
+use soloud::{AttenuationModel, Backend, Soloud, SoloudError, SoloudFlag, Wav};
+
+use crate::{assets::Handle, camera::Camera, game::Impact};
+
+pub const IMPACT_SOUND: &str = "sounds/impact.wav";
+// slower impacts are silent, settling bodies make small ones all the time
+const QUIET_IMPACT_SPEED: f32 = 0.5;
+// and from this fast on they're at full volume
+const LOUD_IMPACT_SPEED: f32 = 10.0;
+// a pile landing at once only plays its loudest impacts
+const MAX_IMPACT_SOUNDS: usize = 8;
+
+// soloud is built with one backend, picked by the features in Cargo.toml. the
+// null driver needs no device and mixes nothing, for headless machines.
+#[cfg(all(feature = "null-audio", not(feature = "audio-device")))]
+const BACKEND: Backend = Backend::Null;
+#[cfg(not(all(feature = "null-audio", not(feature = "audio-device"))))]
+const BACKEND: Backend = Backend::Auto;
+
+// a sound that's playing, to move or stop it
+pub type Voice = soloud::Handle;
+
+pub struct Audio
+{
+    soloud: Soloud,
+}
+
+impl Audio
+{
+    pub fn new() -> Result<Self, SoloudError>
+    {
+        // the backend picks the sample rate and buffer size
+        let soloud = Soloud::new(SoloudFlag::ClipRoundoff, BACKEND, 0, 0, 2)?;
+        Ok(Self { soloud })
+    }
+
+    // quieter the further it is from the listener, and panned towards it
+    pub fn play_at(
+        &mut self,
+        sound: &Wav,
+        position: Vec3,
+        volume: f32,
+    ) -> Voice
+    {
+        let voice = self.soloud.play_3d_ex(
+            sound,
+            position.x,
+            position.y,
+            position.z,
+            0.0,
+            0.0,
+            0.0,
+            volume,
+            true,
+            Voice::PRIMARY,
+        );
+        // sounds don't fade with distance by default
+        self.soloud
+            .set_3d_source_attenuation(voice, AttenuationModel::InverseDistance, 1.0);
+        self.soloud.set_3d_source_minmax_distance(voice, 1.0, 200.0);
+        self.soloud.set_pause(voice, false);
+        voice
+    }
+
+    // hears from the camera, facing where it looks
+    pub fn set_listener(
+        &mut self,
+        camera: &Camera,
+    )
+    {
+        let position = camera.camera_position;
+        let at = camera.front();
+        let up = camera.up();
+        self.soloud.set_3d_listener_params(
+            position.x, position.y, position.z, at.x, at.y, at.z, up.x, up.y, up.z,
+        );
+    }
+
+    // applies the listener and positions set since the last call, once a
+    // frame after they're all set
+    pub fn update(&self)
+    {
+        self.soloud.update_3d_audio();
+    }
+}
+
+// nothing in the game plays these yet, only the test that runs on the null
+// driver
+#[cfg_attr(
+    not(all(test, feature = "null-audio", not(feature = "audio-device"))),
+    allow(dead_code)
+)]
+impl Audio
+{
+    // the same wherever the listener is
+    pub fn play(
+        &self,
+        sound: &Wav,
+        volume: f32,
+    ) -> Voice
+    {
+        self.soloud
+            .play_ex(sound, volume, 0.0, false, Voice::PRIMARY)
+    }
+
+    // plays until stopped
+    pub fn play_looping(
+        &mut self,
+        sound: &Wav,
+        volume: f32,
+    ) -> Voice
+    {
+        // paused until it loops, so a short sound can't end first
+        let voice = self
+            .soloud
+            .play_ex(sound, volume, 0.0, true, Voice::PRIMARY);
+        self.soloud.set_looping(voice, true);
+        self.soloud.set_pause(voice, false);
+        voice
+    }
+
+    // for a looping sound following something around
+    pub fn move_to(
+        &mut self,
+        voice: Voice,
+        position: Vec3,
+    )
+    {
+        self.soloud
+            .set_3d_source_position(voice, position.x, position.y, position.z);
+    }
+
+    pub fn stop(
+        &self,
+        voice: Voice,
+    )
+    {
+        self.soloud.stop(voice);
+    }
+
+    pub fn playing(&self) -> u32
+    {
+        self.soloud.active_voice_count()
+    }
+}
+
+// plays a sound where bodies hit each other, louder the harder they hit
+pub struct CollisionSounds
+{
+    sound: Handle<Wav>,
+}
+
+impl CollisionSounds
+{
+    pub fn new(sound: Handle<Wav>) -> Self
+    {
+        Self { sound }
+    }
+
+    pub fn play(
+        &self,
+        audio: &mut Audio,
+        impacts: &[Impact],
+    )
+    {
+        for (position, volume) in impact_sounds(impacts)
+        {
+            audio.play_at(&self.sound, position, volume);
+        }
+    }
+}
+
+// where to play a sound and how loud, for the loudest MAX_IMPACT_SOUNDS
+// impacts that are loud enough to hear
+fn impact_sounds(impacts: &[Impact]) -> Vec<(Vec3, f32)>
+{
+    let mut sounds: Vec<_> = impacts
+        .iter()
+        .filter(|impact| impact.speed >= QUIET_IMPACT_SPEED)
+        .map(|impact| {
+            let loudness =
+                (impact.speed - QUIET_IMPACT_SPEED) / (LOUD_IMPACT_SPEED - QUIET_IMPACT_SPEED);
+            (impact.position, loudness.min(1.0))
+        })
+        .collect();
+    sounds.sort_by(|a, b| b.1.total_cmp(&a.1));
+    sounds.truncate(MAX_IMPACT_SOUNDS);
+    sounds
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec3;
+
+    use super::{impact_sounds, MAX_IMPACT_SOUNDS};
+    use crate::{ecs::World, game::Impact};
+
+    fn impacts(speeds: &[f32]) -> Vec<Impact>
+    {
+        let mut world = World::new();
+        let (a, b) = (world.spawn(), world.spawn());
+        speeds
+            .iter()
+            .enumerate()
+            .map(|(index, speed)| Impact {
+                entities: (a, b),
+                position: vec3(index as f32, 0.0, 0.0),
+                speed: *speed,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_impact_sounds()
+    {
+        let sounds = impact_sounds(&impacts(&[0.1, 5.25, 30.0]));
+        // the gentle one is silent, the loudest comes first
+        assert_eq!(
+            sounds,
+            vec![(vec3(2.0, 0.0, 0.0), 1.0), (vec3(1.0, 0.0, 0.0), 0.5)]
+        );
+
+        let sounds = impact_sounds(&impacts(&[3.0; 20]));
+        assert_eq!(sounds.len(), MAX_IMPACT_SOUNDS);
+    }
+
+    // needs the null driver, the default features would open the sound card
+    #[cfg(all(feature = "null-audio", not(feature = "audio-device")))]
+    #[test]
+    fn test_plays_sounds()
+    {
+        use super::{Audio, IMPACT_SOUND};
+        use crate::assets::{Assets, ASSET_ROOT};
+
+        let mut audio = Audio::new().unwrap();
+        let sound = Assets::new(ASSET_ROOT).sound(IMPACT_SOUND).unwrap();
+        assert_eq!(audio.playing(), 0);
+
+        audio.play(&sound, 1.0);
+        audio.play_at(&sound, vec3(1.0, 2.0, 3.0), 1.0);
+        let voice = audio.play_looping(&sound, 0.5);
+        audio.move_to(voice, vec3(4.0, 5.0, 6.0));
+        audio.update();
+        assert_eq!(audio.playing(), 3);
+
+        audio.stop(voice);
+        assert_eq!(audio.playing(), 2);
+    }
+}
@@ -0,0 +1,250 @@
+use glm::{vec3, Vec3};
+
+use crate::collision::Obb;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb
+{
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb
+{
+    pub fn from_obb(obb: &Obb) -> Self
+    {
+        let mut extent = vec3(0.0, 0.0, 0.0);
+        for k in 0..3
+        {
+            let axis = obb.axes[k] * obb.half_extents[k];
+            extent = extent + vec3(axis.x.abs(), axis.y.abs(), axis.z.abs());
+        }
+        Self {
+            min: obb.center - extent,
+            max: obb.center + extent,
+        }
+    }
+
+    pub fn overlaps(
+        &self,
+        other: &Aabb,
+    ) -> bool
+    {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Endpoint
+{
+    value: f32,
+    proxy: usize,
+    is_min: bool,
+}
+
+// sweep and prune along the x axis. the endpoint list is kept sorted between
+// calls, bodies only move a little per step so re-sorting it is close to
+// linear with an insertion sort.
+#[derive(Debug, Default)]
+pub struct SweepAndPrune
+{
+    aabbs: Vec<Aabb>,
+    endpoints: Vec<Endpoint>,
+}
+
+impl SweepAndPrune
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    // returns the proxy id, ids are handed out in insertion order starting at
+    // zero.
+    pub fn insert(
+        &mut self,
+        aabb: Aabb,
+    ) -> usize
+    {
+        let proxy = self.aabbs.len();
+        self.aabbs.push(aabb);
+        self.endpoints.push(Endpoint {
+            value: aabb.min.x,
+            proxy,
+            is_min: true,
+        });
+        self.endpoints.push(Endpoint {
+            value: aabb.max.x,
+            proxy,
+            is_min: false,
+        });
+        proxy
+    }
+
+    pub fn update(
+        &mut self,
+        proxy: usize,
+        aabb: Aabb,
+    )
+    {
+        self.aabbs[proxy] = aabb;
+    }
+
+    // every pair of proxies whose boxes overlap, as (lower id, higher id) in
+    // ascending order.
+    pub fn pairs(&mut self) -> Vec<(usize, usize)>
+    {
+        for endpoint in self.endpoints.iter_mut()
+        {
+            let aabb = &self.aabbs[endpoint.proxy];
+            endpoint.value = if endpoint.is_min
+            {
+                aabb.min.x
+            }
+            else
+            {
+                aabb.max.x
+            };
+        }
+        insertion_sort(&mut self.endpoints);
+
+        let mut pairs = vec![];
+        let mut active: Vec<usize> = vec![];
+        for endpoint in &self.endpoints
+        {
+            if endpoint.is_min
+            {
+                let aabb = &self.aabbs[endpoint.proxy];
+                for other in &active
+                {
+                    if aabb.overlaps(&self.aabbs[*other])
+                    {
+                        pairs.push((endpoint.proxy.min(*other), endpoint.proxy.max(*other)));
+                    }
+                }
+                active.push(endpoint.proxy);
+            }
+            else if let Some(position) = active.iter().position(|proxy| *proxy == endpoint.proxy)
+            {
+                active.swap_remove(position);
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+// mins sort before maxes at the same value so touching boxes still pair up
+fn insertion_sort(endpoints: &mut [Endpoint])
+{
+    let before = |l: &Endpoint, r: &Endpoint| {
+        l.value < r.value || (l.value == r.value && l.is_min && !r.is_min)
+    };
+    for i in 1..endpoints.len()
+    {
+        let mut j = i;
+        while j > 0 && before(&endpoints[j], &endpoints[j - 1])
+        {
+            endpoints.swap(j, j - 1);
+            j -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::{vec3, Vec3};
+
+    use super::{Aabb, SweepAndPrune};
+    use crate::collision::Obb;
+
+    fn cube(center: Vec3) -> Aabb
+    {
+        Aabb {
+            min: center - vec3(0.5, 0.5, 0.5),
+            max: center + vec3(0.5, 0.5, 0.5),
+        }
+    }
+
+    fn brute_force(aabbs: &[Aabb]) -> Vec<(usize, usize)>
+    {
+        let mut pairs = vec![];
+        for i in 0..aabbs.len()
+        {
+            for j in (i + 1)..aabbs.len()
+            {
+                if aabbs[i].overlaps(&aabbs[j])
+                {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    fn test_aabb_of_rotated_box()
+    {
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let obb = Obb {
+            center: vec3(1.0, 0.0, 0.0),
+            axes: [vec3(h, h, 0.0), vec3(-h, h, 0.0), vec3(0.0, 0.0, 1.0)],
+            half_extents: vec3(0.5, 0.5, 0.5),
+        };
+
+        let aabb = Aabb::from_obb(&obb);
+
+        assert!(glm::length(aabb.min - vec3(1.0 - h, -h, -0.5)) < 1e-6);
+        assert!(glm::length(aabb.max - vec3(1.0 + h, h, 0.5)) < 1e-6);
+    }
+
+    #[test]
+    fn test_pairs_match_brute_force()
+    {
+        let mut aabbs = vec![];
+        for i in 0..50
+        {
+            let t = i as f32;
+            aabbs.push(cube(vec3(
+                (t * 0.37).sin() * 4.0,
+                (t * 0.91).cos() * 4.0,
+                (t * 0.13).sin() * 4.0,
+            )));
+        }
+
+        let mut sweep = SweepAndPrune::new();
+        for aabb in &aabbs
+        {
+            sweep.insert(*aabb);
+        }
+
+        assert_eq!(sweep.pairs(), brute_force(&aabbs));
+    }
+
+    #[test]
+    fn test_incremental_updates()
+    {
+        let mut sweep = SweepAndPrune::new();
+        let a = sweep.insert(cube(vec3(0.0, 0.0, 0.0)));
+        let b = sweep.insert(cube(vec3(5.0, 0.0, 0.0)));
+        let c = sweep.insert(cube(vec3(10.0, 0.0, 0.0)));
+        assert!(sweep.pairs().is_empty());
+
+        sweep.update(c, cube(vec3(0.5, 0.5, 0.0)));
+        assert_eq!(sweep.pairs(), vec![(a, c)]);
+
+        sweep.update(b, cube(vec3(0.9, 0.0, 0.0)));
+        assert_eq!(sweep.pairs(), vec![(a, b), (a, c), (b, c)]);
+
+        // overlapping on x only is not enough
+        sweep.update(b, cube(vec3(0.9, 3.0, 0.0)));
+        assert_eq!(sweep.pairs(), vec![(a, c)]);
+    }
+}
@@ -1,7 +1,143 @@
-use std::ops::Add;
+use std::{ffi::CStr, ops::Add};
 
-use glm::{vec3, Mat4, Vec3, Vector3};
-use winit::event::VirtualKeyCode;
+use glm::{vec3, vec4, Mat4, Vec3, Vec4};
+
+use crate::scene::CameraStart;
+
+pub const DEFAULT_NEAR: f32 = 0.1;
+pub const DEFAULT_FAR: f32 = 1000.0;
+// what switching a perspective camera to orthographic starts with
+pub const DEFAULT_FOV: f32 = 45.0;
+pub const DEFAULT_HEIGHT: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection
+{
+    // fov is vertical, in degrees
+    Perspective
+    {
+        fov: f32, near: f32, far: f32
+    },
+    // height is how much of the world fits vertically, the width follows the
+    // aspect ratio
+    Orthographic
+    {
+        height: f32, near: f32, far: f32
+    },
+}
+
+impl Projection
+{
+    // reverse_z puts near at depth 1 and far at 0, spreading depth precision
+    // more evenly over large scenes. it needs depth in [0, 1] and the depth
+    // test flipped, see set_depth_mode.
+    pub fn matrix(
+        &self,
+        aspect: f32,
+        reverse_z: bool,
+    ) -> Mat4
+    {
+        let matrix = match *self
+        {
+            Projection::Perspective { fov, near, far } =>
+            {
+                glm::ext::perspective(glm::radians(fov), aspect, near, far)
+            }
+            Projection::Orthographic { height, near, far } =>
+            {
+                let (right, top) = (height * aspect / 2.0, height / 2.0);
+                Mat4::new(
+                    vec4(1.0 / right, 0.0, 0.0, 0.0),
+                    vec4(0.0, 1.0 / top, 0.0, 0.0),
+                    vec4(0.0, 0.0, -2.0 / (far - near), 0.0),
+                    vec4(0.0, 0.0, -(far + near) / (far - near), 1.0),
+                )
+            }
+        };
+        match reverse_z
+        {
+            true =>
+            {
+                // z goes from [-1, 1] to [1, 0]
+                let flip = Mat4::new(
+                    vec4(1.0, 0.0, 0.0, 0.0),
+                    vec4(0.0, 1.0, 0.0, 0.0),
+                    vec4(0.0, 0.0, -0.5, 0.0),
+                    vec4(0.0, 0.0, 0.5, 1.0),
+                );
+                flip * matrix
+            }
+            false => matrix,
+        }
+    }
+
+    // the same near and far with the other kind of projection
+    pub fn toggled(&self) -> Self
+    {
+        match *self
+        {
+            Projection::Perspective { near, far, .. } => Projection::Orthographic {
+                height: DEFAULT_HEIGHT,
+                near,
+                far,
+            },
+            Projection::Orthographic { near, far, .. } => Projection::Perspective {
+                fov: DEFAULT_FOV,
+                near,
+                far,
+            },
+        }
+    }
+}
+
+// glClipControl is core in 4.5 and an extension before that
+pub unsafe fn has_clip_control() -> bool
+{
+    if !gl::ClipControl::is_loaded()
+    {
+        return false;
+    }
+    let (mut major, mut minor) = (0, 0);
+    gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+    gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    if (major, minor) >= (4, 5)
+    {
+        return true;
+    }
+    let mut count = 0;
+    gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    (0..count as u32).any(|i| {
+        let name = gl::GetStringi(gl::EXTENSIONS, i);
+        !name.is_null() && CStr::from_ptr(name.cast()).to_bytes() == b"GL_ARB_clip_control"
+    })
+}
+
+// the clip range, depth test and clear value that go with a reverse_z
+// projection. returns whether reverse_z is on, without glClipControl it stays
+// off since depth in [-1, 1] would lose the precision it's for.
+pub unsafe fn set_depth_mode(reverse_z: bool) -> bool
+{
+    let clip_control = has_clip_control();
+    match reverse_z && clip_control
+    {
+        true =>
+        {
+            gl::ClipControl(gl::LOWER_LEFT, gl::ZERO_TO_ONE);
+            gl::DepthFunc(gl::GREATER);
+            gl::ClearDepth(0.0);
+        }
+        false =>
+        {
+            if clip_control
+            {
+                gl::ClipControl(gl::LOWER_LEFT, gl::NEGATIVE_ONE_TO_ONE);
+            }
+            gl::DepthFunc(gl::LESS);
+            gl::ClearDepth(1.0);
+        }
+    }
+    reverse_z && clip_control
+}
 
 pub struct Camera
 {
@@ -15,84 +151,115 @@ pub struct Camera
     pitch: f32,
     yaw: f32,
 
-    pub fov: f32,
-
-    velocity: Vec3,
+    pub projection: Projection,
+    pub reverse_z: bool,
+    // width over height of what the camera draws to, set by resize
+    aspect: f32,
 }
 
 impl Camera
 {
-    pub fn new() -> Self
+    pub fn new(start: &CameraStart) -> Self
     {
         #[rustfmt::skip]
         let mut new_camera = Self {
-            camera_position: vec3(0.0, 0.0, 3.0),
+            camera_position: start.position,
             camera_front:    vec3(0.0, 0.0, -1.0),
             camera_up:       vec3(0.0, 1.0,  0.0),
             right:           vec3(0.0, 0.0,  0.0),
             world_up:        vec3(0.0, 1.0,  0.0),
-            pitch:           0.0,
-            yaw:             -90.0,
-            fov:             45.0,
-            velocity:        vec3(0.0, 0.0,  0.0),
+            pitch:           start.pitch,
+            yaw:             start.yaw,
+            projection:      Projection::Perspective {
+                fov:  start.fov,
+                near: DEFAULT_NEAR,
+                far:  DEFAULT_FAR,
+            },
+            reverse_z:       false,
+            aspect:          16.0 / 9.0,
         };
         new_camera.update_camera_vectors();
         new_camera
     }
 
-    pub fn handle_keyboard_input(
+    // turns by degrees, pitch stops short of straight up and down
+    pub fn rotate(
         &mut self,
-        now_keys: &[bool; 255],
-        delta_time: f32,
+        yaw: f32,
+        pitch: f32,
     )
     {
-        let movespeed = 500.0 * delta_time;
-        if now_keys[VirtualKeyCode::W as usize]
-        {
-            self.camera_position = self.camera_position + self.camera_front * movespeed;
-        }
-        if now_keys[VirtualKeyCode::S as usize]
-        {
-            self.camera_position = self.camera_position - self.camera_front * movespeed;
-        }
-        if now_keys[VirtualKeyCode::A as usize]
-        {
-            self.camera_position = self.camera_position - self.right * (movespeed);
-        }
-        if now_keys[VirtualKeyCode::D as usize]
-        {
-            self.camera_position = self.camera_position + self.right * movespeed;
-        }
-        if now_keys[VirtualKeyCode::E as usize]
-        {
-            self.camera_position = self.camera_position + self.world_up * movespeed;
-        }
-        if now_keys[VirtualKeyCode::Q as usize]
-        {
-            self.camera_position = self.camera_position - self.world_up * movespeed;
-        }
+        self.set_rotation(self.yaw + yaw, self.pitch + pitch);
     }
 
-    pub fn handle_mouse_input(
+    pub fn set_rotation(
         &mut self,
-        x_offset: f32,
-        y_offset: f32,
+        yaw: f32,
+        pitch: f32,
     )
     {
-        let sensitivity = 0.1;
-
-        self.yaw = self.yaw + (x_offset * sensitivity);
-        self.pitch = self.pitch - (y_offset * sensitivity);
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89.0, 89.0);
+        self.update_camera_vectors();
+    }
 
-        if self.pitch > 89.0
+    pub fn look_at(
+        &mut self,
+        target: Vec3,
+    )
+    {
+        let to_target = target - self.camera_position;
+        if glm::length(to_target) > f32::EPSILON
         {
-            self.pitch = 89.0;
+            let to_target = glm::normalize(to_target);
+            let yaw = glm::degrees(to_target.z.atan2(to_target.x));
+            let pitch = glm::degrees(to_target.y.asin());
+            self.set_rotation(yaw, pitch);
         }
-        if self.pitch < -89.0
+    }
+
+    pub fn yaw(&self) -> f32
+    {
+        self.yaw
+    }
+
+    pub fn pitch(&self) -> f32
+    {
+        self.pitch
+    }
+
+    pub fn front(&self) -> Vec3
+    {
+        self.camera_front
+    }
+
+    pub fn right(&self) -> Vec3
+    {
+        self.right
+    }
+
+    pub fn up(&self) -> Vec3
+    {
+        self.camera_up
+    }
+
+    // called with the framebuffer size in pixels, so it follows resizes and
+    // scale factor changes. a minimized window's zero size is ignored.
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+    )
+    {
+        if width > 0 && height > 0
         {
-            self.pitch = -89.0;
+            self.aspect = width as f32 / height as f32;
         }
-        self.update_camera_vectors();
+    }
+
+    pub fn get_projection_matrix(&self) -> Mat4
+    {
+        self.projection.matrix(self.aspect, self.reverse_z)
     }
 
     pub fn get_view_matrix(&self) -> Mat4
@@ -106,29 +273,157 @@ impl Camera
 
     fn update_camera_vectors(&mut self)
     {
-        let mut direction = vec3(0.0, 0.0, 0.0);
-        direction.x = glm::radians(self.yaw).cos() * glm::radians(self.pitch).cos();
-        direction.y = glm::radians(self.pitch).sin();
-        direction.z = glm::radians(self.yaw).sin() * glm::radians(self.pitch).cos();
-
-        self.camera_front = glm::normalize(direction);
+        self.camera_front = direction(self.yaw, self.pitch);
         self.right = glm::normalize(glm::cross(self.camera_front, self.world_up));
         self.camera_up = glm::normalize(glm::cross(self.right, self.camera_front));
     }
+}
 
-    pub(crate) fn apply_gravity(
-        &mut self,
-        delta_time: f32,
-    )
+// the unit vector yaw and pitch, in degrees, point along. yaw 0 looks down +x
+// and -90 down -z.
+pub fn direction(
+    yaw: f32,
+    pitch: f32,
+) -> Vec3
+{
+    let (yaw, pitch) = (glm::radians(yaw), glm::radians(pitch));
+    glm::normalize(vec3(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    ))
+}
+
+// the four side planes of a view projection, for skipping what's off screen.
+// near and far are left out so reverse_z doesn't matter, past them the
+// projection clips anyway.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum
+{
+    // a point is inside when dot(plane.xyz, point) + plane.w >= 0
+    planes: [Vec4; 4],
+}
+
+impl Frustum
+{
+    pub fn new(view_projection: Mat4) -> Self
+    {
+        // inside is -w <= x <= w and -w <= y <= w in clip space, so the planes
+        // are sums of the matrix's rows
+        let row = |i: usize| {
+            let m = &view_projection;
+            vec4(m[0][i], m[1][i], m[2][i], m[3][i])
+        };
+        let (x, y, w) = (row(0), row(1), row(3));
+        Self {
+            planes: [w + x, w - x, w + y, w - y],
+        }
+    }
+
+    // false only when the box is entirely outside one of the planes
+    pub fn intersects_box(
+        &self,
+        min: Vec3,
+        max: Vec3,
+    ) -> bool
     {
-        //println!("velocity: {:?}", self.velocity);
-        self.camera_position = self.camera_position + self.velocity * delta_time;
-        self.velocity = self.velocity + (GRAVITY / 1.0) * delta_time;
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane's normal
+            let corner = vec3(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.x * corner.x + plane.y * corner.y + plane.z * corner.z + plane.w >= 0.0
+        })
     }
 }
 
-const GRAVITY: glm::Vec3 = Vector3 {
-    x: 0.0,
-    y: 0.0,
-    z: 0.0,
-};
+#[cfg(test)]
+mod test
+{
+    use glm::{vec3, vec4, Vec3, Vec4};
+
+    use super::{Camera, Frustum, Projection, DEFAULT_HEIGHT};
+    use crate::scene::CameraStart;
+
+    fn depth(clip: Vec4) -> f32
+    {
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn test_perspective()
+    {
+        let projection = Projection::Perspective {
+            fov: 90.0,
+            near: 0.5,
+            far: 500.0,
+        };
+        let wide = projection.matrix(2.0, false);
+        // a point on the right edge of a 2:1 view at distance 1 is 2 across
+        let edge = wide * vec4(2.0, 1.0, -1.0, 1.0);
+        assert!((edge.x / edge.w - 1.0).abs() < 1e-5);
+        assert!((edge.y / edge.w - 1.0).abs() < 1e-5);
+        assert!((depth(wide * vec4(0.0, 0.0, -0.5, 1.0)) + 1.0).abs() < 1e-5);
+        assert!((depth(wide * vec4(0.0, 0.0, -500.0, 1.0)) - 1.0).abs() < 1e-4);
+
+        let reversed = projection.matrix(2.0, true);
+        assert!((depth(reversed * vec4(0.0, 0.0, -0.5, 1.0)) - 1.0).abs() < 1e-5);
+        assert!(depth(reversed * vec4(0.0, 0.0, -500.0, 1.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_orthographic()
+    {
+        let projection = Projection::Orthographic {
+            height: 10.0,
+            near: 1.0,
+            far: 11.0,
+        };
+        let matrix = projection.matrix(1.5, false);
+        let corner = matrix * vec4(7.5, 5.0, -1.0, 1.0);
+        assert_eq!(
+            (corner.x, corner.y, corner.z, corner.w),
+            (1.0, 1.0, -1.0, 1.0)
+        );
+        let far = matrix * vec4(0.0, 0.0, -11.0, 1.0);
+        assert!((far.z - 1.0).abs() < 1e-6);
+
+        // switching keeps near and far
+        let perspective = projection.toggled();
+        assert!(matches!(
+            perspective,
+            Projection::Perspective { near, far, .. } if (near, far) == (1.0, 11.0)
+        ));
+        assert!(matches!(
+            perspective.toggled(),
+            Projection::Orthographic { height, .. } if height == DEFAULT_HEIGHT
+        ));
+    }
+
+    #[test]
+    fn test_frustum()
+    {
+        let mut camera = Camera::new(&CameraStart::default());
+        camera.camera_position = vec3(0.0, 0.0, 0.0);
+        camera.look_at(vec3(0.0, 0.0, -1.0));
+        for reverse_z in [false, true]
+        {
+            camera.reverse_z = reverse_z;
+            let frustum = Frustum::new(camera.get_projection_matrix() * camera.get_view_matrix());
+            let unit = |center: Vec3| {
+                frustum.intersects_box(center - vec3(0.5, 0.5, 0.5), center + vec3(0.5, 0.5, 0.5))
+            };
+            assert!(unit(vec3(0.0, 0.0, -10.0)));
+            // behind, and far off to the side
+            assert!(!unit(vec3(0.0, 0.0, 10.0)));
+            assert!(!unit(vec3(100.0, 0.0, -10.0)));
+            assert!(!unit(vec3(0.0, -100.0, -10.0)));
+            // straddling the edge of the view
+            let edge = camera.get_projection_matrix();
+            let half_width = 10.0 / edge[0][0];
+            assert!(unit(vec3(half_width + 0.4, 0.0, -10.0)));
+        }
+    }
+}
@@ -0,0 +1,462 @@
+use glm::{vec3, Vec3};
+
+use crate::{
+    camera::{self, Camera},
+    components::Transform,
+    ecs::{Entity, World},
+    input::{Input, JUMP, MOVE_FORWARD, MOVE_RIGHT, MOVE_UP},
+    terrian::HeightField,
+};
+
+// degrees turned per unit of mouse motion
+const MOUSE_SENSITIVITY: f32 = 0.1;
+// units of mouse motion per second a stick pushed all the way turns by
+pub const STICK_LOOK_SPEED: f32 = 1000.0;
+
+// moves the camera each frame from the input and mouse. the active one is
+// swapped at runtime through a ControllerSet.
+pub trait CameraController
+{
+    fn name(&self) -> &'static str;
+
+    fn mouse_motion(
+        &mut self,
+        camera: &mut Camera,
+        x: f32,
+        y: f32,
+    )
+    {
+        camera.rotate(x * MOUSE_SENSITIVITY, -y * MOUSE_SENSITIVITY);
+    }
+
+    // lines scrolled, positive away from the user
+    fn mouse_wheel(
+        &mut self,
+        _camera: &mut Camera,
+        _lines: f32,
+    )
+    {
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        input: &Input,
+        delta_time: f32,
+        world: &World,
+    );
+}
+
+// moves along where the camera looks, and straight up and down
+pub struct FreeFly
+{
+    // units per second
+    pub speed: f32,
+}
+
+impl CameraController for FreeFly
+{
+    fn name(&self) -> &'static str
+    {
+        "free fly"
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        input: &Input,
+        delta_time: f32,
+        _world: &World,
+    )
+    {
+        let forward = input.axis(MOVE_FORWARD);
+        let sideways = input.axis(MOVE_RIGHT);
+        let up = input.axis(MOVE_UP);
+        let movement = camera.front() * forward + camera.right() * sideways + vec3(0.0, up, 0.0);
+        camera.camera_position = camera.camera_position + movement * self.speed * delta_time;
+    }
+}
+
+// circles a point, the mouse turns around it and the wheel zooms
+pub struct Orbit
+{
+    pub target: Vec3,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    // degrees, of the camera as seen from the target
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Orbit
+{
+    pub fn new(
+        target: Vec3,
+        distance: f32,
+    ) -> Self
+    {
+        Self {
+            target,
+            distance,
+            min_distance: 1.0,
+            max_distance: 500.0,
+            yaw: 90.0,
+            pitch: 20.0,
+        }
+    }
+}
+
+impl CameraController for Orbit
+{
+    fn name(&self) -> &'static str
+    {
+        "orbit"
+    }
+
+    fn mouse_motion(
+        &mut self,
+        _camera: &mut Camera,
+        x: f32,
+        y: f32,
+    )
+    {
+        self.yaw += x * MOUSE_SENSITIVITY;
+        self.pitch = (self.pitch + y * MOUSE_SENSITIVITY).clamp(-89.0, 89.0);
+    }
+
+    // every line scrolled away gets a tenth closer
+    fn mouse_wheel(
+        &mut self,
+        _camera: &mut Camera,
+        lines: f32,
+    )
+    {
+        self.distance =
+            (self.distance * 0.9_f32.powf(lines)).clamp(self.min_distance, self.max_distance);
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        _input: &Input,
+        _delta_time: f32,
+        _world: &World,
+    )
+    {
+        camera.camera_position =
+            self.target + camera::direction(self.yaw, self.pitch) * self.distance;
+        camera.look_at(self.target);
+    }
+}
+
+// walks on the terrain at eye height, and jumps. off the terrain, or with
+// none, the ground is at 0.
+pub struct Walker
+{
+    pub ground: Option<HeightField>,
+    pub eye_height: f32,
+    // units per second
+    pub speed: f32,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    vertical_velocity: f32,
+}
+
+impl Walker
+{
+    pub fn new(ground: Option<HeightField>) -> Self
+    {
+        Self {
+            ground,
+            eye_height: 1.7,
+            speed: 5.0,
+            gravity: -9.81,
+            jump_speed: 5.0,
+            vertical_velocity: 0.0,
+        }
+    }
+
+    fn ground_height(
+        &self,
+        position: Vec3,
+    ) -> f32
+    {
+        self.ground
+            .as_ref()
+            .and_then(|ground| ground.height_at(position.x, position.z))
+            .unwrap_or(0.0)
+    }
+}
+
+impl CameraController for Walker
+{
+    fn name(&self) -> &'static str
+    {
+        "walker"
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        input: &Input,
+        delta_time: f32,
+        _world: &World,
+    )
+    {
+        // looking up or down doesn't walk into the air or the ground
+        let front = camera::direction(camera.yaw(), 0.0);
+        let right = camera.right();
+        let forward = input.axis(MOVE_FORWARD);
+        let sideways = input.axis(MOVE_RIGHT);
+        let mut position =
+            camera.camera_position + (front * forward + right * sideways) * self.speed * delta_time;
+
+        let eye = self.ground_height(position) + self.eye_height;
+        let on_ground = position.y <= eye + f32::EPSILON;
+        if on_ground && input.held(JUMP)
+        {
+            self.vertical_velocity = self.jump_speed;
+        }
+        self.vertical_velocity += self.gravity * delta_time;
+        position.y += self.vertical_velocity * delta_time;
+        if position.y < eye
+        {
+            position.y = eye;
+            self.vertical_velocity = 0.0;
+        }
+        camera.camera_position = position;
+    }
+}
+
+// stays behind and above an entity, easing towards it as it moves
+pub struct Follow
+{
+    pub target: Entity,
+    pub distance: f32,
+    pub height: f32,
+    // degrees around the target, the mouse turns it
+    yaw: f32,
+    // how quickly the camera catches up, higher is tighter
+    pub stiffness: f32,
+}
+
+impl Follow
+{
+    pub fn new(target: Entity) -> Self
+    {
+        Self {
+            target,
+            distance: 8.0,
+            height: 3.0,
+            yaw: 90.0,
+            stiffness: 5.0,
+        }
+    }
+}
+
+impl CameraController for Follow
+{
+    fn name(&self) -> &'static str
+    {
+        "follow"
+    }
+
+    fn mouse_motion(
+        &mut self,
+        _camera: &mut Camera,
+        x: f32,
+        _y: f32,
+    )
+    {
+        self.yaw += x * MOUSE_SENSITIVITY;
+    }
+
+    fn update(
+        &mut self,
+        camera: &mut Camera,
+        _input: &Input,
+        delta_time: f32,
+        world: &World,
+    )
+    {
+        // the camera stays put while the entity is gone
+        let target = match world.components::<Transform>().get(self.target)
+        {
+            Some(transform) => transform.position,
+            None => return,
+        };
+        let wanted =
+            target + camera::direction(self.yaw, 0.0) * self.distance + vec3(0.0, self.height, 0.0);
+        // the same fraction of the way per second whatever the frame rate
+        let blend = 1.0 - (-self.stiffness * delta_time).exp();
+        camera.camera_position = camera.camera_position + (wanted - camera.camera_position) * blend;
+        camera.look_at(target);
+    }
+}
+
+pub struct ControllerSet
+{
+    controllers: Vec<Box<dyn CameraController>>,
+    active: usize,
+}
+
+impl ControllerSet
+{
+    // the first one starts active, there has to be at least one
+    pub fn new(controllers: Vec<Box<dyn CameraController>>) -> Self
+    {
+        assert!(!controllers.is_empty(), "no camera controllers");
+        Self {
+            controllers,
+            active: 0,
+        }
+    }
+
+    pub fn active(&mut self) -> &mut dyn CameraController
+    {
+        self.controllers[self.active].as_mut()
+    }
+
+    // switches to the one after the active one, wrapping around
+    pub fn next(&mut self) -> &mut dyn CameraController
+    {
+        self.active = (self.active + 1) % self.controllers.len();
+        self.active()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::{vec3, Vec3};
+    use image::{DynamicImage, ImageBuffer, Luma};
+    use winit::event::VirtualKeyCode;
+
+    use super::{CameraController, ControllerSet, Follow, FreeFly, Orbit, Walker};
+    use crate::{
+        camera::Camera,
+        components::Transform,
+        ecs::World,
+        input::{Bindings, Button, Input},
+        physics::Quat,
+        scene::CameraStart,
+        terrian::HeightField,
+    };
+
+    fn close(
+        a: Vec3,
+        b: Vec3,
+    ) -> bool
+    {
+        glm::length(a - b) < 1e-3
+    }
+
+    #[test]
+    fn test_look_at()
+    {
+        let mut camera = Camera::new(&CameraStart::default());
+        camera.camera_position = vec3(1.0, 0.0, 0.0);
+        camera.look_at(vec3(1.0, 0.0, -5.0));
+        assert!(close(camera.front(), vec3(0.0, 0.0, -1.0)));
+        camera.look_at(vec3(2.0, 1.0, 0.0));
+        assert!(close(camera.front(), glm::normalize(vec3(1.0, 1.0, 0.0))));
+    }
+
+    #[test]
+    fn test_orbit()
+    {
+        let world = World::new();
+        let mut camera = Camera::new(&CameraStart::default());
+        let mut orbit = Orbit::new(vec3(1.0, 2.0, 3.0), 10.0);
+        orbit.mouse_wheel(&mut camera, 2.0);
+        assert!((orbit.distance - 8.1).abs() < 1e-4);
+        orbit.mouse_wheel(&mut camera, -100.0);
+        assert_eq!(orbit.distance, orbit.max_distance);
+        orbit.distance = 10.0;
+
+        orbit.update(&mut camera, &Input::new(Bindings::default()), 0.1, &world);
+        assert!((glm::distance(camera.camera_position, orbit.target) - 10.0).abs() < 1e-3);
+        let to_target = glm::normalize(orbit.target - camera.camera_position);
+        assert!(close(camera.front(), to_target));
+    }
+
+    #[test]
+    fn test_walker_stands_on_ground()
+    {
+        let world = World::new();
+        // rises 1 unit for every unit along x
+        let image = ImageBuffer::from_fn(11, 11, |x, _| Luma([x as u16 * 4000]));
+        let ground = HeightField::from_image(&DynamicImage::ImageLuma16(image), 10.0);
+        let mut walker = Walker::new(Some(ground));
+        let mut camera = Camera::new(&CameraStart::default());
+        camera.camera_position = vec3(4.0, 50.0, 5.0);
+
+        // falls until it lands
+        let mut input = Input::new(Bindings::default());
+        for _ in 0..1000
+        {
+            walker.update(&mut camera, &input, 0.01, &world);
+        }
+        assert!((camera.camera_position.y - (4.0 + walker.eye_height)).abs() < 1e-3);
+
+        // walking towards +x climbs
+        camera.set_rotation(0.0, -45.0);
+        input.press(Button::Key(VirtualKeyCode::W));
+        walker.update(&mut camera, &input, 0.2, &world);
+        assert!(close(
+            camera.camera_position,
+            vec3(5.0, 5.0 + walker.eye_height, 5.0)
+        ));
+
+        // a jump leaves the ground
+        input.release(Button::Key(VirtualKeyCode::W));
+        input.press(Button::Key(VirtualKeyCode::Space));
+        walker.update(&mut camera, &input, 0.01, &world);
+        assert!(camera.camera_position.y > 5.0 + walker.eye_height);
+    }
+
+    #[test]
+    fn test_follow()
+    {
+        let mut world = World::new();
+        world.register::<Transform>();
+        let target = world.spawn();
+        world.insert(
+            target,
+            Transform {
+                position: vec3(10.0, 0.0, 0.0),
+                orientation: Quat::identity(),
+                scale: vec3(1.0, 1.0, 1.0),
+            },
+        );
+        let mut camera = Camera::new(&CameraStart::default());
+        let mut follow = Follow::new(target);
+        let input = Input::new(Bindings::default());
+        for _ in 0..200
+        {
+            follow.update(&mut camera, &input, 0.05, &world);
+        }
+        let behind = vec3(10.0, follow.height, follow.distance);
+        assert!(close(camera.camera_position, behind));
+        let to_target = glm::normalize(vec3(10.0, 0.0, 0.0) - behind);
+        assert!(close(camera.front(), to_target));
+
+        // a gone target leaves the camera where it was
+        world.despawn(target);
+        follow.update(&mut camera, &input, 0.05, &world);
+        assert!(close(camera.camera_position, behind));
+    }
+
+    #[test]
+    fn test_controller_set()
+    {
+        let mut set = ControllerSet::new(vec![
+            Box::new(FreeFly { speed: 1.0 }),
+            Box::new(Orbit::new(vec3(0.0, 0.0, 0.0), 5.0)),
+        ]);
+        assert_eq!(set.active().name(), "free fly");
+        assert_eq!(set.next().name(), "orbit");
+        assert_eq!(set.next().name(), "free fly");
+    }
+}
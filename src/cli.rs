@@ -0,0 +1,145 @@
+use crate::{assets::ASSET_ROOT, input::DEFAULT_BINDINGS, scene::DEFAULT_SCENE};
+
+pub const DEFAULT_TICKS: u32 = 600;
+
+#[derive(Debug, PartialEq)]
+pub struct Options
+{
+    pub scene: String,
+    // Some means step the simulation this many ticks without opening a window
+    pub headless: Option<u32>,
+    // where to write the scene back out on exit
+    pub save_scene: Option<String>,
+    // directory the asset paths in scenes and renderers are relative to
+    pub asset_root: String,
+    // the file mapping keys, mouse buttons and gamepads to actions
+    pub bindings: String,
+    // where to write a replay of the session on exit
+    pub record: Option<String>,
+    // a replay to step through without a window, in place of --headless
+    pub replay: Option<String>,
+    // only takes if the driver has glClipControl, see camera::set_depth_mode
+    pub reverse_z: bool,
+}
+
+impl Options
+{
+    // understands "--scene <path>", "--save-scene <path>", "--assets <dir>",
+    // "--bindings <path>", "--record <path>", "--replay <path>", "--reverse-z"
+    // and "--headless [ticks]", args[0] is the program name.
+    pub fn parse(args: &[String]) -> Result<Self, String>
+    {
+        let mut options = Self {
+            scene: DEFAULT_SCENE.to_string(),
+            headless: None,
+            save_scene: None,
+            asset_root: ASSET_ROOT.to_string(),
+            bindings: DEFAULT_BINDINGS.to_string(),
+            record: None,
+            replay: None,
+            reverse_z: false,
+        };
+        let mut args = args.iter().skip(1).peekable();
+        while let Some(arg) = args.next()
+        {
+            match arg.as_str()
+            {
+                "--scene" =>
+                {
+                    options.scene = args.next().ok_or("--scene expects a path")?.clone();
+                }
+                "--save-scene" =>
+                {
+                    options.save_scene =
+                        Some(args.next().ok_or("--save-scene expects a path")?.clone());
+                }
+                "--assets" =>
+                {
+                    options.asset_root = args.next().ok_or("--assets expects a directory")?.clone();
+                }
+                "--bindings" =>
+                {
+                    options.bindings = args.next().ok_or("--bindings expects a path")?.clone();
+                }
+                "--record" =>
+                {
+                    options.record = Some(args.next().ok_or("--record expects a path")?.clone());
+                }
+                "--replay" =>
+                {
+                    options.replay = Some(args.next().ok_or("--replay expects a path")?.clone());
+                }
+                "--reverse-z" => options.reverse_z = true,
+                "--headless" =>
+                {
+                    options.headless = match args.next_if(|next| !next.starts_with("--"))
+                    {
+                        Some(ticks) => Some(ticks.parse().map_err(|_| {
+                            format!("--headless expects a tick count, got `{}`", ticks)
+                        })?),
+                        None => Some(DEFAULT_TICKS),
+                    };
+                }
+                other => return Err(format!("unknown argument `{}`", other)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::{Options, DEFAULT_TICKS};
+    use crate::{assets::ASSET_ROOT, input::DEFAULT_BINDINGS, scene::DEFAULT_SCENE};
+
+    fn parse(line: &str) -> Result<Options, String>
+    {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        Options::parse(&args)
+    }
+
+    #[test]
+    fn test_parse()
+    {
+        assert_eq!(
+            parse("rustgl"),
+            Ok(Options {
+                scene: DEFAULT_SCENE.to_string(),
+                headless: None,
+                save_scene: None,
+                asset_root: ASSET_ROOT.to_string(),
+                bindings: DEFAULT_BINDINGS.to_string(),
+                record: None,
+                replay: None,
+                reverse_z: false,
+            })
+        );
+        assert_eq!(
+            parse("rustgl --headless").unwrap().headless,
+            Some(DEFAULT_TICKS)
+        );
+        let options = parse("rustgl --headless 30 --scene a.json --save-scene b.json").unwrap();
+        assert_eq!(options.headless, Some(30));
+        assert_eq!(options.scene, "a.json");
+        assert_eq!(options.save_scene.as_deref(), Some("b.json"));
+        assert_eq!(
+            parse("rustgl --assets ../data").unwrap().asset_root,
+            "../data"
+        );
+        assert_eq!(
+            parse("rustgl --bindings pad.json").unwrap().bindings,
+            "pad.json"
+        );
+        let options = parse("rustgl --record bug.replay --replay old.replay").unwrap();
+        assert_eq!(options.record.as_deref(), Some("bug.replay"));
+        assert_eq!(options.replay.as_deref(), Some("old.replay"));
+        assert!(parse("rustgl --reverse-z").unwrap().reverse_z);
+        assert!(parse("rustgl --headless many").is_err());
+        assert!(parse("rustgl --scene").is_err());
+        assert!(parse("rustgl --assets").is_err());
+        assert!(parse("rustgl --bindings").is_err());
+        assert!(parse("rustgl --replay").is_err());
+        assert!(parse("rustgl --fast").is_err());
+    }
+}
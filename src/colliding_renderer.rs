@@ -1,207 +1,119 @@
-use std::{cell::RefCell, rc::Rc};
-
-use glm::{vec3, vec4, Vec3};
+use glm::{Mat4, Vec3};
 
 use crate::{
-    camera::Camera,
+    assets::{AssetError, Assets, Handle},
+    components::{Collider, PreviousTransform, Renderable, Transform},
     game::CubeGameState,
+    game_loop::Time,
+    mesh::{self, Mesh, CUBE_OBJ},
     program::Program,
-    renderer::{self, VertexArray},
-    shader::Shader,
+    renderer::{self, InstanceBuffer, VertexArray},
+    scene::CUBE_MESH,
 };
 
-#[derive(Debug, Clone)]
-pub struct CubeObject
-{
-    pub id: i32,
-    pub position: Vec3,
-    pub is_colliding: bool,
-    pub normal_vector: Vec3,
-    pub colliding_objects: Vec<Rc<RefCell<CubeObject>>>,
-    pub force: Vec3,
-    pub velocity: Vec3,
-    pub mass: f32,
-}
-
-impl CubeObject
-{
-    pub fn get_verts(&self) -> Vec<Vec3>
-    {
-        #[rustfmt::skip]
-        let mut model = glm::mat4(
-            1.0, 0.0, 0.0, 0.0,
-            0.0, 1.0, 0.0, 0.0,
-            0.0, 0.0, 1.0, 0.0,
-            0.0, 0.0, 0.0, 1.0,
-        );
-
-        model = glm::ext::translate(&model, self.position);
-
-        let mut ret_val = vec![];
-        let a = model * vec4(0.5, 0.5, 0.5, 1.0);
-        let a = vec3(a.x, a.y, a.z);
-        let b = model * vec4(0.5, 0.5, -0.5, 1.0);
-        let b = vec3(b.x, b.y, b.z);
-        let c = model * vec4(0.5, -0.5, 0.5, 1.0);
-        let c = vec3(c.x, c.y, c.z);
-        let e = model * vec4(-0.5, 0.5, 0.5, 1.0);
-        let e = vec3(e.x, e.y, e.z);
-
-        ret_val.push(a);
-        ret_val.push(b);
-        ret_val.push(c);
-
-        ret_val.push(a);
-        ret_val.push(e);
-        ret_val.push(b);
-
-        ret_val.push(a);
-        ret_val.push(e);
-        ret_val.push(c);
-
-        ret_val
-    }
-
-    pub fn integrate(
-        &mut self,
-        delta_time: f32,
-    )
-    {
-        if !self.colliding_objects.is_empty()
-        {
-            elastic_collision_reaction(self, delta_time);
-            //inelastic_collision_reaction(self, delta_time);
-        }
-        else
-        {
-            self.velocity = self.velocity + (self.force / self.mass) * delta_time;
-            self.position = self.position + self.velocity * delta_time;
-        }
-    }
-
-    fn is_moving(&self) -> bool
-    {
-        vec3(0.0, 0.0, 0.0) != self.velocity
-    }
-}
-
-fn elastic_collision_reaction(
-    cube: &mut CubeObject,
-    delta_time: f32,
-)
-{
-    let output = glm::dot(cube.normal_vector, cube.velocity);
-    let reflected = cube.velocity - cube.normal_vector * 2.0 * output;
-
-    cube.velocity = reflected;
-    cube.position = cube.position + cube.velocity * delta_time;
-
-    let tangent = glm::vec3(
-        cube.normal_vector.y,
-        cube.normal_vector.x,
-        cube.normal_vector.z,
-    );
-    let tangent_reflected = reflected - tangent * 2.0;
-
-    for other_cube in cube.colliding_objects.as_mut_slice()
-    {
-        let mut other_cube = other_cube.borrow_mut();
-        other_cube.velocity = tangent_reflected * -1.0;
-    }
-}
-
-fn inelastic_collision_reaction(
-    cube: &mut CubeObject,
-    delta_time: f32,
-)
-{
-    let mut other_cube = cube.colliding_objects.first().unwrap().borrow_mut();
-    let other_momentum = other_cube.velocity * other_cube.mass;
-    let momentum = cube.velocity * cube.mass;
-    let new_velocity = (other_momentum + momentum) / (cube.mass + other_cube.mass);
-    cube.velocity = new_velocity;
-    other_cube.velocity = new_velocity;
-    cube.position = cube.position + cube.velocity * delta_time;
-}
+// a model matrix, column by column, then a color
+const INSTANCE_ATTRIBUTES: [i32; 5] = [4, 4, 4, 4, 3];
 
 pub struct CubeRenderer
 {
-    program: Program,
-    vertex_array: VertexArray,
+    cube: Handle<Mesh>,
+    instanced_program: Handle<Program>,
+    // the cube's buffers plus the instance attributes
+    instanced_vertex_array: VertexArray,
+    instances: InstanceBuffer,
 }
 
 impl CubeRenderer
 {
-    pub fn new() -> Self
+    pub fn new(assets: &mut Assets) -> Result<Self, AssetError>
     {
         unsafe {
-            let v_shader =
-                Shader::new("./resources/shaders/basic_vert.glsl", gl::VERTEX_SHADER).unwrap();
-            let f_shader =
-                Shader::new("./resources/shaders/basic_frag.glsl", gl::FRAGMENT_SHADER).unwrap();
-            let program = Program::new(&[v_shader, f_shader]).unwrap();
-
-            let mut vertex_array = VertexArray::new(&VERTEX_DATA, 6);
-            vertex_array.add_vert_att_ptr(3);
-            vertex_array.add_vert_att_ptr(3);
+            let instanced_program = assets.program_with_defines(
+                "shaders/basic_vert.glsl",
+                "shaders/basic_frag.glsl",
+                &[("INSTANCED", "")],
+            )?;
+            let cube = assets.mesh(CUBE_OBJ)?;
+
+            let instances = InstanceBuffer::new();
+            let mut instanced_vertex_array =
+                VertexArray::new_with_vbo(cube.vertex_array.vbo, mesh::STRIDE);
+            gl::BindBuffer(gl::ARRAY_BUFFER, cube.vertex_array.vbo);
+            instanced_vertex_array.add_vert_att_ptr(3);
+            instanced_vertex_array.add_vert_att_ptr(3);
+            instanced_vertex_array.add_vert_att_ptr(2);
+            instanced_vertex_array.add_instance_att_ptrs(&instances, &INSTANCE_ATTRIBUTES);
+            cube.index_buffer.bind();
 
             gl::Enable(gl::DEPTH_TEST);
-            Self {
-                program,
-                vertex_array,
-            }
+            Ok(Self {
+                cube,
+                instanced_program,
+                instanced_vertex_array,
+                instances,
+            })
         }
     }
 
+    // every cube goes out in one instanced draw
     pub fn draw_state(
         &self,
         state: &CubeGameState,
-        camera: &Camera,
     )
     {
-        let cubes = state.cubes.as_slice();
-        for cube in cubes
+        let renderables = state.world.components::<Renderable>();
+        let transforms = state.world.components::<Transform>();
+        let previous_transforms = state.world.components::<PreviousTransform>();
+        let colliders = state.world.components::<Collider>();
+        let alpha = state.world.resource::<Time>().alpha;
+        let mut instances = vec![];
+        for (entity, renderable) in renderables
+            .iter()
+            .filter(|(_, renderable)| renderable.mesh == CUBE_MESH)
         {
-            let color = match cube.borrow().is_colliding
+            let Some(transform) = transforms.get(entity)
+            else
+            {
+                continue;
+            };
+            let is_colliding = colliders
+                .get(entity)
+                .is_some_and(|collider| collider.is_colliding);
+            let color = match is_colliding
             {
-                true => vec3(1.0, 0.0, 0.0),
-                false => vec3(0.0, 1.0, 0.0),
+                true => renderable.colliding_color,
+                false => renderable.color,
             };
-            self.draw(&cube.borrow().position, &camera, &color);
+            let transform = match previous_transforms.get(entity)
+            {
+                Some(previous) => previous.0.interpolate(transform, alpha),
+                None => *transform,
+            };
+            push_instance(&mut instances, transform.matrix(), color);
         }
+        self.draw_instanced(&instances);
     }
 
-    pub fn draw(
+    // instances holds what push_instance adds for each cube
+    pub fn draw_instanced(
         &self,
-        position: &Vec3,
-        camera: &Camera,
-        color: &Vec3,
+        instances: &[f32],
     )
     {
+        let stride = INSTANCE_ATTRIBUTES.iter().sum::<i32>() as usize;
+        let count = instances.len() / stride;
+        if count == 0
+        {
+            return;
+        }
         unsafe {
-            self.program.bind();
-
-            let view = camera.get_view_matrix();
-            self.program.set_uniform_mat4("view", view);
-
-            let projection =
-                glm::ext::perspective(glm::radians(camera.fov), 1920.0 / 1080.0, 0.1, 100.0);
-            self.program.set_uniform_mat4("projection", projection);
-
-            #[rustfmt::skip]
-            let mut model = glm::mat4(
-                1.0, 0.0, 0.0, 0.0,
-                0.0, 1.0, 0.0, 0.0,
-                0.0, 0.0, 1.0, 0.0,
-                0.0, 0.0, 0.0, 1.0,
+            self.instances.upload(instances);
+            renderer::draw_instanced(
+                &self.instanced_vertex_array,
+                &self.cube.index_buffer,
+                &self.instanced_program,
+                count as i32,
             );
-            model = glm::ext::translate(&model, *position);
-
-            self.program.set_uniform_mat4("model", model);
-
-            self.program.set_uniform_vec3("color", *color);
-
-            renderer::draw_without_ibo(&self.vertex_array, &self.program);
         }
     }
 
@@ -217,47 +129,53 @@ impl CubeRenderer
     }
 }
 
-#[rustfmt::skip]
-const VERTEX_DATA: [f32; 216] = [
-    -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-    -0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-    -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-
-    -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-    -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-    -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-
-    -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,
-    -0.5,  0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,
-    -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,
+impl Drop for CubeRenderer
+{
+    fn drop(&mut self)
+    {
+        // the buffers belong to the cube mesh
+        unsafe { gl::DeleteVertexArrays(1, &self.instanced_vertex_array.vao) }
+    }
+}
 
-     0.5,  0.5,  0.5,  1.0,  0.0,  0.0,
-     0.5,  0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5,  0.5,  1.0,  0.0,  0.0,
-     0.5,  0.5,  0.5,  1.0,  0.0,  0.0,
+pub fn push_instance(
+    instances: &mut Vec<f32>,
+    model: Mat4,
+    color: Vec3,
+)
+{
+    for column in model.as_array()
+    {
+        instances.extend_from_slice(column.as_array());
+    }
+    instances.extend_from_slice(color.as_array());
+}
 
-    -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-    -0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-    -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
+#[cfg(test)]
+mod test
+{
+    use super::{push_instance, INSTANCE_ATTRIBUTES};
+    use crate::{components::Transform, physics::Quat};
 
-    -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-    -0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-    -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-];
+    #[test]
+    fn test_instance_layout()
+    {
+        let transform = Transform {
+            position: glm::vec3(1.0, 2.0, 3.0),
+            orientation: Quat::identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+        };
+        let mut instances = vec![];
+        push_instance(
+            &mut instances,
+            transform.matrix(),
+            glm::vec3(0.5, 0.25, 1.0),
+        );
+        push_instance(&mut instances, transform.matrix(), glm::vec3(0.0, 0.0, 0.0));
+        let stride = INSTANCE_ATTRIBUTES.iter().sum::<i32>() as usize;
+        assert_eq!(instances.len(), 2 * stride);
+        // the translation is the last column, the color comes after it
+        assert_eq!(instances[12..16], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(instances[16..19], [0.5, 0.25, 1.0]);
+    }
+}
@@ -218,3 +218,583 @@ mod test_3d
         assert!(!is_colliding)
     }
 }
+
+// b's face or any edge axis has to be this much shallower than the best axis
+// so far before it wins, keeps the manifold from flipping between near ties.
+const AXIS_TOLERANCE: f32 = 0.95;
+// cross products shorter than this come from (nearly) parallel edges and don't
+// give a usable axis.
+const PARALLEL_EPSILON: f32 = 1e-5;
+const MAX_MANIFOLD_POINTS: usize = 4;
+
+// oriented box, axes are unit length and orthogonal
+#[derive(Debug, Clone, Copy)]
+pub struct Obb
+{
+    pub center: Vec3,
+    pub axes: [Vec3; 3],
+    pub half_extents: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContactPoint
+{
+    pub position: Vec3,
+    pub penetration: f32,
+    // identifies the same point across frames, for warm starting. see
+    // face_feature, edge contacts use their axis alone.
+    pub feature: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactManifold
+{
+    // points from the first box towards the second
+    pub normal: Vec3,
+    pub depth: f32,
+    pub points: Vec<ContactPoint>,
+}
+
+impl Obb
+{
+    pub fn vertices(&self) -> [Vec3; 8]
+    {
+        let x = self.axes[0] * self.half_extents.x;
+        let y = self.axes[1] * self.half_extents.y;
+        let z = self.axes[2] * self.half_extents.z;
+        let c = self.center;
+        [
+            c - x - y - z,
+            c + x - y - z,
+            c - x + y - z,
+            c + x + y - z,
+            c - x - y + z,
+            c + x - y + z,
+            c - x + y + z,
+            c + x + y + z,
+        ]
+    }
+
+    fn projected_radius(
+        &self,
+        axis: Vec3,
+    ) -> f32
+    {
+        (0..3)
+            .map(|k| glm::dot(self.axes[k], axis).abs() * self.half_extents[k])
+            .sum()
+    }
+}
+
+// separating axis test for two oriented boxes over all 15 candidate axes:
+// the 3 face normals of each box and the 9 edge-edge cross products. on
+// overlap returns the axis of least penetration, oriented from a to b, with up
+// to four contact points.
+pub fn test_obb_collision(
+    a: &Obb,
+    b: &Obb,
+) -> Option<ContactManifold>
+{
+    let offset = b.center - a.center;
+
+    let mut best_depth = f32::MAX;
+    let mut best_axis = 0;
+    let mut normal = a.axes[0];
+
+    let mut test_axis = |index: usize, axis: Vec3| -> bool {
+        let distance = glm::dot(offset, axis);
+        let depth = a.projected_radius(axis) + b.projected_radius(axis) - distance.abs();
+        if depth < 0.0
+        {
+            return false;
+        }
+        let tolerance = if index < 3 { 1.0 } else { AXIS_TOLERANCE };
+        if depth < best_depth * tolerance
+        {
+            best_depth = depth;
+            best_axis = index;
+            normal = if distance < 0.0 { -axis } else { axis };
+        }
+        true
+    };
+
+    for i in 0..3
+    {
+        if !test_axis(i, a.axes[i])
+        {
+            return None;
+        }
+    }
+    for i in 0..3
+    {
+        if !test_axis(3 + i, b.axes[i])
+        {
+            return None;
+        }
+    }
+    for i in 0..3
+    {
+        for j in 0..3
+        {
+            let axis = glm::cross(a.axes[i], b.axes[j]);
+            let length = glm::length(axis);
+            if length < PARALLEL_EPSILON
+            {
+                continue;
+            }
+            if !test_axis(6 + i * 3 + j, axis / length)
+            {
+                return None;
+            }
+        }
+    }
+
+    let points = if best_axis < 3
+    {
+        face_contact(a, best_axis, b, normal, best_depth, best_axis)
+    }
+    else if best_axis < 6
+    {
+        face_contact(b, best_axis - 3, a, -normal, best_depth, best_axis)
+    }
+    else
+    {
+        let edge_a = (best_axis - 6) / 3;
+        let edge_b = (best_axis - 6) % 3;
+        edge_contact(a, edge_a, b, edge_b, normal, best_depth, best_axis)
+    };
+
+    Some(ContactManifold {
+        normal,
+        depth: best_depth,
+        points,
+    })
+}
+
+// clips the incident face of `incident` against the side planes of the
+// reference face of `reference` and keeps the points below the reference face.
+// face_normal points out of the reference box towards the incident box. when
+// nothing is left after clipping the deepest incident vertex is used instead,
+// so an overlap always has a point to push apart.
+fn face_contact(
+    reference: &Obb,
+    reference_axis: usize,
+    incident: &Obb,
+    face_normal: Vec3,
+    depth: f32,
+    axis_index: usize,
+) -> Vec<ContactPoint>
+{
+    // the incident face is the one most anti-parallel to the reference normal
+    let (incident_axis, _) = (0..3)
+        .map(|k| (k, glm::dot(incident.axes[k], face_normal).abs()))
+        .fold((0, f32::MIN), |best, current| {
+            if current.1 > best.1
+            {
+                current
+            }
+            else
+            {
+                best
+            }
+        });
+    let sign = if glm::dot(incident.axes[incident_axis], face_normal) > 0.0
+    {
+        -1.0
+    }
+    else
+    {
+        1.0
+    };
+    let face_id = (axis_index * 3 + incident_axis) as u32 * 2 + (sign > 0.0) as u32;
+    let incident_center = incident.center
+        + incident.axes[incident_axis] * (sign * incident.half_extents[incident_axis]);
+    let u_axis = (incident_axis + 1) % 3;
+    let v_axis = (incident_axis + 2) % 3;
+    let u = incident.axes[u_axis] * incident.half_extents[u_axis];
+    let v = incident.axes[v_axis] * incident.half_extents[v_axis];
+    let mut polygon = vec![
+        (incident_center + u + v, 0),
+        (incident_center - u + v, 1),
+        (incident_center - u - v, 2),
+        (incident_center + u - v, 3),
+    ];
+
+    let mut plane = 0;
+    for k in (0..3).filter(|k| *k != reference_axis)
+    {
+        let side = reference.axes[k];
+        let offset = glm::dot(reference.center, side);
+        let extent = reference.half_extents[k];
+        polygon = clip_polygon(&polygon, side, offset + extent, plane);
+        polygon = clip_polygon(&polygon, -side, -offset + extent, plane + 1);
+        plane += 2;
+        if polygon.is_empty()
+        {
+            return vec![deepest_vertex(incident, face_normal, depth, face_id)];
+        }
+    }
+
+    let face_center = reference.center + face_normal * reference.half_extents[reference_axis];
+    let points: Vec<_> = polygon
+        .into_iter()
+        .filter_map(|(position, tag)| {
+            let penetration = glm::dot(face_center - position, face_normal);
+            if penetration < 0.0
+            {
+                return None;
+            }
+            Some(ContactPoint {
+                // halfway between the two surfaces
+                position: position + face_normal * (penetration * 0.5),
+                penetration,
+                feature: face_feature(face_id, tag),
+            })
+        })
+        .collect();
+    if points.is_empty()
+    {
+        return vec![deepest_vertex(incident, face_normal, depth, face_id)];
+    }
+
+    reduce_manifold(points, face_normal)
+}
+
+// the incident vertex furthest into the reference box, halfway back out along
+// the normal by the separating axis depth
+fn deepest_vertex(
+    incident: &Obb,
+    face_normal: Vec3,
+    depth: f32,
+    face_id: u32,
+) -> ContactPoint
+{
+    let vertex = incident
+        .vertices()
+        .into_iter()
+        .fold(incident.center, |deepest, vertex| {
+            if glm::dot(vertex, face_normal) < glm::dot(deepest, face_normal)
+            {
+                vertex
+            }
+            else
+            {
+                deepest
+            }
+        });
+    ContactPoint {
+        position: vertex + face_normal * (depth * 0.5),
+        penetration: depth,
+        // clipping can't make a tag this large
+        feature: face_feature(face_id, u32::MAX),
+    }
+}
+
+// the face in the high half and the clip tag in the low half, so no two faces
+// share an id. the face is off by one to stay clear of edge features.
+fn face_feature(
+    face_id: u32,
+    tag: u32,
+) -> u64
+{
+    ((face_id as u64 + 1) << 32) | tag as u64
+}
+
+// sutherland-hodgman against a single plane, keeps dot(p, normal) <= offset.
+// points created by the clip get a tag derived from the edge they cut so the
+// same point keeps the same feature id from frame to frame.
+fn clip_polygon(
+    polygon: &[(Vec3, u32)],
+    normal: Vec3,
+    offset: f32,
+    plane: u32,
+) -> Vec<(Vec3, u32)>
+{
+    let mut output = vec![];
+    for i in 0..polygon.len()
+    {
+        let (start, start_tag) = polygon[i];
+        let (end, _) = polygon[(i + 1) % polygon.len()];
+        let start_distance = glm::dot(start, normal) - offset;
+        let end_distance = glm::dot(end, normal) - offset;
+
+        if start_distance <= 0.0
+        {
+            output.push((start, start_tag));
+        }
+        if (start_distance <= 0.0) != (end_distance <= 0.0)
+        {
+            let t = start_distance / (start_distance - end_distance);
+            let tag = (start_tag + 1).wrapping_mul(8).wrapping_add(plane);
+            output.push((start + (end - start) * t, tag));
+        }
+    }
+    output
+}
+
+// keeps the deepest point plus the three that span the largest area.
+fn reduce_manifold(
+    mut points: Vec<ContactPoint>,
+    normal: Vec3,
+) -> Vec<ContactPoint>
+{
+    if points.len() <= MAX_MANIFOLD_POINTS
+    {
+        return points;
+    }
+
+    let take_max = |points: &mut Vec<ContactPoint>, score: &dyn Fn(&ContactPoint) -> f32| {
+        let (index, _) = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (i, score(point)))
+            .fold((0, f32::MIN), |best, current| {
+                if current.1 > best.1
+                {
+                    current
+                }
+                else
+                {
+                    best
+                }
+            });
+        points.swap_remove(index)
+    };
+
+    let first = take_max(&mut points, &|point| point.penetration);
+    let second = take_max(&mut points, &|point| {
+        glm::length(point.position - first.position)
+    });
+    let edge = second.position - first.position;
+    let signed_area =
+        |point: &ContactPoint| glm::dot(glm::cross(edge, point.position - first.position), normal);
+    let third = take_max(&mut points, &signed_area);
+    let fourth = take_max(&mut points, &|point| -signed_area(point));
+
+    vec![first, second, third, fourth]
+}
+
+// single point halfway between the closest points of the two crossing edges.
+fn edge_contact(
+    a: &Obb,
+    edge_a: usize,
+    b: &Obb,
+    edge_b: usize,
+    normal: Vec3,
+    depth: f32,
+    axis_index: usize,
+) -> Vec<ContactPoint>
+{
+    // the supporting edge of each box in the direction of the other
+    let support_edge = |obb: &Obb, edge: usize, direction: Vec3| {
+        let mut point = obb.center;
+        for k in (0..3).filter(|k| *k != edge)
+        {
+            let sign = if glm::dot(obb.axes[k], direction) > 0.0
+            {
+                1.0
+            }
+            else
+            {
+                -1.0
+            };
+            point = point + obb.axes[k] * (sign * obb.half_extents[k]);
+        }
+        point
+    };
+    let point_a = support_edge(a, edge_a, normal);
+    let point_b = support_edge(b, edge_b, -normal);
+    let direction_a = a.axes[edge_a];
+    let direction_b = b.axes[edge_b];
+
+    // closest points between the two infinite lines, clamped to the edges
+    let r = point_a - point_b;
+    let d = glm::dot(direction_a, direction_b);
+    let e = glm::dot(direction_a, r);
+    let f = glm::dot(direction_b, r);
+    let denominator = 1.0 - d * d;
+    let (s, t) = if denominator.abs() < PARALLEL_EPSILON
+    {
+        (0.0, f)
+    }
+    else
+    {
+        ((d * f - e) / denominator, (f - d * e) / denominator)
+    };
+    let s = s.clamp(-a.half_extents[edge_a], a.half_extents[edge_a]);
+    let t = t.clamp(-b.half_extents[edge_b], b.half_extents[edge_b]);
+
+    let closest_a = point_a + direction_a * s;
+    let closest_b = point_b + direction_b * t;
+    vec![ContactPoint {
+        position: (closest_a + closest_b) * 0.5,
+        penetration: depth,
+        feature: axis_index as u64,
+    }]
+}
+
+#[cfg(test)]
+mod test_obb
+{
+    use glm::{vec3, Vec3};
+
+    use super::{face_contact, face_feature, test_obb_collision, Obb};
+
+    fn unit_box(center: Vec3) -> Obb
+    {
+        Obb {
+            center,
+            axes: [
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+            ],
+            half_extents: vec3(0.5, 0.5, 0.5),
+        }
+    }
+
+    // rotation of the unit box around x (axis 0) or z (axis 2) by 45 degrees
+    fn rotated_box(
+        center: Vec3,
+        around: usize,
+    ) -> Obb
+    {
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let axes = match around
+        {
+            0 => [vec3(1.0, 0.0, 0.0), vec3(0.0, h, h), vec3(0.0, -h, h)],
+            _ => [vec3(h, h, 0.0), vec3(-h, h, 0.0), vec3(0.0, 0.0, 1.0)],
+        };
+        Obb {
+            center,
+            axes,
+            half_extents: vec3(0.5, 0.5, 0.5),
+        }
+    }
+
+    #[test]
+    fn test_separated()
+    {
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(1.5, 0.0, 0.0));
+
+        assert!(test_obb_collision(&a, &b).is_none());
+    }
+
+    #[test]
+    fn test_face_contact_manifold()
+    {
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(0.0, 0.95, 0.0));
+
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        assert!(glm::length(manifold.normal - vec3(0.0, 1.0, 0.0)) < 1e-5);
+        assert!((manifold.depth - 0.05).abs() < 1e-5);
+        assert_eq!(manifold.points.len(), 4);
+        for point in &manifold.points
+        {
+            assert!((point.penetration - 0.05).abs() < 1e-5);
+            assert!((point.position.y - 0.475).abs() < 1e-5);
+            assert!((point.position.x.abs() - 0.5).abs() < 1e-5);
+            assert!((point.position.z.abs() - 0.5).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_normal_points_from_a_to_b()
+    {
+        let a = unit_box(vec3(0.0, 0.95, 0.0));
+        let b = unit_box(vec3(0.0, 0.0, 0.0));
+
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        assert!(glm::length(manifold.normal - vec3(0.0, -1.0, 0.0)) < 1e-5);
+    }
+
+    #[test]
+    fn test_offset_face_contact_is_clipped()
+    {
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(0.7, 0.9, 0.0));
+
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        assert_eq!(manifold.points.len(), 4);
+        for point in &manifold.points
+        {
+            assert!(point.position.x >= 0.2 - 1e-5 && point.position.x <= 0.5 + 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_face_contact_without_clipped_points()
+    {
+        // the incident face lies past the side of the reference face, which
+        // clips it away completely
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(1.2, 0.9, 0.0));
+
+        let points = face_contact(&a, 1, &b, vec3(0.0, 1.0, 0.0), 0.1, 1);
+
+        assert_eq!(points.len(), 1);
+        assert!((points[0].penetration - 0.1).abs() < 1e-5);
+        assert!((points[0].position.y - 0.45).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_overlaps_always_have_points()
+    {
+        for step in 0..200
+        {
+            let offset = step as f32 * 0.01;
+            let a = rotated_box(vec3(0.0, 0.0, 0.0), 2);
+            let b = rotated_box(vec3(offset, 0.9 + offset * 0.2, -offset * 0.5), 0);
+            if let Some(manifold) = test_obb_collision(&a, &b)
+            {
+                assert!(!manifold.points.is_empty(), "offset {}", offset);
+            }
+        }
+    }
+
+    #[test]
+    fn test_crossed_edges_are_separated()
+    {
+        // the face normals alone can't separate these, only y = x cross z can
+        let a = rotated_box(vec3(0.0, 0.0, 0.0), 2);
+        let b = rotated_box(vec3(0.0, 1.464, 0.0), 0);
+
+        assert!(test_obb_collision(&a, &b).is_none());
+    }
+
+    #[test]
+    fn test_crossed_edges_contact()
+    {
+        let a = rotated_box(vec3(0.0, 0.0, 0.0), 2);
+        let b = rotated_box(vec3(0.0, 1.38, 0.0), 0);
+
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        let expected_depth = std::f32::consts::SQRT_2 - 1.38;
+        assert!(glm::length(manifold.normal - vec3(0.0, 1.0, 0.0)) < 1e-4);
+        assert!((manifold.depth - expected_depth).abs() < 1e-4);
+        assert_eq!(manifold.points.len(), 1);
+        let point = manifold.points[0].position;
+        assert!(glm::length(point - vec3(0.0, 0.69, 0.0)) < 1e-3);
+    }
+
+    #[test]
+    fn test_features_are_unique()
+    {
+        // four chained clips of the last corner, past the old 1024 per face
+        let mut tag = 3;
+        for plane in 0..4
+        {
+            tag = (tag + 1) * 8 + plane;
+        }
+        assert!(tag > 1024);
+        assert_ne!(face_feature(0, tag), face_feature(1, tag - 1024));
+        // nor with an edge contact's axis
+        assert_ne!(face_feature(0, 15), 15);
+    }
+}
@@ -0,0 +1,118 @@
+use glm::{vec3, Mat4, Vec3};
+
+use crate::{
+    ecs::Entity,
+    physics::{BodyHandle, Quat},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform
+{
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform
+{
+    pub fn matrix(&self) -> Mat4
+    {
+        #[rustfmt::skip]
+        let mut model = glm::mat4(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        );
+        model = glm::ext::translate(&model, self.position);
+        model = model * self.orientation.to_mat4();
+        glm::ext::scale(&model, self.scale)
+    }
+
+    // blends from self towards next, alpha 0 is self and 1 is next
+    pub fn interpolate(
+        &self,
+        next: &Transform,
+        alpha: f32,
+    ) -> Transform
+    {
+        Transform {
+            position: self.position + (next.position - self.position) * alpha,
+            orientation: self.orientation.nlerp(next.orientation, alpha),
+            scale: self.scale + (next.scale - self.scale) * alpha,
+        }
+    }
+}
+
+// the transform as of the previous fixed step, drawing blends between it and
+// the current one so motion stays smooth when the frame rate and the physics
+// rate don't line up.
+#[derive(Debug, Clone, Copy)]
+pub struct PreviousTransform(pub Transform);
+
+// the body itself lives in the PhysicsWorld resource, the physics system
+// copies its pose back into the Transform every step.
+#[derive(Debug, Clone, Copy)]
+pub struct RigidBody
+{
+    pub handle: BodyHandle,
+}
+
+// collision state from the last physics step.
+#[derive(Debug, Clone)]
+pub struct Collider
+{
+    pub is_colliding: bool,
+    // points from the last body touched towards this one
+    pub normal: Vec3,
+    pub colliding_with: Vec<Entity>,
+}
+
+impl Collider
+{
+    pub fn new() -> Self
+    {
+        Self {
+            is_colliding: false,
+            normal: vec3(0.0, 0.0, 0.0),
+            colliding_with: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Renderable
+{
+    pub mesh: String,
+    pub color: Vec3,
+    pub colliding_color: Vec3,
+    pub texture: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Name(pub String);
+
+// marks the entity the keyboard pushes around
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerControlled;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind
+{
+    Point
+    {
+        position: Vec3
+    },
+    Directional
+    {
+        direction: Vec3
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light
+{
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+}
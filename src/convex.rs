@@ -0,0 +1,1008 @@
+use glm::{vec3, Mat4, Vec3};
+
+use crate::collision::Obb;
+
+const MAX_ITERATIONS: usize = 64;
+const GJK_TOLERANCE: f32 = 1e-6;
+const EPA_TOLERANCE: f32 = 1e-4;
+const DEGENERATE_EPSILON: f32 = 1e-8;
+
+// anything gjk can collide, described only by its support mapping
+pub trait ConvexShape
+{
+    // the furthest point of the shape along direction, in world space.
+    // direction does not have to be normalized.
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3;
+
+    // any point inside the shape, used to pick the first search direction
+    fn center(&self) -> Vec3;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sphere
+{
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+// all points within radius of the segment from start to end
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule
+{
+    pub start: Vec3,
+    pub end: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cylinder
+{
+    pub center: Vec3,
+    // unit length
+    pub axis: Vec3,
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+// the convex hull of a point cloud, the hull itself never has to be built
+// since the support function only needs the points. there has to be at least
+// one.
+#[derive(Debug, Clone)]
+pub struct ConvexHull
+{
+    points: Vec<Vec3>,
+}
+
+impl ConvexHull
+{
+    // None without any points, the support function needs one to return
+    pub fn new(points: Vec<Vec3>) -> Option<Self>
+    {
+        if points.is_empty()
+        {
+            return None;
+        }
+        Some(Self { points })
+    }
+
+    // reads the positions out of interleaved vertex data, the same layout
+    // handed to VertexArray::new. positions are expected first in every vertex.
+    // None when there isn't a whole vertex.
+    pub fn from_vertex_data(
+        vertex_data: &[f32],
+        stride: usize,
+    ) -> Option<Self>
+    {
+        Self::new(
+            vertex_data
+                .chunks_exact(stride.max(3))
+                .map(|vertex| vec3(vertex[0], vertex[1], vertex[2]))
+                .collect(),
+        )
+    }
+
+    pub fn transformed(
+        &self,
+        model: &Mat4,
+    ) -> Self
+    {
+        let points = self
+            .points
+            .iter()
+            .map(|point| {
+                let p = *model * glm::vec4(point.x, point.y, point.z, 1.0);
+                vec3(p.x, p.y, p.z)
+            })
+            .collect();
+        Self { points }
+    }
+}
+
+fn direction_or_x(direction: Vec3) -> Vec3
+{
+    let length = glm::length(direction);
+    if length > DEGENERATE_EPSILON
+    {
+        direction / length
+    }
+    else
+    {
+        vec3(1.0, 0.0, 0.0)
+    }
+}
+
+impl ConvexShape for Sphere
+{
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3
+    {
+        self.center + direction_or_x(direction) * self.radius
+    }
+
+    fn center(&self) -> Vec3
+    {
+        self.center
+    }
+}
+
+impl ConvexShape for Capsule
+{
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3
+    {
+        let end_point = if glm::dot(self.end - self.start, direction) > 0.0
+        {
+            self.end
+        }
+        else
+        {
+            self.start
+        };
+        end_point + direction_or_x(direction) * self.radius
+    }
+
+    fn center(&self) -> Vec3
+    {
+        (self.start + self.end) * 0.5
+    }
+}
+
+impl ConvexShape for Cylinder
+{
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3
+    {
+        let along = glm::dot(direction, self.axis);
+        let sign = if along >= 0.0 { 1.0 } else { -1.0 };
+        let radial = direction - self.axis * along;
+        let radial_length = glm::length(radial);
+        let rim = if radial_length > DEGENERATE_EPSILON
+        {
+            radial * (self.radius / radial_length)
+        }
+        else
+        {
+            vec3(0.0, 0.0, 0.0)
+        };
+        self.center + self.axis * (sign * self.half_height) + rim
+    }
+
+    fn center(&self) -> Vec3
+    {
+        self.center
+    }
+}
+
+impl ConvexShape for ConvexHull
+{
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3
+    {
+        let mut best = self.points[0];
+        let mut best_distance = f32::MIN;
+        for point in &self.points
+        {
+            let distance = glm::dot(*point, direction);
+            if distance > best_distance
+            {
+                best_distance = distance;
+                best = *point;
+            }
+        }
+        best
+    }
+
+    fn center(&self) -> Vec3
+    {
+        let sum = self
+            .points
+            .iter()
+            .fold(vec3(0.0, 0.0, 0.0), |sum, point| sum + *point);
+        sum / self.points.len() as f32
+    }
+}
+
+impl ConvexShape for Obb
+{
+    fn support(
+        &self,
+        direction: Vec3,
+    ) -> Vec3
+    {
+        let mut point = self.center;
+        for k in 0..3
+        {
+            let sign = if glm::dot(self.axes[k], direction) >= 0.0
+            {
+                1.0
+            }
+            else
+            {
+                -1.0
+            };
+            point = point + self.axes[k] * (sign * self.half_extents[k]);
+        }
+        point
+    }
+
+    fn center(&self) -> Vec3
+    {
+        self.center
+    }
+}
+
+// a point of the minkowski difference a - b, remembering which points of a
+// and b produced it so closest points can be recovered.
+#[derive(Debug, Clone, Copy)]
+struct SupportPoint
+{
+    point: Vec3,
+    on_a: Vec3,
+    on_b: Vec3,
+}
+
+fn minkowski_support(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+    direction: Vec3,
+) -> SupportPoint
+{
+    let on_a = a.support(direction);
+    let on_b = b.support(-direction);
+    SupportPoint {
+        point: on_a - on_b,
+        on_a,
+        on_b,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Separation
+{
+    pub distance: f32,
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Penetration
+{
+    // points from a towards b, moving a by -normal * depth separates the pair
+    pub normal: Vec3,
+    pub depth: f32,
+    pub point_a: Vec3,
+    pub point_b: Vec3,
+}
+
+enum GjkResult
+{
+    Separated(Separation),
+    Intersecting(Vec<SupportPoint>),
+}
+
+pub fn gjk_intersect(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+) -> bool
+{
+    matches!(gjk(a, b), GjkResult::Intersecting(_))
+}
+
+// distance and closest points between two separated shapes, none when they
+// overlap.
+pub fn gjk_distance(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+) -> Option<Separation>
+{
+    match gjk(a, b)
+    {
+        GjkResult::Separated(separation) => Some(separation),
+        GjkResult::Intersecting(_) => None,
+    }
+}
+
+// penetration depth and normal of two overlapping shapes via epa, none when
+// they are separated.
+pub fn epa_penetration(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+) -> Option<Penetration>
+{
+    match gjk(a, b)
+    {
+        GjkResult::Separated(_) => None,
+        GjkResult::Intersecting(simplex) => Some(epa(a, b, simplex)),
+    }
+}
+
+fn gjk(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+) -> GjkResult
+{
+    let first = minkowski_support(a, b, b.center() - a.center());
+    let mut simplex = vec![first];
+    let mut weights = vec![1.0];
+    let mut closest = first.point;
+
+    for _ in 0..MAX_ITERATIONS
+    {
+        let distance_squared = glm::dot(closest, closest);
+        if distance_squared < GJK_TOLERANCE * GJK_TOLERANCE
+        {
+            return GjkResult::Intersecting(simplex);
+        }
+
+        let next = minkowski_support(a, b, -closest);
+        let progress = distance_squared - glm::dot(closest, next.point);
+        let repeated = simplex
+            .iter()
+            .any(|existing| glm::length(existing.point - next.point) < GJK_TOLERANCE);
+        if progress <= GJK_TOLERANCE * distance_squared.max(1.0) || repeated
+        {
+            break;
+        }
+
+        simplex.push(next);
+        let points: Vec<Vec3> = simplex.iter().map(|support| support.point).collect();
+        let (new_closest, new_weights) = closest_on_simplex(&points);
+        if new_weights.len() == 4 && new_weights.iter().all(|weight| *weight > 0.0)
+        {
+            // the origin is inside the tetrahedron
+            return GjkResult::Intersecting(simplex);
+        }
+
+        // drop the vertices that don't contribute to the closest point
+        let mut kept = vec![];
+        weights = vec![];
+        for (support, weight) in simplex.iter().zip(new_weights)
+        {
+            if weight > 0.0
+            {
+                kept.push(*support);
+                weights.push(weight);
+            }
+        }
+        simplex = kept;
+        closest = new_closest;
+    }
+
+    let mut point_a = vec3(0.0, 0.0, 0.0);
+    let mut point_b = vec3(0.0, 0.0, 0.0);
+    for (support, weight) in simplex.iter().zip(weights)
+    {
+        point_a = point_a + support.on_a * weight;
+        point_b = point_b + support.on_b * weight;
+    }
+    GjkResult::Separated(Separation {
+        distance: glm::length(closest),
+        point_a,
+        point_b,
+    })
+}
+
+// closest point to the origin on a simplex of one to four points, with the
+// barycentric weight of every vertex (zero for vertices outside the feature)
+fn closest_on_simplex(points: &[Vec3]) -> (Vec3, Vec<f32>)
+{
+    match points.len()
+    {
+        1 => (points[0], vec![1.0]),
+        2 =>
+        {
+            let (closest, [u, v]) = closest_on_segment(points[0], points[1]);
+            (closest, vec![u, v])
+        }
+        3 =>
+        {
+            let (closest, [u, v, w]) = closest_on_triangle(points[0], points[1], points[2]);
+            (closest, vec![u, v, w])
+        }
+        _ => closest_on_tetrahedron(points),
+    }
+}
+
+fn closest_on_segment(
+    a: Vec3,
+    b: Vec3,
+) -> (Vec3, [f32; 2])
+{
+    let ab = b - a;
+    let length_squared = glm::dot(ab, ab);
+    if length_squared < DEGENERATE_EPSILON
+    {
+        return (a, [1.0, 0.0]);
+    }
+    let t = (-glm::dot(a, ab) / length_squared).clamp(0.0, 1.0);
+    (a + ab * t, [1.0 - t, t])
+}
+
+// voronoi region walk from real time collision detection, 5.1.5
+fn closest_on_triangle(
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+) -> (Vec3, [f32; 3])
+{
+    let ab = b - a;
+    let ac = c - a;
+    let ap = -a;
+    let d1 = glm::dot(ab, ap);
+    let d2 = glm::dot(ac, ap);
+    if d1 <= 0.0 && d2 <= 0.0
+    {
+        return (a, [1.0, 0.0, 0.0]);
+    }
+
+    let bp = -b;
+    let d3 = glm::dot(ab, bp);
+    let d4 = glm::dot(ac, bp);
+    if d3 >= 0.0 && d4 <= d3
+    {
+        return (b, [0.0, 1.0, 0.0]);
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0
+    {
+        let v = d1 / (d1 - d3);
+        return (a + ab * v, [1.0 - v, v, 0.0]);
+    }
+
+    let cp = -c;
+    let d5 = glm::dot(ab, cp);
+    let d6 = glm::dot(ac, cp);
+    if d6 >= 0.0 && d5 <= d6
+    {
+        return (c, [0.0, 0.0, 1.0]);
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0
+    {
+        let w = d2 / (d2 - d6);
+        return (a + ac * w, [1.0 - w, 0.0, w]);
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0
+    {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return (b + (c - b) * w, [0.0, 1.0 - w, w]);
+    }
+
+    let denominator = va + vb + vc;
+    if denominator.abs() < DEGENERATE_EPSILON
+    {
+        // collapsed triangle, fall back to its longest edge
+        let (closest, [u, v]) = closest_on_segment(a, b);
+        return (closest, [u, v, 0.0]);
+    }
+    let v = vb / denominator;
+    let w = vc / denominator;
+    (a + ab * v + ac * w, [1.0 - v - w, v, w])
+}
+
+fn closest_on_tetrahedron(points: &[Vec3]) -> (Vec3, Vec<f32>)
+{
+    const FACES: [[usize; 4]; 4] = [[0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 3, 1], [1, 2, 3, 0]];
+
+    let mut best: Option<(Vec3, Vec<f32>)> = None;
+    let mut best_distance = f32::MAX;
+    for [i, j, k, opposite] in FACES
+    {
+        let (a, b, c) = (points[i], points[j], points[k]);
+        let normal = glm::cross(b - a, c - a);
+        let origin_side = glm::dot(-a, normal);
+        let opposite_side = glm::dot(points[opposite] - a, normal);
+        // only faces with the origin on the outside can hold the closest point
+        if origin_side * opposite_side >= 0.0 && opposite_side.abs() > DEGENERATE_EPSILON
+        {
+            continue;
+        }
+        let (closest, [u, v, w]) = closest_on_triangle(a, b, c);
+        let distance = glm::dot(closest, closest);
+        if distance < best_distance
+        {
+            best_distance = distance;
+            let mut weights = vec![0.0; 4];
+            weights[i] = u;
+            weights[j] = v;
+            weights[k] = w;
+            best = Some((closest, weights));
+        }
+    }
+
+    best.unwrap_or((vec3(0.0, 0.0, 0.0), vec![0.25; 4]))
+}
+
+struct Face
+{
+    indices: [usize; 3],
+    normal: Vec3,
+    distance: f32,
+}
+
+fn make_face(
+    vertices: &[SupportPoint],
+    indices: [usize; 3],
+    interior: Vec3,
+) -> Option<Face>
+{
+    let a = vertices[indices[0]].point;
+    let b = vertices[indices[1]].point;
+    let c = vertices[indices[2]].point;
+    let normal = glm::cross(b - a, c - a);
+    let length = glm::length(normal);
+    if length < DEGENERATE_EPSILON
+    {
+        return None;
+    }
+    let mut normal = normal / length;
+    let mut indices = indices;
+    if glm::dot(normal, a - interior) < 0.0
+    {
+        normal = -normal;
+        indices.swap(1, 2);
+    }
+    Some(Face {
+        indices,
+        normal,
+        distance: glm::dot(normal, a),
+    })
+}
+
+// grows whatever gjk stopped with into a tetrahedron, touching shapes can
+// leave gjk with fewer than four points.
+fn blow_up_simplex(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+    mut simplex: Vec<SupportPoint>,
+) -> Vec<SupportPoint>
+{
+    let directions = [
+        vec3(1.0, 0.0, 0.0),
+        vec3(-1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, -1.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+        vec3(0.0, 0.0, -1.0),
+    ];
+
+    if simplex.len() == 1
+    {
+        for direction in directions
+        {
+            let support = minkowski_support(a, b, direction);
+            if glm::length(support.point - simplex[0].point) > GJK_TOLERANCE
+            {
+                simplex.push(support);
+                break;
+            }
+        }
+    }
+
+    if simplex.len() == 2
+    {
+        let line = simplex[1].point - simplex[0].point;
+        for direction in directions
+        {
+            let perpendicular = glm::cross(line, direction);
+            if glm::length(perpendicular) < DEGENERATE_EPSILON
+            {
+                continue;
+            }
+            let support = minkowski_support(a, b, perpendicular);
+            let area = glm::cross(line, support.point - simplex[0].point);
+            if glm::length(area) > GJK_TOLERANCE
+            {
+                simplex.push(support);
+                break;
+            }
+        }
+    }
+
+    if simplex.len() == 3
+    {
+        let normal = glm::cross(
+            simplex[1].point - simplex[0].point,
+            simplex[2].point - simplex[0].point,
+        );
+        for direction in [normal, -normal]
+        {
+            let support = minkowski_support(a, b, direction);
+            let volume = glm::dot(support.point - simplex[0].point, normal);
+            if volume.abs() > GJK_TOLERANCE
+            {
+                simplex.push(support);
+                break;
+            }
+        }
+    }
+
+    simplex
+}
+
+// the face nearest the origin, faces can't be empty
+fn closest_face(faces: &[Face]) -> usize
+{
+    let mut closest = 0;
+    for (i, face) in faces.iter().enumerate()
+    {
+        if face.distance < faces[closest].distance
+        {
+            closest = i;
+        }
+    }
+    closest
+}
+
+fn epa(
+    a: &dyn ConvexShape,
+    b: &dyn ConvexShape,
+    simplex: Vec<SupportPoint>,
+) -> Penetration
+{
+    let mut vertices = blow_up_simplex(a, b, simplex);
+    if vertices.len() < 4
+    {
+        // flat minkowski difference, the shapes only just touch
+        let on_a = vertices[0].on_a;
+        let on_b = vertices[0].on_b;
+        return Penetration {
+            normal: direction_or_x(b.center() - a.center()),
+            depth: 0.0,
+            point_a: on_a,
+            point_b: on_b,
+        };
+    }
+
+    let interior = vertices
+        .iter()
+        .fold(vec3(0.0, 0.0, 0.0), |sum, vertex| sum + vertex.point)
+        * 0.25;
+    let mut faces: Vec<Face> = [[0, 1, 2], [0, 1, 3], [0, 2, 3], [1, 2, 3]]
+        .into_iter()
+        .filter_map(|indices| make_face(&vertices, indices, interior))
+        .collect();
+
+    for _ in 0..MAX_ITERATIONS
+    {
+        let face = &faces[closest_face(&faces)];
+        let support = minkowski_support(a, b, face.normal);
+        if glm::dot(support.point, face.normal) - face.distance < EPA_TOLERANCE
+        {
+            break;
+        }
+
+        // remove every face the new point can see and stitch the hole shut
+        vertices.push(support);
+        let new_index = vertices.len() - 1;
+        let mut horizon: Vec<(usize, usize)> = vec![];
+        faces.retain(|face| {
+            let visible =
+                glm::dot(face.normal, support.point - vertices[face.indices[0]].point) > 0.0;
+            if visible
+            {
+                for edge in [
+                    (face.indices[0], face.indices[1]),
+                    (face.indices[1], face.indices[2]),
+                    (face.indices[2], face.indices[0]),
+                ]
+                {
+                    // an edge shared by two removed faces is interior to the hole
+                    if let Some(position) =
+                        horizon.iter().position(|other| *other == (edge.1, edge.0))
+                    {
+                        horizon.swap_remove(position);
+                    }
+                    else
+                    {
+                        horizon.push(edge);
+                    }
+                }
+            }
+            !visible
+        });
+        for (start, end) in horizon
+        {
+            if let Some(face) = make_face(&vertices, [start, end, new_index], interior)
+            {
+                faces.push(face);
+            }
+        }
+        if faces.is_empty()
+        {
+            break;
+        }
+    }
+
+    if faces.is_empty()
+    {
+        return Penetration {
+            normal: direction_or_x(b.center() - a.center()),
+            depth: 0.0,
+            point_a: a.center(),
+            point_b: b.center(),
+        };
+    }
+    // the faces can have changed since the last pick when the iterations ran out
+    let face = &faces[closest_face(&faces)];
+    let [i, j, k] = face.indices;
+    let projected = face.normal * face.distance;
+    let (_, [u, v, w]) = closest_on_triangle(
+        vertices[i].point - projected,
+        vertices[j].point - projected,
+        vertices[k].point - projected,
+    );
+    Penetration {
+        normal: face.normal,
+        depth: face.distance,
+        point_a: vertices[i].on_a * u + vertices[j].on_a * v + vertices[k].on_a * w,
+        point_b: vertices[i].on_b * u + vertices[j].on_b * v + vertices[k].on_b * w,
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::{vec3, Vec3};
+
+    use super::{
+        epa_penetration, gjk_distance, gjk_intersect, Capsule, ConvexHull, ConvexShape, Cylinder,
+        Sphere,
+    };
+    use crate::collision::{test_obb_collision, Obb};
+
+    fn unit_box(center: Vec3) -> Obb
+    {
+        Obb {
+            center,
+            axes: [
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+            ],
+            half_extents: vec3(0.5, 0.5, 0.5),
+        }
+    }
+
+    fn close(
+        a: Vec3,
+        b: Vec3,
+    ) -> bool
+    {
+        glm::length(a - b) < 1e-3
+    }
+
+    #[test]
+    fn test_triangles_colliding()
+    {
+        // same triangles as collision::test_3d
+        let triangle_one = ConvexHull::new(vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+        ])
+        .unwrap();
+        let triangle_two = triangle_one.clone();
+
+        assert!(gjk_intersect(&triangle_one, &triangle_two));
+    }
+
+    #[test]
+    fn test_triangles_not_colliding()
+    {
+        let triangle_one = ConvexHull::new(vec![
+            vec3(0.0, 0.0, 1.0),
+            vec3(0.0, 1.0, 1.0),
+            vec3(1.0, 0.0, 1.0),
+            vec3(0.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.0, 0.0, 1.0),
+        ])
+        .unwrap();
+        let triangle_two = ConvexHull::new(vec![
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.0, 1.0, 0.0),
+            vec3(1.0, 0.0, 0.0),
+        ])
+        .unwrap();
+
+        assert!(!gjk_intersect(&triangle_one, &triangle_two));
+        let separation = gjk_distance(&triangle_one, &triangle_two).unwrap();
+        assert!((separation.distance - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_demo_grid_cubes_are_separated()
+    {
+        // neighbours in the CubeGameState grid sit 1.1 apart
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(1.1, 0.0, 0.0));
+
+        let separation = gjk_distance(&a, &b).unwrap();
+        assert!((separation.distance - 0.1).abs() < 1e-4);
+        assert!((separation.point_a.x - 0.5).abs() < 1e-4);
+        assert!((separation.point_b.x - 0.6).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_box_penetration_matches_sat()
+    {
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(0.2, 0.9, 0.1));
+
+        let penetration = epa_penetration(&a, &b).unwrap();
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        assert!((penetration.depth - manifold.depth).abs() < 1e-3);
+        assert!(close(penetration.normal, manifold.normal));
+    }
+
+    #[test]
+    fn test_crossed_edges_match_sat()
+    {
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let a = Obb {
+            center: vec3(0.0, 0.0, 0.0),
+            axes: [vec3(h, h, 0.0), vec3(-h, h, 0.0), vec3(0.0, 0.0, 1.0)],
+            half_extents: vec3(0.5, 0.5, 0.5),
+        };
+        let b = Obb {
+            center: vec3(0.0, 1.38, 0.0),
+            axes: [vec3(1.0, 0.0, 0.0), vec3(0.0, h, h), vec3(0.0, -h, h)],
+            half_extents: vec3(0.5, 0.5, 0.5),
+        };
+
+        let penetration = epa_penetration(&a, &b).unwrap();
+        let manifold = test_obb_collision(&a, &b).unwrap();
+
+        assert!((penetration.depth - manifold.depth).abs() < 1e-3);
+        assert!(close(penetration.normal, manifold.normal));
+        assert!(close(
+            penetration.point_a,
+            vec3(0.0, std::f32::consts::SQRT_2 * 0.5, 0.0)
+        ));
+        // the two points are the penetration apart
+        assert!(close(
+            penetration.point_a - penetration.point_b,
+            penetration.normal * penetration.depth
+        ));
+    }
+
+    #[test]
+    fn test_hull_from_vertex_data_matches_box()
+    {
+        #[rustfmt::skip]
+        let vertex_data = [
+            -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
+             0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
+            -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
+             0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
+             0.5,  0.5,  0.5,  1.0,  0.0,  0.0,
+            -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,
+             0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
+            -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
+        ];
+        #[rustfmt::skip]
+        let model = glm::mat4(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            2.0, 0.0, 0.0, 1.0,
+        );
+        let hull = ConvexHull::from_vertex_data(&vertex_data, 6)
+            .unwrap()
+            .transformed(&model);
+        let obb = unit_box(vec3(2.0, 0.0, 0.0));
+
+        for direction in [
+            vec3(1.0, 2.0, 3.0),
+            vec3(-1.0, 0.5, 0.1),
+            vec3(0.3, -0.7, -0.2),
+        ]
+        {
+            assert!(close(hull.support(direction), obb.support(direction)));
+        }
+
+        assert!(ConvexHull::from_vertex_data(&[], 6).is_none());
+        assert!(ConvexHull::from_vertex_data(&vertex_data[..5], 6).is_none());
+        assert!(ConvexHull::new(vec![]).is_none());
+    }
+
+    #[test]
+    fn test_spheres()
+    {
+        let a = Sphere {
+            center: vec3(0.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let b = Sphere {
+            center: vec3(3.0, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let separation = gjk_distance(&a, &b).unwrap();
+        assert!((separation.distance - 1.0).abs() < 1e-4);
+
+        let b = Sphere {
+            center: vec3(1.5, 0.0, 0.0),
+            radius: 1.0,
+        };
+        let penetration = epa_penetration(&a, &b).unwrap();
+        assert!((penetration.depth - 0.5).abs() < 1e-2);
+        assert!(glm::length(penetration.normal - vec3(1.0, 0.0, 0.0)) < 1e-2);
+    }
+
+    #[test]
+    fn test_capsule_resting_on_box()
+    {
+        let floor = unit_box(vec3(0.0, 0.0, 0.0));
+        let capsule = Capsule {
+            start: vec3(-1.0, 0.7, 0.0),
+            end: vec3(1.0, 0.7, 0.0),
+            radius: 0.25,
+        };
+
+        let penetration = epa_penetration(&floor, &capsule).unwrap();
+        assert!((penetration.depth - 0.05).abs() < 1e-3);
+        assert!(close(penetration.normal, vec3(0.0, 1.0, 0.0)));
+
+        let lifted = Capsule {
+            start: vec3(-1.0, 1.0, 0.0),
+            end: vec3(1.0, 1.0, 0.0),
+            radius: 0.25,
+        };
+        let separation = gjk_distance(&floor, &lifted).unwrap();
+        assert!((separation.distance - 0.25).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_cylinder_and_sphere()
+    {
+        let cylinder = Cylinder {
+            center: vec3(0.0, 0.0, 0.0),
+            axis: vec3(0.0, 1.0, 0.0),
+            half_height: 1.0,
+            radius: 0.5,
+        };
+        let beside = Sphere {
+            center: vec3(1.0, 0.0, 0.0),
+            radius: 0.25,
+        };
+        let separation = gjk_distance(&cylinder, &beside).unwrap();
+        assert!((separation.distance - 0.25).abs() < 1e-4);
+
+        let above = Sphere {
+            center: vec3(0.0, 1.2, 0.0),
+            radius: 0.25,
+        };
+        let penetration = epa_penetration(&cylinder, &above).unwrap();
+        assert!((penetration.depth - 0.05).abs() < 1e-3);
+        assert!(close(penetration.normal, vec3(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_touching_boxes()
+    {
+        let a = unit_box(vec3(0.0, 0.0, 0.0));
+        let b = unit_box(vec3(1.0, 0.0, 0.0));
+
+        // faces touching count as overlapping, by nothing
+        let penetration = epa_penetration(&a, &b).unwrap();
+        assert!(penetration.depth.abs() < 1e-3);
+        assert!(close(penetration.normal, vec3(1.0, 0.0, 0.0)));
+    }
+}
@@ -1,2 +1,733 @@
+use std::{path::PathBuf, time::Duration};
 
+use gl::types::{GLenum, GLsizei, GLsizeiptr, GLuint};
+use glm::{vec3, vec4, Mat4};
+use image::{DynamicImage, RgbaImage};
+use imgui::{
+    Condition, Context, Drag, DrawCmd, DrawCmdParams, DrawData, DrawIdx, Key, TextureId,
+    TreeNodeFlags, Ui,
+};
+use winit::{
+    event::{
+        DeviceEvent, ElementState, KeyboardInput, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+    },
+    window::Window,
+};
 
+use crate::{
+    assets::{AssetError, Assets, Handle},
+    camera::{Camera, Projection},
+    components::{Collider, Name, RigidBody, Transform},
+    ecs::World,
+    physics::PhysicsWorld,
+    program::Program,
+    renderer::VertexArray,
+    texture::{Texture, TextureOptions},
+    window_utils::grab_cursor,
+};
+
+// how many frames the timings plot goes back
+const FRAME_HISTORY: usize = 120;
+
+// the most recent frame times in milliseconds, oldest first
+#[derive(Debug, Default)]
+pub struct FrameTimes
+{
+    times: Vec<f32>,
+}
+
+impl FrameTimes
+{
+    pub fn push(
+        &mut self,
+        seconds: f32,
+    )
+    {
+        if self.times.len() == FRAME_HISTORY
+        {
+            self.times.remove(0);
+        }
+        self.times.push(seconds * 1000.0);
+    }
+
+    pub fn values(&self) -> &[f32]
+    {
+        &self.times
+    }
+
+    pub fn average(&self) -> f32
+    {
+        self.times.iter().sum::<f32>() / self.times.len().max(1) as f32
+    }
+
+    pub fn worst(&self) -> f32
+    {
+        self.times.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+// an imgui overlay with panels for the frame timings, the camera and the
+// bodies. while it's open the cursor is let go and the mouse works the panels
+// instead of the camera.
+pub struct DebugGui
+{
+    context: Context,
+    renderer: GuiRenderer,
+    frame_times: FrameTimes,
+    // every asset that failed the last time it was built, see set_errors
+    errors: Vec<(PathBuf, String)>,
+    // off while recording, a replay only has the player's input
+    editable: bool,
+    open: bool,
+}
+
+impl DebugGui
+{
+    pub fn new(assets: &mut Assets) -> Result<Self, AssetError>
+    {
+        let mut context = Context::create();
+        // otherwise imgui writes imgui.ini wherever it was started from
+        context.set_ini_filename(None);
+        let renderer = unsafe { GuiRenderer::new(&mut context, assets)? };
+        Ok(Self {
+            context,
+            renderer,
+            frame_times: FrameTimes::default(),
+            errors: Vec::new(),
+            editable: true,
+            open: false,
+        })
+    }
+
+    pub fn set_errors(
+        &mut self,
+        assets: &Assets,
+    )
+    {
+        self.errors = assets
+            .errors()
+            .map(|(path, error)| (path.to_path_buf(), error.to_string()))
+            .collect();
+        self.errors.sort();
+    }
+
+    pub fn set_editable(
+        &mut self,
+        editable: bool,
+    )
+    {
+        self.editable = editable;
+    }
+
+    pub fn is_open(&self) -> bool
+    {
+        self.open
+    }
+
+    pub fn set_open(
+        &mut self,
+        window: &Window,
+        open: bool,
+    )
+    {
+        self.open = open;
+        grab_cursor(window, !open);
+        if !open
+        {
+            // imgui's way of saying the mouse is gone
+            self.context
+                .io_mut()
+                .add_mouse_pos_event([-f32::MAX, -f32::MAX]);
+        }
+    }
+
+    // the mouse only reaches imgui while the overlay is open, keys always do
+    // so releases aren't lost
+    pub fn handle_window_event(
+        &mut self,
+        window: &Window,
+        event: &WindowEvent,
+    )
+    {
+        let io = self.context.io_mut();
+        match event
+        {
+            WindowEvent::CursorMoved { position, .. } if self.open =>
+            {
+                let position = position.to_logical::<f32>(window.scale_factor());
+                io.add_mouse_pos_event([position.x, position.y]);
+            }
+            WindowEvent::MouseInput { state, button, .. } if self.open =>
+            {
+                if let Some(button) = gui_mouse_button(*button)
+                {
+                    io.add_mouse_button_event(button, *state == ElementState::Pressed);
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } if self.open =>
+            {
+                let wheel = match delta
+                {
+                    MouseScrollDelta::LineDelta(x, y) => [*x, *y],
+                    MouseScrollDelta::PixelDelta(position) =>
+                    {
+                        [position.x as f32 / 20.0, position.y as f32 / 20.0]
+                    }
+                };
+                io.add_mouse_wheel_event(wheel);
+            }
+            WindowEvent::KeyboardInput { input, .. } =>
+            {
+                if let Some(key) = input.virtual_keycode.and_then(gui_key)
+                {
+                    io.add_key_event(key, input.state == ElementState::Pressed);
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) =>
+            {
+                io.add_key_event(Key::ModCtrl, modifiers.ctrl());
+                io.add_key_event(Key::ModShift, modifiers.shift());
+                io.add_key_event(Key::ModAlt, modifiers.alt());
+                io.add_key_event(Key::ModSuper, modifiers.logo());
+            }
+            WindowEvent::ReceivedCharacter(character) if self.open =>
+            {
+                io.add_input_character(*character)
+            }
+            _ =>
+            {}
+        }
+    }
+
+    // whether the game should leave a device event alone: mouse movement while
+    // the overlay is open, and key presses while a text field has focus
+    pub fn blocks(
+        &self,
+        event: &DeviceEvent,
+    ) -> bool
+    {
+        match event
+        {
+            DeviceEvent::MouseMotion { .. } | DeviceEvent::MouseWheel { .. } => self.open,
+            DeviceEvent::Key(KeyboardInput {
+                state: ElementState::Pressed,
+                ..
+            }) => self.open && self.context.io().want_capture_keyboard,
+            _ => false,
+        }
+    }
+
+    // draws over whatever is in the framebuffer, edits made in the panels go
+    // straight into the camera and the world
+    pub fn draw(
+        &mut self,
+        window: &Window,
+        frame_time: f32,
+        steps: u32,
+        camera: &mut Camera,
+        controller: &str,
+        world: &World,
+    )
+    {
+        self.frame_times.push(frame_time);
+        if !self.open
+        {
+            return;
+        }
+
+        let io = self.context.io_mut();
+        let scale = window.scale_factor() as f32;
+        let size = window.inner_size();
+        io.display_size = [size.width as f32 / scale, size.height as f32 / scale];
+        io.display_framebuffer_scale = [scale, scale];
+        // imgui wants time to pass between frames
+        io.update_delta_time(Duration::from_secs_f32(frame_time.max(1e-6)));
+
+        let ui = self.context.new_frame();
+        timings_panel(ui, &self.frame_times, steps);
+        camera_panel(ui, camera, controller);
+        bodies_panel(ui, world, self.editable);
+        errors_panel(ui, &self.errors);
+        let draw_data = self.context.render();
+        unsafe { self.renderer.render(draw_data) };
+    }
+}
+
+fn timings_panel(
+    ui: &Ui,
+    frame_times: &FrameTimes,
+    steps: u32,
+)
+{
+    ui.window("Frame")
+        .position([10.0, 10.0], Condition::FirstUseEver)
+        .always_auto_resize(true)
+        .build(|| {
+            let average = frame_times.average();
+            ui.text(format!("{:.2} ms, {:.0} fps", average, 1000.0 / average));
+            ui.text(format!("worst {:.2} ms", frame_times.worst()));
+            ui.text(format!("{} physics steps this frame", steps));
+            ui.plot_lines("ms", frame_times.values())
+                .scale_min(0.0)
+                .graph_size([240.0, 60.0])
+                .build();
+        });
+}
+
+fn camera_panel(
+    ui: &Ui,
+    camera: &mut Camera,
+    controller: &str,
+)
+{
+    ui.window("Camera")
+        .position([10.0, 170.0], Condition::FirstUseEver)
+        .always_auto_resize(true)
+        .build(|| {
+            ui.text(format!("controller: {}", controller));
+            let mut position = *camera.camera_position.as_array();
+            if Drag::new("position")
+                .speed(0.1)
+                .build_array(ui, &mut position)
+            {
+                camera.camera_position = vec3(position[0], position[1], position[2]);
+            }
+            let mut rotation = [camera.yaw(), camera.pitch()];
+            if Drag::new("yaw, pitch").build_array(ui, &mut rotation)
+            {
+                camera.set_rotation(rotation[0], rotation[1]);
+            }
+            let mut orthographic = matches!(camera.projection, Projection::Orthographic { .. });
+            if ui.checkbox("orthographic", &mut orthographic)
+            {
+                camera.projection = camera.projection.toggled();
+            }
+            match &mut camera.projection
+            {
+                Projection::Perspective { fov, .. } =>
+                {
+                    Drag::new("fov").range(10.0, 120.0).build(ui, fov);
+                }
+                Projection::Orthographic { height, .. } =>
+                {
+                    Drag::new("height")
+                        .range(1.0, 500.0)
+                        .speed(0.1)
+                        .build(ui, height);
+                }
+            }
+            // turns itself back off without glClipControl
+            ui.checkbox("reverse z", &mut camera.reverse_z);
+        });
+}
+
+fn bodies_panel(
+    ui: &Ui,
+    world: &World,
+    editable: bool,
+)
+{
+    let bodies = world.components::<RigidBody>();
+    let names = world.components::<Name>();
+    let colliders = world.components::<Collider>();
+    let mut transforms = world.components_mut::<Transform>();
+    let mut physics = world.resource_mut::<PhysicsWorld>();
+    ui.window("Bodies")
+        .position([10.0, 320.0], Condition::FirstUseEver)
+        .size([320.0, 400.0], Condition::FirstUseEver)
+        .build(|| {
+            if !editable
+            {
+                ui.text_disabled("read only while recording");
+            }
+            let _disabled = ui.begin_disabled(!editable);
+            for (entity, body) in bodies.iter()
+            {
+                let label = names.get(entity).map_or_else(
+                    || format!("entity {}", entity.index()),
+                    |name| name.0.clone(),
+                );
+                // names don't have to be unique
+                let _id = ui.push_id_usize(entity.index() as usize);
+                if !ui.collapsing_header(&label, TreeNodeFlags::empty())
+                {
+                    continue;
+                }
+                let body = physics.body_mut(body.handle);
+                let mut position = *body.position.as_array();
+                if Drag::new("position")
+                    .speed(0.05)
+                    .build_array(ui, &mut position)
+                {
+                    body.position = vec3(position[0], position[1], position[2]);
+                    // physics only copies it over on its next step
+                    if let Some(transform) = transforms.get_mut(entity)
+                    {
+                        transform.position = body.position;
+                    }
+                }
+                let mut velocity = *body.linear_velocity.as_array();
+                if Drag::new("velocity")
+                    .speed(0.05)
+                    .build_array(ui, &mut velocity)
+                {
+                    body.linear_velocity = vec3(velocity[0], velocity[1], velocity[2]);
+                }
+                let mut mass = body.mass;
+                if ui.input_float("mass", &mut mass).build()
+                {
+                    body.set_mass(mass);
+                }
+                // physics decides this one, it's only shown
+                if let Some(collider) = colliders.get(entity)
+                {
+                    let mut is_colliding = collider.is_colliding;
+                    ui.disabled(true, || {
+                        ui.checkbox("is colliding", &mut is_colliding);
+                    });
+                }
+            }
+        });
+}
+
+// the whole log of every asset that is failing right now
+fn errors_panel(
+    ui: &Ui,
+    errors: &[(PathBuf, String)],
+)
+{
+    if errors.is_empty()
+    {
+        return;
+    }
+    ui.window("Errors")
+        .position([340.0, 10.0], Condition::FirstUseEver)
+        .size([480.0, 240.0], Condition::FirstUseEver)
+        .build(|| {
+            for (path, error) in errors
+            {
+                ui.text_colored([1.0, 0.4, 0.4, 1.0], path.display().to_string());
+                ui.text_wrapped(error);
+                ui.separator();
+            }
+        });
+}
+
+// draws imgui's triangles with its font atlas, streaming the vertices and
+// indices in every frame
+struct GuiRenderer
+{
+    program: Handle<Program>,
+    vertex_array: VertexArray,
+    index_buffer: GLuint,
+    font: Texture,
+}
+
+impl Drop for GuiRenderer
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            gl::DeleteBuffers(1, &self.index_buffer);
+            gl::DeleteBuffers(1, &self.vertex_array.vbo);
+            gl::DeleteVertexArrays(1, &self.vertex_array.vao);
+        }
+    }
+}
+
+impl GuiRenderer
+{
+    unsafe fn new(
+        context: &mut Context,
+        assets: &mut Assets,
+    ) -> Result<Self, AssetError>
+    {
+        let program = assets.program("shaders/gui/gui_vert.glsl", "shaders/gui/gui_frag.glsl")?;
+
+        // imgui's DrawVert, position and uv then an rgba8 color, 5 floats wide
+        let mut vertex_array = VertexArray::new(&[], 5);
+        vertex_array.add_vert_att_ptr(2);
+        vertex_array.add_vert_att_ptr(2);
+        vertex_array.add_byte_color_att_ptr();
+        // the vertex array remembers the index buffer bound while it is
+        let mut index_buffer = 0;
+        gl::GenBuffers(1, &mut index_buffer);
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, index_buffer);
+
+        let fonts = context.fonts();
+        let font = {
+            let atlas = fonts.build_rgba32_texture();
+            let image = RgbaImage::from_raw(atlas.width, atlas.height, atlas.data.to_vec())
+                .expect("imgui's font atlas is the size it says");
+            let options = TextureOptions {
+                wrap_s: gl::CLAMP_TO_EDGE,
+                wrap_t: gl::CLAMP_TO_EDGE,
+                min_filter: gl::LINEAR,
+                mipmaps: false,
+                ..TextureOptions::default()
+            };
+            Texture::from_image(&DynamicImage::ImageRgba8(image), &options)
+        };
+        fonts.tex_id = TextureId::new(font.id as usize);
+
+        Ok(Self {
+            program,
+            vertex_array,
+            index_buffer,
+            font,
+        })
+    }
+
+    unsafe fn set_state(
+        &self,
+        draw_data: &DrawData,
+        framebuffer: [f32; 2],
+    )
+    {
+        gl::Enable(gl::BLEND);
+        gl::BlendEquation(gl::FUNC_ADD);
+        gl::BlendFuncSeparate(
+            gl::SRC_ALPHA,
+            gl::ONE_MINUS_SRC_ALPHA,
+            gl::ONE,
+            gl::ONE_MINUS_SRC_ALPHA,
+        );
+        gl::Disable(gl::CULL_FACE);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Viewport(0, 0, framebuffer[0] as GLsizei, framebuffer[1] as GLsizei);
+
+        self.program.bind();
+        self.program.set_uniform_mat4(
+            "guiProjection",
+            gui_projection(draw_data.display_pos, draw_data.display_size),
+        );
+        self.program.set_uniform_sampler("guiTexture", 0);
+        self.font.activate(0);
+        self.vertex_array.bind();
+    }
+
+    unsafe fn render(
+        &self,
+        draw_data: &DrawData,
+    )
+    {
+        let scale = draw_data.framebuffer_scale;
+        let framebuffer = [
+            draw_data.display_size[0] * scale[0],
+            draw_data.display_size[1] * scale[1],
+        ];
+        if framebuffer[0] <= 0.0 || framebuffer[1] <= 0.0
+        {
+            return;
+        }
+        self.set_state(draw_data, framebuffer);
+
+        let index_kind: GLenum = match std::mem::size_of::<DrawIdx>()
+        {
+            2 => gl::UNSIGNED_SHORT,
+            _ => gl::UNSIGNED_INT,
+        };
+        for list in draw_data.draw_lists()
+        {
+            let vertices = list.vtx_buffer();
+            let indices = list.idx_buffer();
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vertex_array.vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertices) as GLsizeiptr,
+                vertices.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.index_buffer);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(indices) as GLsizeiptr,
+                indices.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+
+            for command in list.commands()
+            {
+                match command
+                {
+                    DrawCmd::Elements {
+                        count,
+                        cmd_params:
+                            DrawCmdParams {
+                                clip_rect,
+                                texture_id,
+                                idx_offset,
+                                ..
+                            },
+                    } =>
+                    {
+                        let Some([x, y, width, height]) =
+                            scissor_box(clip_rect, draw_data.display_pos, scale, framebuffer[1])
+                        else
+                        {
+                            continue;
+                        };
+                        gl::Scissor(x, y, width, height);
+                        gl::BindTexture(gl::TEXTURE_2D, texture_id.id() as GLuint);
+                        gl::DrawElements(
+                            gl::TRIANGLES,
+                            count as GLsizei,
+                            index_kind,
+                            (idx_offset * std::mem::size_of::<DrawIdx>()) as *const _,
+                        );
+                    }
+                    DrawCmd::ResetRenderState => self.set_state(draw_data, framebuffer),
+                    // nothing here adds callbacks
+                    DrawCmd::RawCallback { .. } =>
+                    {}
+                }
+            }
+        }
+
+        // back to what the scene renderers expect
+        gl::Disable(gl::SCISSOR_TEST);
+        gl::Disable(gl::BLEND);
+        gl::Enable(gl::DEPTH_TEST);
+    }
+}
+
+// maps imgui's display rectangle, y down, onto clip space
+fn gui_projection(
+    position: [f32; 2],
+    size: [f32; 2],
+) -> Mat4
+{
+    let (left, top) = (position[0], position[1]);
+    let (right, bottom) = (left + size[0], top + size[1]);
+    Mat4::new(
+        vec4(2.0 / (right - left), 0.0, 0.0, 0.0),
+        vec4(0.0, 2.0 / (top - bottom), 0.0, 0.0),
+        vec4(0.0, 0.0, -1.0, 0.0),
+        vec4(
+            (right + left) / (left - right),
+            (top + bottom) / (bottom - top),
+            0.0,
+            1.0,
+        ),
+    )
+}
+
+// a clip rectangle, left top right bottom in points, as a scissor box in
+// framebuffer pixels with y up. None when it covers nothing.
+fn scissor_box(
+    clip_rect: [f32; 4],
+    display_position: [f32; 2],
+    scale: [f32; 2],
+    framebuffer_height: f32,
+) -> Option<[i32; 4]>
+{
+    let left = (clip_rect[0] - display_position[0]) * scale[0];
+    let top = (clip_rect[1] - display_position[1]) * scale[1];
+    let right = (clip_rect[2] - display_position[0]) * scale[0];
+    let bottom = (clip_rect[3] - display_position[1]) * scale[1];
+    if right <= left || bottom <= top
+    {
+        return None;
+    }
+    Some([
+        left as i32,
+        (framebuffer_height - bottom) as i32,
+        (right - left) as i32,
+        (bottom - top) as i32,
+    ])
+}
+
+fn gui_mouse_button(button: winit::event::MouseButton) -> Option<imgui::MouseButton>
+{
+    match button
+    {
+        winit::event::MouseButton::Left => Some(imgui::MouseButton::Left),
+        winit::event::MouseButton::Right => Some(imgui::MouseButton::Right),
+        winit::event::MouseButton::Middle => Some(imgui::MouseButton::Middle),
+        winit::event::MouseButton::Other(_) => None,
+    }
+}
+
+// the keys imgui's widgets use, for editing text and moving between fields
+fn gui_key(key: VirtualKeyCode) -> Option<Key>
+{
+    Some(match key
+    {
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Left => Key::LeftArrow,
+        VirtualKeyCode::Right => Key::RightArrow,
+        VirtualKeyCode::Up => Key::UpArrow,
+        VirtualKeyCode::Down => Key::DownArrow,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::NumpadEnter => Key::KeypadEnter,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::LControl => Key::LeftCtrl,
+        VirtualKeyCode::RControl => Key::RightCtrl,
+        VirtualKeyCode::LShift => Key::LeftShift,
+        VirtualKeyCode::RShift => Key::RightShift,
+        VirtualKeyCode::LAlt => Key::LeftAlt,
+        VirtualKeyCode::RAlt => Key::RightAlt,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec4;
+
+    use super::{gui_projection, scissor_box, FrameTimes, FRAME_HISTORY};
+
+    #[test]
+    fn test_frame_times()
+    {
+        let mut times = FrameTimes::default();
+        assert_eq!(times.average(), 0.0);
+        for frame in 0..FRAME_HISTORY + 10
+        {
+            times.push(if frame % 2 == 0 { 0.010 } else { 0.020 });
+        }
+        assert_eq!(times.values().len(), FRAME_HISTORY);
+        assert!((times.average() - 15.0).abs() < 1e-3);
+        assert!((times.worst() - 20.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_gui_projection()
+    {
+        let projection = gui_projection([0.0, 0.0], [800.0, 600.0]);
+        let top_left = projection * vec4(0.0, 0.0, 0.0, 1.0);
+        assert_eq!((top_left.x, top_left.y), (-1.0, 1.0));
+        let bottom_right = projection * vec4(800.0, 600.0, 0.0, 1.0);
+        assert_eq!((bottom_right.x, bottom_right.y), (1.0, -1.0));
+    }
+
+    #[test]
+    fn test_scissor_box()
+    {
+        // 10 points in from the top left, on a 2x display 600 pixels high
+        assert_eq!(
+            scissor_box([10.0, 10.0, 110.0, 60.0], [0.0, 0.0], [2.0, 2.0], 600.0),
+            Some([20, 480, 200, 100])
+        );
+        assert_eq!(
+            scissor_box([10.0, 10.0, 10.0, 60.0], [0.0, 0.0], [1.0, 1.0], 600.0),
+            None
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    any::{Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+    fmt,
+};
+
+// an entity is an index into the world plus the generation of that slot, so
+// a handle kept around after a despawn stops matching once the slot is reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity
+{
+    index: u32,
+    generation: u32,
+}
+
+impl Entity
+{
+    pub fn index(&self) -> u32
+    {
+        self.index
+    }
+}
+
+// storage for one component type, indexed by entity index.
+#[derive(Debug)]
+pub struct Components<T>
+{
+    slots: Vec<Option<(Entity, T)>>,
+}
+
+impl<T> Components<T>
+{
+    fn new() -> Self
+    {
+        Self { slots: vec![] }
+    }
+
+    fn insert(
+        &mut self,
+        entity: Entity,
+        component: T,
+    )
+    {
+        let index = entity.index as usize;
+        if self.slots.len() <= index
+        {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index] = Some((entity, component));
+    }
+
+    fn remove(
+        &mut self,
+        entity: Entity,
+    ) -> Option<T>
+    {
+        let slot = self.slots.get_mut(entity.index as usize)?;
+        match slot
+        {
+            Some((owner, _)) if *owner == entity => slot.take().map(|(_, component)| component),
+            _ => None,
+        }
+    }
+
+    pub fn get(
+        &self,
+        entity: Entity,
+    ) -> Option<&T>
+    {
+        match self.slots.get(entity.index as usize)
+        {
+            Some(Some((owner, component))) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(
+        &mut self,
+        entity: Entity,
+    ) -> Option<&mut T>
+    {
+        match self.slots.get_mut(entity.index as usize)
+        {
+            Some(Some((owner, component))) if *owner == entity => Some(component),
+            _ => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)>
+    {
+        self.slots.iter().filter_map(|slot| {
+            slot.as_ref()
+                .map(|(entity, component)| (*entity, component))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)>
+    {
+        self.slots.iter_mut().filter_map(|slot| {
+            slot.as_mut()
+                .map(|(entity, component)| (*entity, component))
+        })
+    }
+}
+
+// lets the world clear an entity out of every storage without knowing the
+// component types.
+trait Storage
+{
+    fn remove_entity(
+        &self,
+        entity: Entity,
+    );
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: 'static> Storage for RefCell<Components<T>>
+{
+    fn remove_entity(
+        &self,
+        entity: Entity,
+    )
+    {
+        self.borrow_mut().remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any
+    {
+        self
+    }
+}
+
+// every component type and resource sits behind its own RefCell, so systems
+// can hold several storages at once (say transforms mutably while reading
+// bodies) and only borrowing the same type twice mutably panics.
+#[derive(Default)]
+pub struct World
+{
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    components: HashMap<TypeId, Box<dyn Storage>>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl World
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity
+    {
+        if let Some(index) = self.free.pop()
+        {
+            self.alive[index as usize] = true;
+            return Entity {
+                index,
+                generation: self.generations[index as usize],
+            };
+        }
+        let index = self.generations.len() as u32;
+        self.generations.push(0);
+        self.alive.push(true);
+        Entity {
+            index,
+            generation: 0,
+        }
+    }
+
+    pub fn despawn(
+        &mut self,
+        entity: Entity,
+    ) -> bool
+    {
+        if !self.is_alive(entity)
+        {
+            return false;
+        }
+        for storage in self.components.values()
+        {
+            storage.remove_entity(entity);
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] += 1;
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn is_alive(
+        &self,
+        entity: Entity,
+    ) -> bool
+    {
+        let index = entity.index as usize;
+        index < self.alive.len()
+            && self.alive[index]
+            && self.generations[index] == entity.generation
+    }
+
+    // makes sure a storage exists for T, components() panics on unknown types
+    pub fn register<T: 'static>(&mut self)
+    {
+        self.components
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(Components::<T>::new())));
+    }
+
+    pub fn insert<T: 'static>(
+        &mut self,
+        entity: Entity,
+        component: T,
+    )
+    {
+        assert!(
+            self.is_alive(entity),
+            "inserting a component on a dead entity"
+        );
+        self.register::<T>();
+        self.components_mut::<T>().insert(entity, component);
+    }
+
+    pub fn remove<T: 'static>(
+        &mut self,
+        entity: Entity,
+    ) -> Option<T>
+    {
+        self.storage::<T>()?.borrow_mut().remove(entity)
+    }
+
+    pub fn components<T: 'static>(&self) -> Ref<'_, Components<T>>
+    {
+        self.storage::<T>()
+            .unwrap_or_else(|| panic!("{} is not registered", std::any::type_name::<T>()))
+            .borrow()
+    }
+
+    pub fn components_mut<T: 'static>(&self) -> RefMut<'_, Components<T>>
+    {
+        self.storage::<T>()
+            .unwrap_or_else(|| panic!("{} is not registered", std::any::type_name::<T>()))
+            .borrow_mut()
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&RefCell<Components<T>>>
+    {
+        self.components
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    // resources are singletons that live next to the entities, such as the
+    // physics world.
+    pub fn insert_resource<R: 'static>(
+        &mut self,
+        resource: R,
+    )
+    {
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(RefCell::new(resource)));
+    }
+
+    pub fn resource<R: 'static>(&self) -> Ref<'_, R>
+    {
+        self.resource_cell::<R>().borrow()
+    }
+
+    pub fn resource_mut<R: 'static>(&self) -> RefMut<'_, R>
+    {
+        self.resource_cell::<R>().borrow_mut()
+    }
+
+    fn resource_cell<R: 'static>(&self) -> &RefCell<R>
+    {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_ref())
+            .unwrap_or_else(|| panic!("missing resource {}", std::any::type_name::<R>()))
+    }
+}
+
+impl fmt::Debug for World
+{
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result
+    {
+        f.debug_struct("World")
+            .field("entities", &(self.alive.len() - self.free.len()))
+            .field("component_types", &self.components.len())
+            .field("resources", &self.resources.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32);
+
+    #[test]
+    fn test_insert_and_get()
+    {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.insert(a, Position(1.0));
+        world.insert(b, Position(2.0));
+        world.insert(b, Velocity(3.0));
+
+        assert_eq!(world.components::<Position>().get(a), Some(&Position(1.0)));
+        assert_eq!(world.components::<Velocity>().get(a), None);
+        assert_eq!(world.remove::<Velocity>(b), Some(Velocity(3.0)));
+        assert_eq!(world.components::<Velocity>().get(b), None);
+    }
+
+    #[test]
+    fn test_despawn_invalidates_handle()
+    {
+        let mut world = World::new();
+        let a = world.spawn();
+        world.insert(a, Position(1.0));
+        assert!(world.despawn(a));
+        assert!(!world.despawn(a));
+
+        // the slot is reused but the old handle must not see the new entity
+        let b = world.spawn();
+        world.insert(b, Position(2.0));
+        assert!(!world.is_alive(a));
+        assert_eq!(world.components::<Position>().get(a), None);
+        assert_eq!(world.components::<Position>().get(b), Some(&Position(2.0)));
+    }
+
+    #[test]
+    fn test_systems_borrow_several_storages()
+    {
+        let mut world = World::new();
+        for i in 0..4
+        {
+            let entity = world.spawn();
+            world.insert(entity, Position(0.0));
+            if i % 2 == 0
+            {
+                world.insert(entity, Velocity(i as f32));
+            }
+        }
+
+        let velocities = world.components::<Velocity>();
+        let mut positions = world.components_mut::<Position>();
+        for (entity, velocity) in velocities.iter()
+        {
+            positions.get_mut(entity).unwrap().0 += velocity.0;
+        }
+
+        let moved: Vec<f32> = positions.iter().map(|(_, position)| position.0).collect();
+        assert_eq!(moved, vec![0.0, 0.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn test_resources()
+    {
+        let mut world = World::new();
+        world.insert_resource(Velocity(1.0));
+        world.resource_mut::<Velocity>().0 += 1.0;
+        assert_eq!(*world.resource::<Velocity>(), Velocity(2.0));
+    }
+}
@@ -1,147 +1,453 @@
-use std::{cell::RefCell, rc::Rc};
+use std::collections::HashMap;
 
-use glm::vec3;
-use winit::event::VirtualKeyCode;
+use glm::{vec3, Vec3};
 
-use crate::{colliding_renderer::CubeObject, collision};
+use crate::{
+    components::{
+        Collider, Light, Name, PlayerControlled, PreviousTransform, Renderable, RigidBody,
+        Transform,
+    },
+    ecs::{Entity, World},
+    game_loop::Time,
+    input::{Input, PUSH_X, PUSH_Y, PUSH_Z},
+    physics::{self, PhysicsWorld},
+    scene::{BodyDesc, ColliderDesc, EntityDesc, MaterialDesc, Scene, TransformDesc},
+};
 
 #[derive(Debug)]
 pub struct CubeGameState
 {
-    pub cubes: Vec<Rc<RefCell<CubeObject>>>,
+    pub world: World,
 }
 
 impl CubeGameState
 {
-    pub fn new() -> Self
-    {
-        let mut cubes = vec![];
-        let cube = CubeObject {
-            id: 0,
-            position: vec3(2.5, -3.0, -1.0),
-            is_colliding: false,
-            colliding_objects: vec![],
-            normal_vector: vec3(0.0, 0.0, 0.0),
-            force: vec3(0.0, 0.0, 0.0),
-            velocity: vec3(0.0, 0.0, 0.0),
-            mass: 0.5,
-        };
-        cubes.push(Rc::new(RefCell::new(cube)));
+    pub fn from_scene(scene: &Scene) -> Self
+    {
+        let mut world = World::new();
+        world.register::<Transform>();
+        world.register::<PreviousTransform>();
+        world.register::<RigidBody>();
+        world.register::<Collider>();
+        world.register::<Renderable>();
+        world.register::<PlayerControlled>();
+        world.register::<Light>();
+        world.register::<Name>();
+        world.insert_resource(PhysicsWorld::new());
+        world.insert_resource(Time::default());
+        world.insert_resource(Impacts::default());
 
-        for x in 0..2
+        for entity in &scene.entities
         {
-            for y in 0..2
-            {
-                for z in 0..2
-                {
-                    let cube = CubeObject {
-                        id: 0,
-                        position: vec3(1.1 * x as f32, 1.1 * y as f32, 1.1 * z as f32),
-                        is_colliding: false,
-                        colliding_objects: vec![],
-                        normal_vector: vec3(0.0, 0.0, 0.0),
-                        force: vec3(0.0, 0.0, 0.0),
-                        velocity: vec3(0.0, 0.0, 0.0),
-                        mass: 0.5,
-                    };
-                    cubes.push(Rc::new(RefCell::new(cube)));
-                }
-            }
+            spawn_entity(&mut world, entity);
+        }
+        for light in &scene.lights
+        {
+            let entity = world.spawn();
+            world.insert(entity, *light);
         }
 
-        Self { cubes }
+        Self { world }
     }
 
-    pub fn handle_keyboard_input(
+    // one frame: the input is applied, then as many fixed steps run as the
+    // frame time makes due. returns how many that was. the game loop and
+    // replays both go through here, so a replay steps exactly like the run it
+    // was recorded from.
+    pub fn step_frame(
         &mut self,
-        now_keys: &[bool; 255],
-    )
+        frame_time: f32,
+        input: PlayerInput,
+    ) -> u32
     {
-        let cube = self.cubes.get_mut(0).unwrap();
-        if let Some(cube) = Rc::get_mut(cube)
+        let steps = self.world.resource_mut::<Time>().advance(frame_time);
+        self.world.resource_mut::<Impacts>().0.clear();
+        input_system(&self.world, input);
+        for _ in 0..steps
         {
-            if now_keys[VirtualKeyCode::I as usize]
-            {
-                cube.borrow_mut().force = glm::vec3(0.0, 10000.0, 0.0);
-            }
-            else if now_keys[VirtualKeyCode::K as usize]
-            {
-                cube.borrow_mut().force = glm::vec3(0.0, -10000.0, 0.0);
-            }
-            else if now_keys[VirtualKeyCode::J as usize]
-            {
-                cube.borrow_mut().force = glm::vec3(-10000.0, 0.0, 0.0);
-            }
-            else if now_keys[VirtualKeyCode::L as usize]
-            {
-                cube.borrow_mut().force = glm::vec3(10000.0, 0.0, 0.0);
-            }
-            else if now_keys[VirtualKeyCode::U as usize]
-            {
-                cube.borrow_mut().force = glm::vec3(0.0, 0.0, 10000.0);
-            }
-            else if now_keys[VirtualKeyCode::O as usize]
+            self.fixed_update();
+        }
+        steps
+    }
+
+    // runs once per fixed step, Time::advance decides how many that is
+    pub fn fixed_update(&mut self)
+    {
+        physics_system(&self.world);
+    }
+
+    // the world as it is now, written back out as a scene. the camera and
+    // terrain aren't part of the world and are taken from base.
+    pub fn to_scene(
+        &self,
+        base: &Scene,
+    ) -> Scene
+    {
+        let transforms = self.world.components::<Transform>();
+        let renderables = self.world.components::<Renderable>();
+        let names = self.world.components::<Name>();
+        let bodies = self.world.components::<RigidBody>();
+        let players = self.world.components::<PlayerControlled>();
+        let physics = self.world.resource::<PhysicsWorld>();
+
+        let entities = renderables
+            .iter()
+            .filter_map(|(entity, renderable)| {
+                let transform = transforms.get(entity)?;
+                let body = bodies.get(entity).map(|body| physics.body(body.handle));
+                Some(EntityDesc {
+                    name: names.get(entity).map(|name| name.0.clone()),
+                    transform: TransformDesc {
+                        position: transform.position,
+                        rotation: transform.orientation,
+                        scale: transform.scale,
+                    },
+                    mesh: renderable.mesh.clone(),
+                    material: MaterialDesc {
+                        color: renderable.color,
+                        colliding_color: renderable.colliding_color,
+                        texture: renderable.texture.clone(),
+                    },
+                    collider: body.map(|body| ColliderDesc::Box {
+                        half_extents: body.half_extents,
+                    }),
+                    body: body.map(|body| BodyDesc {
+                        mass: body.mass,
+                        restitution: body.restitution,
+                        friction: body.friction,
+                    }),
+                    player: players.get(entity).is_some(),
+                })
+            })
+            .collect();
+
+        Scene {
+            camera: base.camera.clone(),
+            terrain: base.terrain.clone(),
+            lights: self
+                .world
+                .components::<Light>()
+                .iter()
+                .map(|(_, light)| *light)
+                .collect(),
+            entities,
+        }
+    }
+}
+
+pub fn spawn_entity(
+    world: &mut World,
+    desc: &EntityDesc,
+) -> Entity
+{
+    let transform = Transform {
+        position: desc.transform.position,
+        orientation: desc.transform.rotation,
+        scale: desc.transform.scale,
+    };
+
+    let entity = world.spawn();
+    world.insert(entity, transform);
+    world.insert(entity, PreviousTransform(transform));
+    world.insert(
+        entity,
+        Renderable {
+            mesh: desc.mesh.clone(),
+            color: desc.material.color,
+            colliding_color: desc.material.colliding_color,
+            texture: desc.material.texture.clone(),
+        },
+    );
+    if let Some(name) = &desc.name
+    {
+        world.insert(entity, Name(name.clone()));
+    }
+
+    if let Some(ColliderDesc::Box { half_extents }) = desc.collider
+    {
+        // a collider without a body still needs something in the physics
+        // world to hit, it becomes a static box.
+        let body_desc = desc.body.clone().unwrap_or(BodyDesc {
+            mass: 0.0,
+            ..BodyDesc::default()
+        });
+        let mut body =
+            physics::RigidBody::new_box(transform.position, half_extents, body_desc.mass);
+        body.orientation = transform.orientation;
+        body.restitution = body_desc.restitution;
+        body.friction = body_desc.friction;
+        let handle = world.resource_mut::<PhysicsWorld>().add_body(body);
+        world.insert(entity, RigidBody { handle });
+        world.insert(entity, Collider::new());
+    }
+
+    if desc.player
+    {
+        world.insert(entity, PlayerControlled);
+    }
+    entity
+}
+
+// what the simulation reads from the player each frame, kept apart from the
+// devices so a replay can feed it back in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerInput
+{
+    // each axis between -1 and 1
+    pub push: Vec3,
+}
+
+impl PlayerInput
+{
+    pub fn none() -> Self
+    {
+        Self {
+            push: vec3(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn from_input(input: &Input) -> Self
+    {
+        Self {
+            push: vec3(input.axis(PUSH_X), input.axis(PUSH_Y), input.axis(PUSH_Z)),
+        }
+    }
+}
+
+// pushes the players along the push axes
+pub fn input_system(
+    world: &World,
+    input: PlayerInput,
+)
+{
+    let force = input.push * 10000.0;
+
+    let players = world.components::<PlayerControlled>();
+    let bodies = world.components::<RigidBody>();
+    let mut physics = world.resource_mut::<PhysicsWorld>();
+    for (entity, _) in players.iter()
+    {
+        if let Some(body) = bodies.get(entity)
+        {
+            physics.body_mut(body.handle).force = force;
+        }
+    }
+}
+
+// two entities that started touching during a fixed step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Impact
+{
+    pub entities: (Entity, Entity),
+    // the middle of the contact points
+    pub position: Vec3,
+    // how fast they were closing along the contact normal, units per second
+    pub speed: f32,
+}
+
+// the impacts of every fixed step in the current frame, step_frame clears them
+#[derive(Debug, Default)]
+pub struct Impacts(pub Vec<Impact>);
+
+// advances the physics world by one fixed step, then copies poses and
+// contacts back onto the entities. contacts between entities that weren't
+// touching the step before are added to Impacts.
+pub fn physics_system(world: &World)
+{
+    let mut physics = world.resource_mut::<PhysicsWorld>();
+    physics.time_step = world.resource::<Time>().fixed_delta;
+    physics.step_fixed();
+
+    let bodies = world.components::<RigidBody>();
+    let mut transforms = world.components_mut::<Transform>();
+    let mut previous_transforms = world.components_mut::<PreviousTransform>();
+    let mut owners = HashMap::new();
+    for (entity, body) in bodies.iter()
+    {
+        owners.insert(body.handle, entity);
+        if let Some(transform) = transforms.get_mut(entity)
+        {
+            if let Some(previous) = previous_transforms.get_mut(entity)
             {
-                cube.borrow_mut().force = glm::vec3(0.0, 0.0, -10000.0);
+                previous.0 = *transform;
             }
-            else
+            let body = physics.body(body.handle);
+            transform.position = body.position;
+            transform.orientation = body.orientation;
+        }
+    }
+
+    let mut colliders = world.components_mut::<Collider>();
+    let mut impacts = world.resource_mut::<Impacts>();
+    for contact in physics.contacts()
+    {
+        if let (Some(&a), Some(&b)) = (owners.get(&contact.body_a), owners.get(&contact.body_b))
+        {
+            // colliding_with still holds the last step's contacts here
+            let touching = colliders
+                .get(a)
+                .is_some_and(|collider| collider.colliding_with.contains(&b));
+            if !touching
             {
-                cube.borrow_mut().force = glm::vec3(0.0, 0.0, 0.0);
+                let points = &contact.points;
+                let sum = points
+                    .iter()
+                    .fold(vec3(0.0, 0.0, 0.0), |sum, point| sum + point.position);
+                impacts.0.push(Impact {
+                    entities: (a, b),
+                    position: sum / points.len().max(1) as f32,
+                    speed: contact.approach_speed,
+                });
             }
         }
     }
 
-    pub fn integrate(
-        &mut self,
-        delta_time: f32,
-    )
+    for (_, collider) in colliders.iter_mut()
+    {
+        collider.is_colliding = false;
+        collider.colliding_with.clear();
+    }
+
+    let mut touch = |entity: Entity, other: Entity, normal: Vec3| {
+        if let Some(collider) = colliders.get_mut(entity)
+        {
+            collider.is_colliding = true;
+            collider.normal = normal;
+            collider.colliding_with.push(other);
+        }
+    };
+    for contact in physics.contacts()
     {
-        calc_colliding(&mut self.cubes);
-        for cube in self.cubes.as_slice()
+        if let (Some(&a), Some(&b)) = (owners.get(&contact.body_a), owners.get(&contact.body_b))
         {
-            cube.borrow_mut().integrate(delta_time);
+            touch(a, b, -contact.normal);
+            touch(b, a, contact.normal);
         }
     }
 }
 
-fn calc_colliding(cubes: &mut Vec<Rc<RefCell<CubeObject>>>)
+#[cfg(test)]
+mod test
 {
-    let cube_len = cubes.len();
-    for i in 0..cube_len
+    use glm::vec3;
+
+    use super::{physics_system, spawn_entity, CubeGameState, Impacts};
+    use crate::{
+        components::{Collider, Transform},
+        ecs::World,
+        physics,
+        scene::{BodyDesc, ColliderDesc, EntityDesc, Scene, TransformDesc, DEFAULT_SCENE},
+    };
+
+    #[test]
+    fn test_resting_cube_reports_floor_collision()
     {
-        let main_cube = &mut cubes[i];
-        main_cube.borrow_mut().colliding_objects = vec![];
-        let main_verts = main_cube.borrow().get_verts();
+        let mut world = CubeGameState::from_scene(&Scene::load(DEFAULT_SCENE).unwrap()).world;
+        let floor = {
+            let colliders = world.components::<Collider>();
+            colliders.iter().last().unwrap().0
+        };
+        let cube = spawn_entity(
+            &mut world,
+            &EntityDesc {
+                transform: TransformDesc {
+                    position: vec3(-5.0, -3.55, 5.0),
+                    ..TransformDesc::default()
+                },
+                collider: Some(ColliderDesc::Box {
+                    half_extents: vec3(0.5, 0.5, 0.5),
+                }),
+                body: Some(BodyDesc::default()),
+                ..EntityDesc::default()
+            },
+        );
 
-        let mut is_colliding = false;
-        let mut normal_vector = None;
-        for j in 0..cube_len
+        for _ in 0..30
         {
-            if i == j
-            {
-                continue;
-            }
-            let other_cube = &cubes[j].clone();
-            let other_verts = other_cube.borrow().get_verts();
-            (is_colliding, normal_vector) =
-                collision::test_collision_3d(&main_verts, 3, &other_verts, 3);
+            physics_system(&world);
+        }
 
-            if is_colliding
-            {
-                if let Some(cube) = Rc::get_mut(&mut cubes[i])
-                {
-                    let mut borrowed = cube.borrow_mut();
-                    borrowed.colliding_objects.push(other_cube.to_owned());
-                    if let Some(normal) = normal_vector
-                    {
-                        borrowed.normal_vector = normal;
-                    }
-                }
-                break;
-            }
+        let colliders = world.components::<Collider>();
+        assert!(colliders.get(cube).unwrap().is_colliding);
+        assert!(colliders.get(cube).unwrap().colliding_with.contains(&floor));
+        assert!(colliders.get(floor).unwrap().colliding_with.contains(&cube));
+        assert!(colliders.get(cube).unwrap().normal.y > 0.9);
+
+        let transforms = world.components::<Transform>();
+        assert!((transforms.get(cube).unwrap().position.y + 3.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_landing_is_one_impact()
+    {
+        let mut world = CubeGameState::from_scene(&Scene::load(DEFAULT_SCENE).unwrap()).world;
+        // 2 units above where it rests on the floor
+        let cube = spawn_entity(
+            &mut world,
+            &EntityDesc {
+                transform: TransformDesc {
+                    position: vec3(-5.0, -1.5, 5.0),
+                    ..TransformDesc::default()
+                },
+                collider: Some(ColliderDesc::Box {
+                    half_extents: vec3(0.5, 0.5, 0.5),
+                }),
+                body: Some(BodyDesc::default()),
+                ..EntityDesc::default()
+            },
+        );
+        let cube_impacts = |world: &World| {
+            let impacts = world.resource::<Impacts>();
+            let impacts = impacts.0.iter();
+            impacts
+                .filter(|impact| impact.entities.0 == cube || impact.entities.1 == cube)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        for _ in 0..120
+        {
+            physics_system(&world);
         }
+        let impacts = cube_impacts(&world);
+        let landing = impacts.first().unwrap();
+        let fall_speed = (2.0 * -physics::DEFAULT_GRAVITY.y * 2.0).sqrt();
+        assert!(
+            (landing.speed - fall_speed).abs() < fall_speed * 0.1,
+            "{}",
+            landing.speed
+        );
+        assert!((landing.position.y + 4.0).abs() < 0.1);
 
-        let main_cube = &mut cubes[i];
-        main_cube.borrow_mut().is_colliding = is_colliding;
+        // resting on the floor doesn't keep hitting it
+        for _ in 0..60
+        {
+            physics_system(&world);
+        }
+        assert_eq!(cube_impacts(&world).len(), impacts.len());
+    }
+
+    #[test]
+    fn test_to_scene_round_trips()
+    {
+        let scene = Scene::load(DEFAULT_SCENE).unwrap();
+        let state = CubeGameState::from_scene(&scene);
+        assert_eq!(state.to_scene(&scene), scene);
+
+        // a saved scene picks up where the simulation left off
+        let mut state = CubeGameState::from_scene(&scene);
+        for _ in 0..10
+        {
+            state.fixed_update();
+        }
+        let saved = state.to_scene(&scene);
+        assert!(saved.entities[1].transform.position.y < scene.entities[1].transform.position.y);
+        let reloaded = Scene::parse(&saved.to_json_string().unwrap()).unwrap();
+        assert_eq!(reloaded.entities.len(), scene.entities.len());
+        assert!(
+            glm::length(
+                reloaded.entities[1].transform.position - saved.entities[1].transform.position
+            ) < 1e-6
+        );
     }
 }
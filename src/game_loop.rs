@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+use crate::physics::DEFAULT_TIME_STEP;
+
+// frames longer than this are cut short, otherwise a stall (window drag,
+// breakpoint) queues up more physics steps than the next frame can run and
+// the loop never catches up.
+pub const MAX_FRAME_TIME: f32 = 0.25;
+// the smallest fixed step worth running, a capped frame is at most 4096 of them
+pub const MIN_FIXED_DELTA: f32 = MAX_FRAME_TIME / 4096.0;
+
+// timing shared with every system through the world. delta is the clamped wall
+// time of the last frame, alpha is how far the clock is between the previous
+// and the current fixed step, for interpolating what gets drawn.
+#[derive(Debug, Clone)]
+pub struct Time
+{
+    pub delta: f32,
+    pub fixed_delta: f32,
+    pub elapsed: f64,
+    pub frame_count: u64,
+    pub alpha: f32,
+    accumulator: f32,
+}
+
+impl Time
+{
+    pub fn new(fixed_delta: f32) -> Self
+    {
+        // advance would never run out of steps
+        debug_assert!(
+            fixed_delta >= MIN_FIXED_DELTA,
+            "fixed_delta {} is too small",
+            fixed_delta
+        );
+        Self {
+            delta: 0.0,
+            fixed_delta,
+            elapsed: 0.0,
+            frame_count: 0,
+            alpha: 0.0,
+            accumulator: 0.0,
+        }
+    }
+
+    // feeds one frame of wall time in and returns how many fixed steps are due
+    pub fn advance(
+        &mut self,
+        frame_time: f32,
+    ) -> u32
+    {
+        self.delta = frame_time.clamp(0.0, MAX_FRAME_TIME);
+        self.elapsed += self.delta as f64;
+        self.frame_count += 1;
+
+        self.accumulator += self.delta;
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_delta
+        {
+            self.accumulator -= self.fixed_delta;
+            steps += 1;
+        }
+        self.alpha = self.accumulator / self.fixed_delta;
+        steps
+    }
+}
+
+impl Default for Time
+{
+    fn default() -> Self
+    {
+        Self::new(DEFAULT_TIME_STEP)
+    }
+}
+
+// monotonic frame clock, ticked once per frame rather than once per event.
+#[derive(Debug)]
+pub struct Clock
+{
+    last: Instant,
+}
+
+impl Clock
+{
+    pub fn new() -> Self
+    {
+        Self {
+            last: Instant::now(),
+        }
+    }
+
+    pub fn tick(&mut self) -> Duration
+    {
+        let now = Instant::now();
+        let frame_time = now - self.last;
+        self.last = now;
+        frame_time
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::{Time, MAX_FRAME_TIME};
+
+    #[test]
+    fn test_accumulates_partial_frames()
+    {
+        let mut time = Time::new(0.01);
+        assert_eq!(time.advance(0.004), 0);
+        assert_eq!(time.advance(0.004), 0);
+        assert_eq!(time.advance(0.004), 1);
+        assert!((time.alpha - 0.2).abs() < 1e-4);
+        assert_eq!(time.advance(0.025), 2);
+        assert!((time.alpha - 0.7).abs() < 1e-4);
+        assert_eq!(time.frame_count, 4);
+        assert!((time.elapsed - 0.037).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_long_frames_are_capped()
+    {
+        let mut time = Time::new(0.01);
+        let steps = time.advance(10.0);
+        assert_eq!(time.delta, MAX_FRAME_TIME);
+        assert!((24..=25).contains(&steps));
+        assert!(time.alpha < 1.0);
+    }
+}
@@ -0,0 +1,2075 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use gl::types::GLenum;
+use glm::{vec3, vec4, Mat3, Mat4, Vec3};
+use thiserror::Error;
+
+use crate::{
+    animation::{
+        AnimationClip, Channel, Interpolation, Joint, Keyframes, Skeleton, SkinWeights, Track,
+        MAX_JOINTS,
+    },
+    components::Transform,
+    json::{
+        self, read_array, read_bool, read_f32, read_floats, read_string, read_usize, Field,
+        FieldError, Json, JsonError, Object, Value,
+    },
+    mesh::{Mesh, MeshData, Submesh, STRIDE},
+    physics::Quat,
+    scene::CameraStart,
+};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+// the sampler values gltf uses are the gl enums themselves
+const REPEAT: GLenum = 10497;
+
+#[derive(Debug, Error)]
+pub enum GltfError
+{
+    #[error("can't read {path}: {source}")]
+    Io
+    {
+        path: String, source: io::Error
+    },
+    #[error("{0}")]
+    Syntax(#[from] JsonError),
+    #[error("{0}")]
+    Invalid(#[from] FieldError),
+    #[error("invalid glb: {0}")]
+    Glb(String),
+    #[error("there is no skinned mesh")]
+    NoSkinnedMesh,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRef
+{
+    pub texture: usize,
+    pub tex_coord: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode
+{
+    Opaque,
+    Mask,
+    Blend,
+}
+
+// metallic roughness parameters, factors multiply the texture when there is one
+#[derive(Debug, Clone, PartialEq)]
+pub struct PbrMaterial
+{
+    pub name: Option<String>,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub occlusion_texture: Option<TextureRef>,
+    pub emissive_texture: Option<TextureRef>,
+    pub emissive_factor: Vec3,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler
+{
+    pub mag_filter: Option<GLenum>,
+    pub min_filter: Option<GLenum>,
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+}
+
+impl Default for Sampler
+{
+    fn default() -> Self
+    {
+        Self {
+            mag_filter: None,
+            min_filter: None,
+            wrap_s: REPEAT,
+            wrap_t: REPEAT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfTexture
+{
+    // an index into images, textures can leave it to extensions
+    pub source: Option<usize>,
+    pub sampler: Sampler,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageSource
+{
+    Path(PathBuf),
+    // data uris and images stored in a buffer view
+    Embedded
+    {
+        mime_type: Option<String>,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfImage
+{
+    pub name: Option<String>,
+    pub source: ImageSource,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfPrimitive
+{
+    pub data: MeshData,
+    pub material: Option<usize>,
+    // JOINTS_0 and WEIGHTS_0, indexing the joints of the skin on the node
+    pub skin: Option<SkinWeights>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfMesh
+{
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection
+{
+    // radians, a missing aspect ratio means the viewport's, a missing zfar an
+    // infinite projection
+    Perspective
+    {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic
+    {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfCamera
+{
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfNode
+{
+    pub name: Option<String>,
+    // relative to the parent
+    pub transform: Transform,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfSkin
+{
+    pub name: Option<String>,
+    // node indices, a vertex's joint index picks from this list
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+    pub skeleton: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfScene
+{
+    pub name: Option<String>,
+    pub nodes: Vec<usize>,
+}
+
+// everything is kept in the file's order so the indices gltf uses to refer
+// between objects index these vectors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GltfDocument
+{
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub textures: Vec<GltfTexture>,
+    pub images: Vec<GltfImage>,
+    pub cameras: Vec<GltfCamera>,
+    pub nodes: Vec<GltfNode>,
+    pub scenes: Vec<GltfScene>,
+    pub scene: Option<usize>,
+    pub skins: Vec<GltfSkin>,
+    // channels target node indices, see clip
+    pub animations: Vec<AnimationClip>,
+}
+
+impl GltfDocument
+{
+    // .glb files are told apart from .gltf by their magic, not the extension
+    pub fn load(path: &str) -> Result<Self, GltfError>
+    {
+        let bytes = fs::read(path).map_err(|source| GltfError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        let base = Path::new(path).parent().unwrap_or(Path::new("."));
+        if bytes.starts_with(GLB_MAGIC)
+        {
+            Self::parse_glb(&bytes, base)
+        }
+        else
+        {
+            let source = String::from_utf8(bytes).map_err(|error| GltfError::Io {
+                path: path.to_string(),
+                source: io::Error::new(io::ErrorKind::InvalidData, error),
+            })?;
+            Self::parse(&source, base)
+        }
+    }
+
+    // base is the directory relative uris are resolved against
+    pub fn parse(
+        source: &str,
+        base: &Path,
+    ) -> Result<Self, GltfError>
+    {
+        Self::from_json(&json::parse(source)?, None, base)
+    }
+
+    pub fn parse_glb(
+        bytes: &[u8],
+        base: &Path,
+    ) -> Result<Self, GltfError>
+    {
+        let word = |offset: usize| {
+            bytes
+                .get(offset..offset + 4)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        };
+        if !bytes.starts_with(GLB_MAGIC)
+        {
+            return Err(GltfError::Glb("missing glTF magic".to_string()));
+        }
+        if word(4) != Some(2)
+        {
+            return Err(GltfError::Glb("only version 2 is supported".to_string()));
+        }
+        let length = word(8).ok_or_else(|| GltfError::Glb("truncated header".to_string()))?;
+        if length as usize > bytes.len()
+        {
+            return Err(GltfError::Glb(format!(
+                "header says {} bytes, file has {}",
+                length,
+                bytes.len()
+            )));
+        }
+
+        let mut chunks = vec![];
+        let mut offset = 12;
+        while offset < length as usize
+        {
+            let (Some(chunk_length), Some(chunk_type)) = (word(offset), word(offset + 4))
+            else
+            {
+                return Err(GltfError::Glb("truncated chunk header".to_string()));
+            };
+            let start = offset + 8;
+            let data = bytes
+                .get(start..start + chunk_length as usize)
+                .ok_or_else(|| GltfError::Glb("truncated chunk".to_string()))?;
+            chunks.push((chunk_type, data));
+            offset = start + chunk_length as usize;
+        }
+
+        let Some(&(CHUNK_JSON, json)) = chunks.first()
+        else
+        {
+            return Err(GltfError::Glb("the first chunk must be json".to_string()));
+        };
+        let json = std::str::from_utf8(json)
+            .map_err(|_| GltfError::Glb("json chunk is not utf-8".to_string()))?;
+        let bin = chunks
+            .get(1)
+            .filter(|(chunk_type, _)| *chunk_type == CHUNK_BIN)
+            .map(|(_, data)| *data);
+        Self::from_json(&json::parse(json)?, bin, base)
+    }
+
+    fn from_json(
+        json: &Json,
+        bin: Option<&[u8]>,
+        base: &Path,
+    ) -> Result<Self, GltfError>
+    {
+        let root = Object::any_keys(json, "gltf")?;
+        let asset = object(root.required("asset")?)?;
+        let version = asset.required("version")?;
+        if !read_string(version.clone())?.starts_with("2.")
+        {
+            return Err(version.invalid("only glTF 2.x is supported").into());
+        }
+
+        let buffers = list(&root, "buffers", |field| read_buffer(field, bin, base))?;
+        let views = list(&root, "bufferViews", |field| read_view(field, &buffers))?;
+        let accessors = list(&root, "accessors", |field| {
+            read_accessor(field, &views, &buffers)
+        })?;
+        let data = Data {
+            buffers,
+            views,
+            accessors,
+            counts: Counts {
+                materials: count(&root, "materials"),
+                textures: count(&root, "textures"),
+                images: count(&root, "images"),
+                meshes: count(&root, "meshes"),
+                cameras: count(&root, "cameras"),
+                nodes: count(&root, "nodes"),
+                samplers: count(&root, "samplers"),
+                skins: count(&root, "skins"),
+            },
+        };
+
+        let samplers = list(&root, "samplers", read_sampler)?;
+        let images = list(&root, "images", |field| data.read_image(field, base))?;
+        let textures = list(&root, "textures", |field| {
+            let texture = object(field)?;
+            Ok(GltfTexture {
+                source: optional_index(&texture, "source", data.counts.images, "image")?,
+                sampler: optional_index(&texture, "sampler", data.counts.samplers, "sampler")?
+                    .map_or_else(Sampler::default, |sampler| samplers[sampler].clone()),
+            })
+        })?;
+        let materials = list(&root, "materials", |field| data.read_material(field))?;
+        let meshes = list(&root, "meshes", |field| data.read_mesh(field))?;
+        let cameras = list(&root, "cameras", read_camera)?;
+        let nodes = data.read_nodes(&root)?;
+        let skins = list(&root, "skins", |field| data.read_skin(field))?;
+        // the skinning shader indexes the skin's joint matrices with these,
+        // so every one has to be in the skin
+        for (index, node) in nodes.iter().enumerate()
+        {
+            let (Some(mesh), Some(skin)) = (node.mesh, node.skin)
+            else
+            {
+                continue;
+            };
+            let count = skins[skin].joints.len();
+            let highest = meshes[mesh]
+                .primitives
+                .iter()
+                .filter_map(|primitive| primitive.skin.as_ref())
+                .flat_map(|weights| weights.joints.iter().flatten())
+                .fold(0.0, |highest: f32, joint| highest.max(*joint));
+            if highest as usize >= count
+            {
+                return Err(root
+                    .required("nodes")?
+                    .invalid(format!(
+                        "node {} uses joint {} of skin {}, which has {}",
+                        index, highest, skin, count
+                    ))
+                    .into());
+            }
+        }
+        let animations = list(&root, "animations", |field| data.read_animation(field))?;
+        let scenes = list(&root, "scenes", |field| {
+            let scene = object(field)?;
+            Ok(GltfScene {
+                name: optional_string(&scene, "name")?,
+                nodes: match scene.optional("nodes")
+                {
+                    Some(nodes) =>
+                    {
+                        read_array(nodes, |node| index(node, data.counts.nodes, "node"))?
+                    }
+                    None => vec![],
+                },
+            })
+        })?;
+        let scene = optional_index(&root, "scene", scenes.len(), "scene")?;
+
+        Ok(Self {
+            meshes,
+            materials,
+            textures,
+            images,
+            cameras,
+            nodes,
+            scenes,
+            scene,
+            skins,
+            animations,
+        })
+    }
+
+    // every node's transform relative to the scene, indexed like nodes
+    pub fn world_transforms(&self) -> Vec<Mat4>
+    {
+        (0..self.nodes.len())
+            .map(|node| {
+                let mut matrix = self.nodes[node].transform.matrix();
+                let mut parent = self.nodes[node].parent;
+                while let Some(index) = parent
+                {
+                    matrix = self.nodes[index].transform.matrix() * matrix;
+                    parent = self.nodes[index].parent;
+                }
+                matrix
+            })
+            .collect()
+    }
+
+    // each node with a mesh and where it sits in the scene
+    pub fn mesh_nodes(&self) -> Vec<(usize, Mat4)>
+    {
+        let world = self.world_transforms();
+        self.nodes
+            .iter()
+            .zip(world)
+            .filter_map(|(node, world)| Some((node.mesh?, world)))
+            .collect()
+    }
+
+    // where a perspective camera node would put the engine's camera, gltf
+    // cameras look down their local -z.
+    pub fn camera_start(
+        &self,
+        node: usize,
+    ) -> Option<CameraStart>
+    {
+        let camera = self.cameras.get(self.nodes.get(node)?.camera?)?;
+        let Projection::Perspective { yfov, .. } = camera.projection
+        else
+        {
+            return None;
+        };
+        let world = self.world_transforms()[node];
+        let forward = world * vec4(0.0, 0.0, -1.0, 0.0);
+        let forward = glm::normalize(vec3(forward.x, forward.y, forward.z));
+        Some(CameraStart {
+            position: vec3(world.c3.x, world.c3.y, world.c3.z),
+            yaw: forward.z.atan2(forward.x).to_degrees(),
+            pitch: forward.y.asin().to_degrees(),
+            fov: yfov.to_degrees(),
+        })
+    }
+
+    // the skin's joints in the skin's order. joints whose parent is not in the
+    // skin are roots, they hang from the first root's parent node.
+    pub fn skeleton(
+        &self,
+        skin: usize,
+    ) -> Skeleton
+    {
+        let skin = &self.skins[skin];
+        let joint = |node: usize| skin.joints.iter().position(|joint| *joint == node);
+        let joints: Vec<Joint> = skin
+            .joints
+            .iter()
+            .zip(&skin.inverse_bind_matrices)
+            .map(|(node, inverse_bind)| {
+                let node = &self.nodes[*node];
+                Joint {
+                    name: node.name.clone(),
+                    parent: node.parent.and_then(joint),
+                    rest: node.transform,
+                    inverse_bind: *inverse_bind,
+                }
+            })
+            .collect();
+        let root = joints
+            .iter()
+            .zip(&skin.joints)
+            .find(|(joint, _)| joint.parent.is_none())
+            .and_then(|(_, node)| self.nodes[*node].parent)
+            .map_or_else(
+                || Quat::identity().to_mat4(),
+                |parent| self.world_transforms()[parent],
+            );
+        Skeleton { joints, root }
+    }
+
+    // an animation retargeted onto a skin's joints, channels for other nodes
+    // are dropped.
+    pub fn clip(
+        &self,
+        animation: usize,
+        skin: usize,
+    ) -> AnimationClip
+    {
+        let animation = &self.animations[animation];
+        let joints = &self.skins[skin].joints;
+        AnimationClip {
+            channels: animation
+                .channels
+                .iter()
+                .filter_map(|channel| {
+                    let target = joints.iter().position(|node| *node == channel.target)?;
+                    Some(Channel {
+                        target,
+                        keyframes: channel.keyframes.clone(),
+                    })
+                })
+                .collect(),
+            ..animation.clone()
+        }
+    }
+
+    pub unsafe fn upload(&self) -> Result<GltfModel, GltfError>
+    {
+        let meshes = self
+            .meshes
+            .iter()
+            .map(|mesh| {
+                mesh.primitives
+                    .iter()
+                    .map(|primitive| match &primitive.skin
+                    {
+                        Some(skin) => Mesh::new_skinned(&primitive.data, skin),
+                        None => Mesh::new(&primitive.data),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(GltfModel { meshes })
+    }
+}
+
+// a document's geometry on the gpu, indexed like the document's meshes, with
+// one Mesh per primitive. the renderers only draw flat colors, so textures
+// stay on the cpu side.
+pub struct GltfModel
+{
+    pub meshes: Vec<Vec<Mesh>>,
+}
+
+struct View
+{
+    buffer: usize,
+    offset: usize,
+    length: usize,
+    stride: Option<usize>,
+}
+
+struct Accessor
+{
+    view: Option<usize>,
+    offset: usize,
+    component_type: u32,
+    normalized: bool,
+    count: usize,
+    components: usize,
+}
+
+// array lengths, so references can be checked before what they point at is
+// read
+struct Counts
+{
+    materials: usize,
+    textures: usize,
+    images: usize,
+    meshes: usize,
+    cameras: usize,
+    nodes: usize,
+    samplers: usize,
+    skins: usize,
+}
+
+struct Data
+{
+    buffers: Vec<Vec<u8>>,
+    views: Vec<View>,
+    accessors: Vec<Accessor>,
+    counts: Counts,
+}
+
+impl Data
+{
+    fn view_bytes(
+        &self,
+        view: usize,
+    ) -> &[u8]
+    {
+        let view = &self.views[view];
+        &self.buffers[view.buffer][view.offset..view.offset + view.length]
+    }
+
+    // each component's bytes in order, accessors without a buffer view are all
+    // zeros.
+    fn component_bytes(
+        &self,
+        accessor: &Accessor,
+    ) -> Vec<&[u8]>
+    {
+        static ZEROS: [u8; 4] = [0; 4];
+        let size = component_size(accessor.component_type);
+        let Some(view) = accessor.view
+        else
+        {
+            return vec![&ZEROS[..size]; accessor.count * accessor.components];
+        };
+        let bytes = self.view_bytes(view);
+        let stride = self.views[view]
+            .stride
+            .unwrap_or(size * accessor.components);
+        (0..accessor.count)
+            .flat_map(|element| {
+                (0..accessor.components).map(move |component| {
+                    let start = accessor.offset + element * stride + component * size;
+                    &bytes[start..start + size]
+                })
+            })
+            .collect()
+    }
+
+    // a vertex attribute as flat floats, checked against the layout the
+    // engine expects.
+    fn attribute(
+        &self,
+        field: Field,
+        components: usize,
+        count: Option<usize>,
+    ) -> Result<Vec<f32>, FieldError>
+    {
+        let accessor = index(field.clone(), self.accessors.len(), "accessor")?;
+        self.floats(field, accessor, components, count)
+    }
+
+    fn floats(
+        &self,
+        field: Field,
+        accessor: usize,
+        components: usize,
+        count: Option<usize>,
+    ) -> Result<Vec<f32>, FieldError>
+    {
+        let accessor = &self.accessors[accessor];
+        if accessor.components != components
+        {
+            return Err(field.invalid(format!(
+                "expected an accessor with {} components, found {}",
+                components, accessor.components
+            )));
+        }
+        if let Some(count) = count.filter(|count| *count != accessor.count)
+        {
+            return Err(field.invalid(format!(
+                "expected {} elements, found {}",
+                count, accessor.count
+            )));
+        }
+        Ok(self
+            .component_bytes(accessor)
+            .into_iter()
+            .map(|bytes| read_component(bytes, accessor.component_type, accessor.normalized))
+            .collect())
+    }
+
+    fn indices(
+        &self,
+        field: Field,
+        vertex_count: usize,
+    ) -> Result<Vec<i32>, FieldError>
+    {
+        let accessor = &self.accessors[index(field.clone(), self.accessors.len(), "accessor")?];
+        if accessor.components != 1 || ![5121, 5123, 5125].contains(&accessor.component_type)
+        {
+            return Err(field.invalid("indices must be unsigned scalars"));
+        }
+        self.component_bytes(accessor)
+            .into_iter()
+            .map(|bytes| {
+                let mut word = [0; 4];
+                word[..bytes.len()].copy_from_slice(bytes);
+                let index = u32::from_le_bytes(word) as usize;
+                if index >= vertex_count
+                {
+                    return Err(field.invalid(format!(
+                        "index {} is out of range for {} vertices",
+                        index, vertex_count
+                    )));
+                }
+                Ok(index as i32)
+            })
+            .collect()
+    }
+
+    fn read_image(
+        &self,
+        field: Field,
+        base: &Path,
+    ) -> Result<GltfImage, GltfError>
+    {
+        let image = object(field.clone())?;
+        let mime_type = optional_string(&image, "mimeType")?;
+        let source = match (image.optional("uri"), image.optional("bufferView"))
+        {
+            (Some(uri), _) =>
+            {
+                let text = read_string(uri.clone())?;
+                if text.starts_with("data:")
+                {
+                    let (mime_type, bytes) = decode_data_uri(uri, &text)?;
+                    ImageSource::Embedded {
+                        mime_type: Some(mime_type),
+                        bytes,
+                    }
+                }
+                else
+                {
+                    ImageSource::Path(base.join(percent_decode(&text)))
+                }
+            }
+            (None, Some(view)) => ImageSource::Embedded {
+                mime_type,
+                bytes: self
+                    .view_bytes(index(view, self.views.len(), "buffer view")?)
+                    .to_vec(),
+            },
+            (None, None) => return Err(field.invalid("expected a uri or a bufferView").into()),
+        };
+        Ok(GltfImage {
+            name: optional_string(&image, "name")?,
+            source,
+        })
+    }
+
+    fn texture_ref(
+        &self,
+        material: &Object,
+        key: &str,
+    ) -> Result<Option<TextureRef>, FieldError>
+    {
+        let Some(field) = material.optional(key)
+        else
+        {
+            return Ok(None);
+        };
+        let info = object(field)?;
+        Ok(Some(TextureRef {
+            texture: index(info.required("index")?, self.counts.textures, "texture")?,
+            tex_coord: info
+                .optional("texCoord")
+                .map(read_usize)
+                .transpose()?
+                .unwrap_or(0),
+        }))
+    }
+
+    fn read_material(
+        &self,
+        field: Field,
+    ) -> Result<PbrMaterial, GltfError>
+    {
+        let material = object(field)?;
+        let mut pbr = PbrMaterial {
+            name: optional_string(&material, "name")?,
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: self.texture_ref(&material, "normalTexture")?,
+            occlusion_texture: self.texture_ref(&material, "occlusionTexture")?,
+            emissive_texture: self.texture_ref(&material, "emissiveTexture")?,
+            emissive_factor: vec3(0.0, 0.0, 0.0),
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: optional_f32(&material, "alphaCutoff")?.unwrap_or(0.5),
+            double_sided: material
+                .optional("doubleSided")
+                .map(read_bool)
+                .transpose()?
+                .unwrap_or(false),
+        };
+        if let Some(factor) = material.optional("emissiveFactor")
+        {
+            let [r, g, b] = read_floats(factor)?;
+            pbr.emissive_factor = vec3(r, g, b);
+        }
+        if let Some(mode) = material.optional("alphaMode")
+        {
+            pbr.alpha_mode = match read_string(mode.clone())?.as_str()
+            {
+                "OPAQUE" => AlphaMode::Opaque,
+                "MASK" => AlphaMode::Mask,
+                "BLEND" => AlphaMode::Blend,
+                _ => return Err(mode.invalid("expected OPAQUE, MASK or BLEND").into()),
+            };
+        }
+        if let Some(field) = material.optional("pbrMetallicRoughness")
+        {
+            let metallic = object(field)?;
+            if let Some(factor) = metallic.optional("baseColorFactor")
+            {
+                pbr.base_color_factor = read_floats(factor)?;
+            }
+            pbr.base_color_texture = self.texture_ref(&metallic, "baseColorTexture")?;
+            pbr.metallic_factor = optional_f32(&metallic, "metallicFactor")?.unwrap_or(1.0);
+            pbr.roughness_factor = optional_f32(&metallic, "roughnessFactor")?.unwrap_or(1.0);
+            pbr.metallic_roughness_texture =
+                self.texture_ref(&metallic, "metallicRoughnessTexture")?;
+        }
+        Ok(pbr)
+    }
+
+    fn read_mesh(
+        &self,
+        field: Field,
+    ) -> Result<GltfMesh, GltfError>
+    {
+        let mesh = object(field)?;
+        Ok(GltfMesh {
+            name: optional_string(&mesh, "name")?,
+            primitives: read_array(mesh.required("primitives")?, |field| {
+                self.read_primitive(field)
+            })?,
+        })
+    }
+
+    // only POSITION, NORMAL and TEXCOORD_0 are used, missing normals are
+    // generated from the faces.
+    fn read_primitive(
+        &self,
+        field: Field,
+    ) -> Result<GltfPrimitive, GltfError>
+    {
+        let primitive = object(field)?;
+        if let Some(mode) = primitive.optional("mode")
+        {
+            if read_usize(mode.clone())? != 4
+            {
+                return Err(mode.invalid("only triangle lists are supported").into());
+            }
+        }
+        let attributes = object(primitive.required("attributes")?)?;
+        let positions = self.attribute(attributes.required("POSITION")?, 3, None)?;
+        let vertex_count = positions.len() / 3;
+        let normals = attributes
+            .optional("NORMAL")
+            .map(|field| self.attribute(field, 3, Some(vertex_count)))
+            .transpose()?;
+        let uvs = attributes
+            .optional("TEXCOORD_0")
+            .map(|field| self.attribute(field, 2, Some(vertex_count)))
+            .transpose()?;
+        let skin = match (
+            attributes.optional("JOINTS_0"),
+            attributes.optional("WEIGHTS_0"),
+        )
+        {
+            (Some(joints), Some(weights)) => Some(SkinWeights {
+                joints: quads(&self.attribute(joints, 4, Some(vertex_count))?),
+                weights: quads(&self.attribute(weights, 4, Some(vertex_count))?),
+            }),
+            (None, None) => None,
+            (Some(field), None) | (None, Some(field)) =>
+            {
+                return Err(field.invalid("JOINTS_0 and WEIGHTS_0 come together").into())
+            }
+        };
+
+        let mut vertices = Vec::with_capacity(vertex_count * STRIDE as usize);
+        for i in 0..vertex_count
+        {
+            vertices.extend_from_slice(&positions[i * 3..i * 3 + 3]);
+            match &normals
+            {
+                Some(normals) => vertices.extend_from_slice(&normals[i * 3..i * 3 + 3]),
+                None => vertices.extend_from_slice(&[0.0; 3]),
+            }
+            match &uvs
+            {
+                Some(uvs) => vertices.extend_from_slice(&uvs[i * 2..i * 2 + 2]),
+                None => vertices.extend_from_slice(&[0.0; 2]),
+            }
+        }
+
+        let indices = match primitive.optional("indices")
+        {
+            Some(field) =>
+            {
+                let indices = self.indices(field.clone(), vertex_count)?;
+                if indices.len() % 3 != 0
+                {
+                    return Err(field.invalid("index count is not a multiple of 3").into());
+                }
+                indices
+            }
+            None => (0..vertex_count as i32).collect(),
+        };
+
+        let mut data = MeshData {
+            vertices,
+            submeshes: vec![Submesh {
+                material: None,
+                first_index: 0,
+                index_count: indices.len(),
+            }],
+            indices,
+            materials: vec![],
+        };
+        if normals.is_none()
+        {
+            data.generate_normals();
+        }
+        Ok(GltfPrimitive {
+            data,
+            material: optional_index(&primitive, "material", self.counts.materials, "material")?,
+            skin,
+        })
+    }
+
+    fn read_nodes(
+        &self,
+        root: &Object,
+    ) -> Result<Vec<GltfNode>, GltfError>
+    {
+        let Some(field) = root.optional("nodes")
+        else
+        {
+            return Ok(vec![]);
+        };
+        let mut nodes: Vec<GltfNode> = read_array(field.clone(), |field| self.read_node(field))?;
+        for parent in 0..nodes.len()
+        {
+            for child in nodes[parent].children.clone()
+            {
+                if nodes[child].parent.is_some() || child == parent
+                {
+                    return Err(field
+                        .invalid(format!("node {} has more than one parent", child))
+                        .into());
+                }
+                nodes[child].parent = Some(parent);
+            }
+        }
+        // with one parent each, a cycle is a chain that never reaches a root
+        for node in 0..nodes.len()
+        {
+            let mut parent = nodes[node].parent;
+            for _ in 0..nodes.len()
+            {
+                parent = parent.and_then(|parent| nodes[parent].parent);
+            }
+            if parent.is_some()
+            {
+                return Err(field.invalid("node hierarchy has a cycle").into());
+            }
+        }
+        Ok(nodes)
+    }
+
+    // parents are filled in by read_nodes once every node is read
+    fn read_node(
+        &self,
+        field: Field,
+    ) -> Result<GltfNode, GltfError>
+    {
+        let node = object(field)?;
+        let children = match node.optional("children")
+        {
+            Some(children) =>
+            {
+                read_array(children, |field| index(field, self.counts.nodes, "node"))?
+            }
+            None => vec![],
+        };
+        let transform = match node.optional("matrix")
+        {
+            Some(matrix) => decompose(read_floats::<16>(matrix)?),
+            None => Transform {
+                position: optional_vec3(&node, "translation")?.unwrap_or(vec3(0.0, 0.0, 0.0)),
+                orientation: match node.optional("rotation")
+                {
+                    Some(rotation) =>
+                    {
+                        let [x, y, z, w] = read_floats(rotation)?;
+                        Quat { w, x, y, z }.normalize()
+                    }
+                    None => Quat::identity(),
+                },
+                scale: optional_vec3(&node, "scale")?.unwrap_or(vec3(1.0, 1.0, 1.0)),
+            },
+        };
+        Ok(GltfNode {
+            name: optional_string(&node, "name")?,
+            transform,
+            parent: None,
+            children,
+            mesh: optional_index(&node, "mesh", self.counts.meshes, "mesh")?,
+            camera: optional_index(&node, "camera", self.counts.cameras, "camera")?,
+            skin: optional_index(&node, "skin", self.counts.skins, "skin")?,
+        })
+    }
+}
+
+struct AnimationSampler
+{
+    times: Vec<f32>,
+    output: usize,
+    interpolation: Interpolation,
+}
+
+impl Data
+{
+    fn read_skin(
+        &self,
+        field: Field,
+    ) -> Result<GltfSkin, GltfError>
+    {
+        let skin = object(field)?;
+        let joints_field = skin.required("joints")?;
+        let joints = read_array(joints_field.clone(), |field| {
+            index(field, self.counts.nodes, "node")
+        })?;
+        if joints.is_empty() || joints.len() > MAX_JOINTS
+        {
+            return Err(joints_field
+                .invalid(format!("expected 1 to {} joints", MAX_JOINTS))
+                .into());
+        }
+        let inverse_bind_matrices = match skin.optional("inverseBindMatrices")
+        {
+            Some(field) => self
+                .attribute(field, 16, Some(joints.len()))?
+                .chunks_exact(16)
+                .map(matrix)
+                .collect(),
+            None => vec![Quat::identity().to_mat4(); joints.len()],
+        };
+        Ok(GltfSkin {
+            name: optional_string(&skin, "name")?,
+            joints,
+            inverse_bind_matrices,
+            skeleton: optional_index(&skin, "skeleton", self.counts.nodes, "node")?,
+        })
+    }
+
+    fn read_animation(
+        &self,
+        field: Field,
+    ) -> Result<AnimationClip, GltfError>
+    {
+        let animation = object(field)?;
+        let samplers = read_array(animation.required("samplers")?, |field| {
+            self.read_animation_sampler(field)
+        })?;
+        let channels: Vec<Channel> = read_array(animation.required("channels")?, |field| {
+            self.read_channel(field, &samplers)
+        })?
+        .into_iter()
+        .flatten()
+        .collect();
+        Ok(AnimationClip {
+            name: optional_string(&animation, "name")?,
+            duration: samplers
+                .iter()
+                .map(|sampler| sampler.times[sampler.times.len() - 1])
+                .fold(0.0, f32::max),
+            channels,
+        })
+    }
+
+    fn read_animation_sampler(
+        &self,
+        field: Field,
+    ) -> Result<AnimationSampler, GltfError>
+    {
+        let sampler = object(field)?;
+        let input = sampler.required("input")?;
+        let times = self.attribute(input.clone(), 1, None)?;
+        if times.is_empty()
+        {
+            return Err(input.invalid("an animation needs at least one key").into());
+        }
+        if times.windows(2).any(|pair| pair[1] < pair[0])
+        {
+            return Err(input.invalid("key times must not decrease").into());
+        }
+        let interpolation = match sampler.optional("interpolation")
+        {
+            Some(field) => match read_string(field.clone())?.as_str()
+            {
+                "STEP" => Interpolation::Step,
+                "LINEAR" => Interpolation::Linear,
+                "CUBICSPLINE" => Interpolation::CubicSpline,
+                _ => return Err(field.invalid("expected STEP, LINEAR or CUBICSPLINE").into()),
+            },
+            None => Interpolation::Linear,
+        };
+        Ok(AnimationSampler {
+            times,
+            output: index(
+                sampler.required("output")?,
+                self.accessors.len(),
+                "accessor",
+            )?,
+            interpolation,
+        })
+    }
+
+    // None for channels the engine doesn't animate, morph target weights or
+    // ones left to extensions.
+    fn read_channel(
+        &self,
+        field: Field,
+        samplers: &[AnimationSampler],
+    ) -> Result<Option<Channel>, GltfError>
+    {
+        let channel = object(field)?;
+        let sampler_field = channel.required("sampler")?;
+        let sampler = &samplers[index(sampler_field.clone(), samplers.len(), "sampler")?];
+        let target = object(channel.required("target")?)?;
+        let Some(node) = optional_index(&target, "node", self.counts.nodes, "node")?
+        else
+        {
+            return Ok(None);
+        };
+
+        let keys = match sampler.interpolation
+        {
+            Interpolation::CubicSpline => sampler.times.len() * 3,
+            _ => sampler.times.len(),
+        };
+        let output = |components| {
+            self.floats(
+                sampler_field.clone(),
+                sampler.output,
+                components,
+                Some(keys),
+            )
+        };
+        let path = target.required("path")?;
+        let keyframes = match read_string(path.clone())?.as_str()
+        {
+            "translation" => Keyframes::Translation(track(sampler, vec3s(&output(3)?))),
+            "scale" => Keyframes::Scale(track(sampler, vec3s(&output(3)?))),
+            "rotation" => Keyframes::Rotation(track(
+                sampler,
+                quads(&output(4)?)
+                    .into_iter()
+                    .map(|[x, y, z, w]| Quat { w, x, y, z })
+                    .collect(),
+            )),
+            "weights" => return Ok(None),
+            _ =>
+            {
+                return Err(path
+                    .invalid("expected translation, rotation, scale or weights")
+                    .into())
+            }
+        };
+        Ok(Some(Channel {
+            target: node,
+            keyframes,
+        }))
+    }
+}
+
+fn track<T>(
+    sampler: &AnimationSampler,
+    values: Vec<T>,
+) -> Track<T>
+{
+    Track {
+        times: sampler.times.clone(),
+        values,
+        interpolation: sampler.interpolation,
+    }
+}
+
+fn vec3s(values: &[f32]) -> Vec<Vec3>
+{
+    values
+        .chunks_exact(3)
+        .map(|v| vec3(v[0], v[1], v[2]))
+        .collect()
+}
+
+fn quads(values: &[f32]) -> Vec<[f32; 4]>
+{
+    values
+        .chunks_exact(4)
+        .map(|v| [v[0], v[1], v[2], v[3]])
+        .collect()
+}
+
+// column major, as gltf stores them
+fn matrix(m: &[f32]) -> Mat4
+{
+    let column = |i: usize| vec4(m[i * 4], m[i * 4 + 1], m[i * 4 + 2], m[i * 4 + 3]);
+    Mat4::new(column(0), column(1), column(2), column(3))
+}
+
+fn object(field: Field) -> Result<Object, FieldError>
+{
+    Object::any_keys(field.json, &field.path)
+}
+
+fn count(
+    root: &Object,
+    key: &str,
+) -> usize
+{
+    match root.optional(key).map(|field| &field.json.value)
+    {
+        Some(Value::Array(items)) => items.len(),
+        _ => 0,
+    }
+}
+
+// an optional top level array, missing means empty
+fn list<T>(
+    root: &Object,
+    key: &str,
+    read: impl Fn(Field) -> Result<T, GltfError>,
+) -> Result<Vec<T>, GltfError>
+{
+    match root.optional(key)
+    {
+        Some(field) => read_array(field, read),
+        None => Ok(vec![]),
+    }
+}
+
+fn index(
+    field: Field,
+    count: usize,
+    what: &str,
+) -> Result<usize, FieldError>
+{
+    let index = read_usize(field.clone())?;
+    if index >= count
+    {
+        return Err(field.invalid(format!(
+            "there is no {} {}, the file has {}",
+            what, index, count
+        )));
+    }
+    Ok(index)
+}
+
+fn optional_index(
+    object: &Object,
+    key: &str,
+    count: usize,
+    what: &str,
+) -> Result<Option<usize>, FieldError>
+{
+    object
+        .optional(key)
+        .map(|field| index(field, count, what))
+        .transpose()
+}
+
+fn optional_string(
+    object: &Object,
+    key: &str,
+) -> Result<Option<String>, FieldError>
+{
+    object.optional(key).map(read_string).transpose()
+}
+
+fn optional_f32(
+    object: &Object,
+    key: &str,
+) -> Result<Option<f32>, FieldError>
+{
+    object.optional(key).map(read_f32).transpose()
+}
+
+fn optional_vec3(
+    object: &Object,
+    key: &str,
+) -> Result<Option<Vec3>, FieldError>
+{
+    Ok(object
+        .optional(key)
+        .map(read_floats)
+        .transpose()?
+        .map(|[x, y, z]| vec3(x, y, z)))
+}
+
+fn read_buffer(
+    field: Field,
+    bin: Option<&[u8]>,
+    base: &Path,
+) -> Result<Vec<u8>, GltfError>
+{
+    let buffer = object(field.clone())?;
+    let length = read_usize(buffer.required("byteLength")?)?;
+    let bytes = match buffer.optional("uri")
+    {
+        Some(uri) =>
+        {
+            let text = read_string(uri.clone())?;
+            if text.starts_with("data:")
+            {
+                decode_data_uri(uri, &text)?.1
+            }
+            else
+            {
+                let path = base.join(percent_decode(&text));
+                fs::read(&path).map_err(|source| GltfError::Io {
+                    path: path.display().to_string(),
+                    source,
+                })?
+            }
+        }
+        None => bin
+            .ok_or_else(|| field.invalid("no uri and no glb binary chunk"))?
+            .to_vec(),
+    };
+    if bytes.len() < length
+    {
+        return Err(field
+            .invalid(format!(
+                "byteLength is {}, found {} bytes",
+                length,
+                bytes.len()
+            ))
+            .into());
+    }
+    Ok(bytes)
+}
+
+fn read_view(
+    field: Field,
+    buffers: &[Vec<u8>],
+) -> Result<View, GltfError>
+{
+    let view = object(field.clone())?;
+    let buffer = index(view.required("buffer")?, buffers.len(), "buffer")?;
+    let offset = view
+        .optional("byteOffset")
+        .map(read_usize)
+        .transpose()?
+        .unwrap_or(0);
+    let length = read_usize(view.required("byteLength")?)?;
+    if offset
+        .checked_add(length)
+        .is_none_or(|end| end > buffers[buffer].len())
+    {
+        return Err(field
+            .invalid(format!(
+                "{} bytes from {} are past the end of buffer {}",
+                length, offset, buffer
+            ))
+            .into());
+    }
+    Ok(View {
+        buffer,
+        offset,
+        length,
+        stride: view.optional("byteStride").map(read_usize).transpose()?,
+    })
+}
+
+// counts come straight from the file, so the bytes they need are worked out
+// without overflowing and checked before anything is allocated or sliced
+fn read_accessor(
+    field: Field,
+    views: &[View],
+    buffers: &[Vec<u8>],
+) -> Result<Accessor, GltfError>
+{
+    let accessor = object(field.clone())?;
+    if accessor.optional("sparse").is_some()
+    {
+        return Err(field.invalid("sparse accessors are not supported").into());
+    }
+    let type_field = accessor.required("componentType")?;
+    let component_type = read_usize(type_field.clone())? as u32;
+    if ![5120, 5121, 5122, 5123, 5125, 5126].contains(&component_type)
+    {
+        return Err(type_field.invalid("unknown component type").into());
+    }
+    let kind = accessor.required("type")?;
+    let components = match read_string(kind.clone())?.as_str()
+    {
+        "SCALAR" => 1,
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" | "MAT2" => 4,
+        "MAT3" => 9,
+        "MAT4" => 16,
+        _ => return Err(kind.invalid("unknown accessor type").into()),
+    };
+    let result = Accessor {
+        view: optional_index(&accessor, "bufferView", views.len(), "buffer view")?,
+        offset: accessor
+            .optional("byteOffset")
+            .map(read_usize)
+            .transpose()?
+            .unwrap_or(0),
+        component_type,
+        normalized: accessor
+            .optional("normalized")
+            .map(read_bool)
+            .transpose()?
+            .unwrap_or(false),
+        count: read_usize(accessor.required("count")?)?,
+        components,
+    };
+
+    let element = components * component_size(component_type);
+    match (result.view, result.count)
+    {
+        (_, 0) =>
+        {}
+        (Some(view), count) =>
+        {
+            let stride = views[view].stride.unwrap_or(element);
+            let end = stride
+                .checked_mul(count - 1)
+                .and_then(|bytes| bytes.checked_add(result.offset))
+                .and_then(|bytes| bytes.checked_add(element));
+            if end.is_none_or(|end| end > views[view].length)
+            {
+                return Err(field
+                    .invalid(format!(
+                        "{} elements don't fit in buffer view {} of {} bytes",
+                        count, view, views[view].length
+                    ))
+                    .into());
+            }
+        }
+        // all zeros, which only sparse accessors have a use for. a file
+        // never needs more of them than it has data.
+        (None, count) =>
+        {
+            let data: usize = buffers.iter().map(Vec::len).sum();
+            if count.checked_mul(element).is_none_or(|bytes| bytes > data)
+            {
+                return Err(field
+                    .invalid(format!(
+                        "{} elements without a buffer view is more than the file's {} bytes of data",
+                        count, data
+                    ))
+                    .into());
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn read_sampler(field: Field) -> Result<Sampler, GltfError>
+{
+    let sampler = object(field)?;
+    let value = |key| {
+        sampler
+            .optional(key)
+            .map(|field| read_usize(field).map(|value| value as GLenum))
+            .transpose()
+    };
+    Ok(Sampler {
+        mag_filter: value("magFilter")?,
+        min_filter: value("minFilter")?,
+        wrap_s: value("wrapS")?.unwrap_or(REPEAT),
+        wrap_t: value("wrapT")?.unwrap_or(REPEAT),
+    })
+}
+
+fn read_camera(field: Field) -> Result<GltfCamera, GltfError>
+{
+    let camera = object(field)?;
+    let kind = camera.required("type")?;
+    let projection = match read_string(kind.clone())?.as_str()
+    {
+        "perspective" =>
+        {
+            let perspective = object(camera.required("perspective")?)?;
+            Projection::Perspective {
+                yfov: read_f32(perspective.required("yfov")?)?,
+                aspect_ratio: optional_f32(&perspective, "aspectRatio")?,
+                znear: read_f32(perspective.required("znear")?)?,
+                zfar: optional_f32(&perspective, "zfar")?,
+            }
+        }
+        "orthographic" =>
+        {
+            let orthographic = object(camera.required("orthographic")?)?;
+            Projection::Orthographic {
+                xmag: read_f32(orthographic.required("xmag")?)?,
+                ymag: read_f32(orthographic.required("ymag")?)?,
+                znear: read_f32(orthographic.required("znear")?)?,
+                zfar: read_f32(orthographic.required("zfar")?)?,
+            }
+        }
+        _ => return Err(kind.invalid("expected perspective or orthographic").into()),
+    };
+    Ok(GltfCamera {
+        name: optional_string(&camera, "name")?,
+        projection,
+    })
+}
+
+fn component_size(component_type: u32) -> usize
+{
+    match component_type
+    {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        _ => 4,
+    }
+}
+
+fn read_component(
+    bytes: &[u8],
+    component_type: u32,
+    normalized: bool,
+) -> f32
+{
+    match component_type
+    {
+        5120 if normalized => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+        5120 => bytes[0] as i8 as f32,
+        5121 if normalized => bytes[0] as f32 / 255.0,
+        5121 => bytes[0] as f32,
+        5122 if normalized => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+        5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        5123 if normalized => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+        5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+        5125 => u32::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        _ => f32::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+// splits a column major matrix back into translation, rotation and scale, a
+// mirroring matrix gets a negative x scale.
+fn decompose(m: [f32; 16]) -> Transform
+{
+    let column = |i: usize| vec3(m[i * 4], m[i * 4 + 1], m[i * 4 + 2]);
+    let (mut x, y, z) = (column(0), column(1), column(2));
+    let mut scale = vec3(glm::length(x), glm::length(y), glm::length(z));
+    if glm::dot(glm::cross(x, y), z) < 0.0
+    {
+        scale.x = -scale.x;
+    }
+    x = x / scale.x;
+    Transform {
+        position: column(3),
+        orientation: Quat::from_mat3(Mat3::new(x, y / scale.y, z / scale.z)),
+        scale,
+    }
+}
+
+// only base64 data uris, which is all gltf allows
+fn decode_data_uri(
+    field: Field,
+    uri: &str,
+) -> Result<(String, Vec<u8>), FieldError>
+{
+    let (header, data) = uri["data:".len()..]
+        .split_once(',')
+        .ok_or_else(|| field.invalid("data uri has no data"))?;
+    let mime_type = header
+        .strip_suffix(";base64")
+        .ok_or_else(|| field.invalid("only base64 data uris are supported"))?;
+    let bytes = decode_base64(data).ok_or_else(|| field.invalid("invalid base64"))?;
+    Ok((mime_type.to_string(), bytes))
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>>
+{
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut bit_count = 0;
+    for c in text.bytes().take_while(|c| *c != b'=')
+    {
+        let value = match c
+        {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6 | value as u32) & 0xffff;
+        bit_count += 6;
+        if bit_count >= 8
+        {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// uris are percent encoded, "my%20model.bin" is "my model.bin" on disk
+fn percent_decode(uri: &str) -> String
+{
+    let mut bytes = vec![];
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first()
+    {
+        let hex = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, hex)
+        {
+            (b'%', Some(decoded)) =>
+            {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ =>
+            {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::path::Path;
+
+    use glm::vec4;
+
+    use super::{
+        decode_base64, percent_decode, AlphaMode, GltfDocument, GltfError, ImageSource, Projection,
+        TextureRef,
+    };
+    use crate::{
+        animation::{skin_position, Pose},
+        mesh::STRIDE,
+    };
+
+    // a triangle: f32 positions, u16 indices and normalized u8 uvs padded to a
+    // 4 byte stride.
+    fn triangle_buffer() -> Vec<u8>
+    {
+        let mut bytes = vec![];
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+        {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0]
+        {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[0, 0, 9, 9, 255, 0, 9, 9, 0, 255, 9, 9]);
+        bytes
+    }
+
+    fn encode_base64(bytes: &[u8]) -> String
+    {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3)
+        {
+            let word = chunk.iter().enumerate().fold(0u32, |word, (i, byte)| {
+                word | (*byte as u32) << (16 - i * 8)
+            });
+            for i in 0..4
+            {
+                if i <= chunk.len()
+                {
+                    text.push(ALPHABET[(word >> (18 - i * 6)) as usize & 63] as char);
+                }
+                else
+                {
+                    text.push('=');
+                }
+            }
+        }
+        text
+    }
+
+    // the json for the triangle buffer, buffer is spliced into "buffers"
+    fn triangle_json(buffer: &str) -> String
+    {
+        format!(
+            r#"{{
+    "asset": {{ "version": "2.0" }},
+    "scene": 0,
+    "scenes": [ {{ "nodes": [0] }} ],
+    "nodes": [
+        {{ "name": "root", "translation": [1, 0, 0], "rotation": [0, 0.7071068, 0, 0.7071068], "children": [1] }},
+        {{ "name": "child", "translation": [0, 0, 1], "mesh": 0 }},
+        {{ "camera": 0, "translation": [0, 0, 5] }}
+    ],
+    "meshes": [ {{ "name": "triangle", "primitives": [ {{
+        "attributes": {{ "POSITION": 0, "TEXCOORD_0": 2 }},
+        "indices": 1,
+        "material": 0
+    }} ] }} ],
+    "materials": [ {{
+        "name": "red",
+        "pbrMetallicRoughness": {{
+            "baseColorFactor": [1, 0, 0, 1],
+            "baseColorTexture": {{ "index": 0 }},
+            "metallicFactor": 0.25
+        }},
+        "alphaMode": "MASK",
+        "doubleSided": true
+    }} ],
+    "textures": [ {{ "source": 0, "sampler": 0 }} ],
+    "samplers": [ {{ "magFilter": 9728, "wrapS": 33071 }} ],
+    "images": [ {{ "uri": "textures/red%20brick.png" }} ],
+    "cameras": [ {{ "type": "perspective", "perspective": {{ "yfov": 0.7853982, "znear": 0.1 }} }} ],
+    "buffers": [ {} ],
+    "bufferViews": [
+        {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+        {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }},
+        {{ "buffer": 0, "byteOffset": 44, "byteLength": 12, "byteStride": 4 }}
+    ],
+    "accessors": [
+        {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+        {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }},
+        {{ "bufferView": 2, "componentType": 5121, "normalized": true, "count": 3, "type": "VEC2" }}
+    ]
+}}"#,
+            buffer
+        )
+    }
+
+    fn triangle_gltf() -> GltfDocument
+    {
+        let buffer = triangle_buffer();
+        let uri = format!(
+            r#"{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}"#,
+            buffer.len(),
+            encode_base64(&buffer)
+        );
+        GltfDocument::parse(&triangle_json(&uri), Path::new("models")).unwrap()
+    }
+
+    fn glb(
+        json: &str,
+        bin: &[u8],
+    ) -> Vec<u8>
+    {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&((28 + json.len() + bin.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"JSON");
+        bytes.extend_from_slice(&json);
+        bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"BIN\0");
+        bytes.extend_from_slice(bin);
+        bytes
+    }
+
+    #[test]
+    fn test_triangle_from_data_uri()
+    {
+        let document = triangle_gltf();
+        let primitive = &document.meshes[0].primitives[0];
+        assert_eq!(document.meshes[0].name.as_deref(), Some("triangle"));
+        assert_eq!(primitive.material, Some(0));
+        assert_eq!(primitive.data.indices, vec![0, 1, 2]);
+        assert_eq!(primitive.data.vertex_count(), 3);
+
+        // the second vertex, with a generated normal and a strided uv
+        let second = &primitive.data.vertices[STRIDE as usize..2 * STRIDE as usize];
+        assert_eq!(second, &[1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_glb_matches_gltf()
+    {
+        let buffer = triangle_buffer();
+        let json = triangle_json(&format!(r#"{{ "byteLength": {} }}"#, buffer.len()));
+        let document = GltfDocument::parse_glb(&glb(&json, &buffer), Path::new("models")).unwrap();
+        assert_eq!(document, triangle_gltf());
+    }
+
+    #[test]
+    fn test_materials_textures_and_cameras()
+    {
+        let document = triangle_gltf();
+        let material = &document.materials[0];
+        assert_eq!(material.name.as_deref(), Some("red"));
+        assert_eq!(material.base_color_factor, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(
+            material.base_color_texture,
+            Some(TextureRef {
+                texture: 0,
+                tex_coord: 0
+            })
+        );
+        assert_eq!(material.metallic_factor, 0.25);
+        assert_eq!(material.roughness_factor, 1.0);
+        assert_eq!(material.alpha_mode, AlphaMode::Mask);
+        assert!(material.double_sided);
+
+        let sampler = &document.textures[0].sampler;
+        assert_eq!(
+            (sampler.mag_filter, sampler.min_filter),
+            (Some(gl::NEAREST), None)
+        );
+        assert_eq!(
+            (sampler.wrap_s, sampler.wrap_t),
+            (gl::CLAMP_TO_EDGE, gl::REPEAT)
+        );
+        assert_eq!(
+            document.images[0].source,
+            ImageSource::Path(Path::new("models/textures/red brick.png").to_path_buf())
+        );
+
+        assert!(matches!(
+            document.cameras[0].projection,
+            Projection::Perspective {
+                aspect_ratio: None,
+                zfar: None,
+                ..
+            }
+        ));
+        assert!(document.camera_start(3).is_none());
+        let start = document.camera_start(2).unwrap();
+        assert_eq!(start.position, glm::vec3(0.0, 0.0, 5.0));
+        assert!((start.yaw + 90.0).abs() < 1e-3 && start.pitch.abs() < 1e-3);
+        assert!((start.fov - 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_node_hierarchy()
+    {
+        let document = triangle_gltf();
+        assert_eq!(document.scene, Some(0));
+        assert_eq!(document.scenes[0].nodes, vec![0]);
+        assert_eq!(document.nodes[0].children, vec![1]);
+        assert_eq!(document.nodes[1].parent, Some(0));
+
+        // the child's +z offset turned a quarter around y lands on +x
+        let world = document.world_transforms();
+        let origin = world[1] * vec4(0.0, 0.0, 0.0, 1.0);
+        assert!(glm::length(origin - vec4(2.0, 0.0, 0.0, 1.0)) < 1e-5);
+
+        // only the child draws anything
+        let mesh_nodes = document.mesh_nodes();
+        assert_eq!(mesh_nodes.len(), 1);
+        assert_eq!(mesh_nodes[0].0, 0);
+        assert!(glm::length(mesh_nodes[0].1 * vec4(0.0, 0.0, 0.0, 1.0) - origin) < 1e-5);
+    }
+
+    #[test]
+    fn test_matrix_is_decomposed()
+    {
+        let json = r#"{ "asset": { "version": "2.0" }, "nodes": [
+            { "matrix": [0, 0, -2, 0, 0, 2, 0, 0, 2, 0, 0, 0, 1, 2, 3, 1] },
+            { "translation": [1, 2, 3], "rotation": [0, 0.7071068, 0, 0.7071068], "scale": [2, 2, 2] }
+        ] }"#;
+        let document = GltfDocument::parse(json, Path::new(".")).unwrap();
+        let world = document.world_transforms();
+        for (a, b) in [
+            (world[0].c0, world[1].c0),
+            (world[0].c1, world[1].c1),
+            (world[0].c2, world[1].c2),
+            (world[0].c3, world[1].c3),
+        ]
+        {
+            assert!(glm::length(a - b) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_skin_and_animation()
+    {
+        let mut buffer = vec![];
+        let mut floats = |values: &[f32]| {
+            for value in values
+            {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        // positions, then u8 joints and f32 weights with every vertex on the
+        // elbow
+        floats(&[0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 2.0, 1.0, 0.0]);
+        floats(&[f32::from_le_bytes([1, 0, 0, 0]); 3]);
+        floats(&[1.0, 0.0, 0.0, 0.0].repeat(3));
+        // inverse bind matrices for the shoulder and the elbow one unit along x
+        floats(&[
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+        ]);
+        floats(&[
+            1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0,
+        ]);
+        // a quarter turn around z over a second
+        floats(&[0.0, 1.0]);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, half, half]);
+
+        let json = format!(
+            r#"{{
+    "asset": {{ "version": "2.0" }},
+    "nodes": [
+        {{ "name": "shoulder", "children": [1] }},
+        {{ "name": "elbow", "translation": [1, 0, 0] }},
+        {{ "mesh": 0, "skin": 0 }}
+    ],
+    "meshes": [ {{ "primitives": [ {{ "attributes": {{ "POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2 }} }} ] }} ],
+    "skins": [ {{ "joints": [0, 1], "inverseBindMatrices": 3 }} ],
+    "animations": [ {{
+        "samplers": [ {{ "input": 4, "output": 5 }} ],
+        "channels": [
+            {{ "sampler": 0, "target": {{ "node": 1, "path": "rotation" }} }},
+            {{ "sampler": 0, "target": {{ "node": 2, "path": "weights" }} }}
+        ]
+    }} ],
+    "buffers": [ {{ "byteLength": {} }} ],
+    "bufferViews": [
+        {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+        {{ "buffer": 0, "byteOffset": 36, "byteLength": 12 }},
+        {{ "buffer": 0, "byteOffset": 48, "byteLength": 48 }},
+        {{ "buffer": 0, "byteOffset": 96, "byteLength": 128 }},
+        {{ "buffer": 0, "byteOffset": 224, "byteLength": 8 }},
+        {{ "buffer": 0, "byteOffset": 232, "byteLength": 32 }}
+    ],
+    "accessors": [
+        {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+        {{ "bufferView": 1, "componentType": 5121, "count": 3, "type": "VEC4" }},
+        {{ "bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4" }},
+        {{ "bufferView": 3, "componentType": 5126, "count": 2, "type": "MAT4" }},
+        {{ "bufferView": 4, "componentType": 5126, "count": 2, "type": "SCALAR" }},
+        {{ "bufferView": 5, "componentType": 5126, "count": 2, "type": "VEC4" }}
+    ]
+}}"#,
+            buffer.len()
+        );
+        let document = GltfDocument::parse_glb(&glb(&json, &buffer), Path::new(".")).unwrap();
+        assert_eq!(document.nodes[2].skin, Some(0));
+        let skin = document.meshes[0].primitives[0].skin.as_ref().unwrap();
+        assert_eq!(skin.joints, vec![[1.0, 0.0, 0.0, 0.0]; 3]);
+
+        let skeleton = document.skeleton(0);
+        assert_eq!(skeleton.joints[1].parent, Some(0));
+        let clip = document.clip(0, 0);
+        assert_eq!(clip.duration, 1.0);
+        // the weights channel is dropped and the rotation now targets joint 1
+        assert_eq!(clip.channels.len(), 1);
+        assert_eq!(clip.channels[0].target, 1);
+
+        let mut pose = Pose::rest(&skeleton);
+        clip.sample(1.0, &mut pose);
+        let matrices = skeleton.joint_matrices(&pose);
+        let hand = skin_position(
+            &matrices,
+            skin.joints[1],
+            skin.weights[1],
+            glm::vec3(2.0, 0.0, 0.0),
+        );
+        assert!(glm::length(hand - glm::vec3(1.0, 1.0, 0.0)) < 1e-5);
+
+        // every vertex uses joint 1, which a one joint skin doesn't have
+        let one_joint = json.replace(
+            r#""joints": [0, 1], "inverseBindMatrices": 3"#,
+            r#""joints": [0]"#,
+        );
+        let error = GltfDocument::parse_glb(&glb(&one_joint, &buffer), Path::new("."))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.ends_with("node 2 uses joint 1 of skin 0, which has 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_errors()
+    {
+        let error = |json: &str| {
+            GltfDocument::parse(json, Path::new("."))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(r#"{ "asset": { "version": "1.0" } }"#),
+            "1:25: gltf.asset.version: only glTF 2.x is supported"
+        );
+        assert_eq!(
+            error(r#"{ "asset": { "version": "2.0" }, "nodes": [ { "mesh": 0 } ] }"#),
+            "1:55: gltf.nodes[0].mesh: there is no mesh 0, the file has 0"
+        );
+        assert_eq!(
+            error(
+                r#"{ "asset": { "version": "2.0" }, "nodes": [ { "children": [1] }, { "children": [0] } ] }"#
+            ),
+            "1:43: gltf.nodes: node hierarchy has a cycle"
+        );
+        assert_eq!(
+            error(
+                r#"{ "asset": { "version": "2.0" }, "buffers": [ { "byteLength": 4, "uri": "data:,abc" } ] }"#
+            ),
+            "1:73: gltf.buffers[0].uri: only base64 data uris are supported"
+        );
+        assert!(matches!(
+            GltfDocument::parse_glb(b"glTF\x01\0\0\0", Path::new(".")),
+            Err(GltfError::Glb(_))
+        ));
+    }
+
+    #[test]
+    fn test_accessor_counts_are_checked()
+    {
+        let buffer = triangle_buffer();
+        let uri = format!(
+            r#"{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}"#,
+            buffer.len(),
+            encode_base64(&buffer)
+        );
+        let json = triangle_json(&uri);
+        let positions = r#"{ "bufferView": 0, "componentType": 5126, "count": 3,"#;
+        let parse = |accessor: &str| {
+            GltfDocument::parse(&json.replace(positions, accessor), Path::new("models"))
+        };
+
+        // the byte count would overflow
+        let error = parse(r#"{ "bufferView": 0, "componentType": 5126, "count": 1e30,"#)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.ends_with("elements don't fit in buffer view 0 of 36 bytes"),
+            "{}",
+            error
+        );
+
+        // zeros without a buffer view, a few are fine but not billions
+        assert!(parse(r#"{ "componentType": 5126, "count": 3,"#).is_ok());
+        let error = parse(r#"{ "componentType": 5126, "count": 4000000000,"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("without a buffer view"), "{}", error);
+    }
+
+    #[test]
+    fn test_uri_decoding()
+    {
+        assert_eq!(decode_base64("aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGk"), Some(b"hi".to_vec()));
+        assert_eq!(decode_base64("a*"), None);
+        assert_eq!(percent_decode("a%20b%2"), "a b%2");
+    }
+}
@@ -0,0 +1,177 @@
+use std::{fmt, io::Write};
+
+use glm::Vec3;
+
+use crate::{
+    components::RigidBody,
+    ecs::Entity,
+    game::CubeGameState,
+    physics::{PhysicsWorld, Quat},
+    scene::Scene,
+};
+
+// a body at one point of the simulation, written out one per line as
+// "entity px py pz qw qx qy qz vx vy vz wx wy wz".
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyState
+{
+    pub entity: Entity,
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+}
+
+impl fmt::Display for BodyState
+{
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result
+    {
+        let Self {
+            position: p,
+            orientation: q,
+            linear_velocity: v,
+            angular_velocity: w,
+            ..
+        } = self;
+        write!(
+            f,
+            "{} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            self.entity.index(),
+            p.x,
+            p.y,
+            p.z,
+            q.w,
+            q.x,
+            q.y,
+            q.z,
+            v.x,
+            v.y,
+            v.z,
+            w.x,
+            w.y,
+            w.z
+        )
+    }
+}
+
+pub fn body_states(state: &CubeGameState) -> Vec<BodyState>
+{
+    let bodies = state.world.components::<RigidBody>();
+    let physics = state.world.resource::<PhysicsWorld>();
+    bodies
+        .iter()
+        .map(|(entity, body)| {
+            let body = physics.body(body.handle);
+            BodyState {
+                entity,
+                position: body.position,
+                orientation: body.orientation,
+                linear_velocity: body.linear_velocity,
+                angular_velocity: body.angular_velocity,
+            }
+        })
+        .collect()
+}
+
+// steps a scene without a window or gl context, no keys are held.
+pub fn simulate(
+    scene: &Scene,
+    ticks: u32,
+) -> CubeGameState
+{
+    let mut state = CubeGameState::from_scene(scene);
+    for _ in 0..ticks
+    {
+        state.fixed_update();
+    }
+    state
+}
+
+// simulates and writes the final body states, the state is handed back so it
+// can be saved.
+pub fn run(
+    scene: &Scene,
+    ticks: u32,
+    out: &mut impl Write,
+) -> std::io::Result<CubeGameState>
+{
+    let state = simulate(scene, ticks);
+    write_states(&state, ticks.into(), out)?;
+    Ok(state)
+}
+
+// "# tick <ticks>" and then a line per body
+pub fn write_states(
+    state: &CubeGameState,
+    ticks: u64,
+    out: &mut impl Write,
+) -> std::io::Result<()>
+{
+    writeln!(out, "# tick {}", ticks)?;
+    for body in body_states(state)
+    {
+        writeln!(out, "{}", body)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::{body_states, run, simulate};
+    use crate::{
+        cli::DEFAULT_TICKS,
+        scene::{Scene, DEFAULT_SCENE},
+    };
+
+    fn demo() -> Scene
+    {
+        Scene::load(DEFAULT_SCENE).unwrap()
+    }
+
+    #[test]
+    fn test_runs_are_deterministic()
+    {
+        let mut first = vec![];
+        let mut second = vec![];
+        run(&demo(), 240, &mut first).unwrap();
+        run(&demo(), 240, &mut second).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_demo_scene_settles_on_floor()
+    {
+        let state = simulate(&demo(), DEFAULT_TICKS);
+        let bodies = body_states(&state);
+        assert_eq!(bodies.len(), 10);
+
+        // the floor's top is at -4.0 and every cube is 1.0 across
+        for body in &bodies[..9]
+        {
+            assert!(body.position.y > -3.6, "{} fell through the floor", body);
+            assert!(
+                glm::length(body.linear_velocity) < 0.05,
+                "{} is still moving",
+                body
+            );
+        }
+        // the player cube starts alone and should sit right on the floor
+        assert!((bodies[0].position.y + 3.5).abs() < 0.02, "{}", bodies[0]);
+    }
+
+    #[test]
+    fn test_output_has_a_line_per_body()
+    {
+        let mut out = vec![];
+        run(&demo(), 1, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "# tick 1");
+        assert_eq!(lines.len(), 11);
+        assert!(lines[1..].iter().all(|line| line.split(' ').count() == 14));
+    }
+}
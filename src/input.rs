@@ -0,0 +1,591 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+};
+
+use gilrs::{Axis, EventType};
+use thiserror::Error;
+use winit::event::{DeviceEvent, ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+
+use crate::json::{self, read_array, read_string, Field, FieldError, JsonError, Object, Value};
+
+pub const DEFAULT_BINDINGS: &str = "./resources/input.json";
+
+// stick values closer to the middle than this count as 0
+pub const DEAD_ZONE: f32 = 0.15;
+
+// the actions and axes the engine reads, bindings files can add more
+pub const MOVE_FORWARD: &str = "move_forward";
+pub const MOVE_RIGHT: &str = "move_right";
+pub const MOVE_UP: &str = "move_up";
+pub const LOOK_RIGHT: &str = "look_right";
+pub const LOOK_UP: &str = "look_up";
+pub const JUMP: &str = "jump";
+pub const SWITCH_CAMERA: &str = "switch_camera";
+pub const QUIT: &str = "quit";
+pub const TOGGLE_GUI: &str = "toggle_gui";
+pub const PUSH_X: &str = "push_x";
+pub const PUSH_Y: &str = "push_y";
+pub const PUSH_Z: &str = "push_z";
+
+#[derive(Debug, Error)]
+pub enum BindingsError
+{
+    #[error("can't read bindings file {path}: {source}")]
+    Io
+    {
+        path: String, source: io::Error
+    },
+    #[error("{0}")]
+    Syntax(#[from] JsonError),
+    #[error("{0}")]
+    Invalid(#[from] FieldError),
+    #[error("{path}:{source}")]
+    InFile
+    {
+        path: String,
+        source: Box<BindingsError>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button
+{
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(gilrs::Button),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AxisBinding
+{
+    // 1 while positive is held, -1 while negative is, 0 with both
+    Buttons
+    {
+        positive: Button,
+        negative: Button,
+    },
+    Gamepad(Axis),
+}
+
+// lists of (name in bindings files, value), the names are the variants'
+macro_rules! names {
+    ($table:ident: $type:ty { $($variant:ident),* $(,)? }) => {
+        const $table: &[(&str, $type)] = &[$((stringify!($variant), <$type>::$variant)),*];
+    };
+}
+
+names!(KEYS: VirtualKeyCode {
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Space, Return, Tab, Back, Insert, Delete, Home, End, PageUp, PageDown,
+    Left, Right, Up, Down, LShift, RShift, LControl, RControl, LAlt, RAlt,
+});
+names!(MOUSE_BUTTONS: MouseButton { Left, Right, Middle });
+names!(GAMEPAD_BUTTONS: gilrs::Button {
+    South, East, North, West, C, Z,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+    Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+});
+names!(GAMEPAD_AXES: Axis {
+    LeftStickX, LeftStickY, LeftZ, RightStickX, RightStickY, RightZ, DPadX, DPadY,
+});
+
+fn find<T: Copy>(
+    table: &[(&str, T)],
+    name: &str,
+) -> Option<T>
+{
+    table
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, value)| *value)
+}
+
+impl Button
+{
+    // "W" for keys, "Mouse Left" and "Gamepad South"
+    pub fn parse(name: &str) -> Option<Self>
+    {
+        if let Some(name) = name.strip_prefix("Mouse ")
+        {
+            find(MOUSE_BUTTONS, name).map(Button::Mouse)
+        }
+        else if let Some(name) = name.strip_prefix("Gamepad ")
+        {
+            find(GAMEPAD_BUTTONS, name).map(Button::Gamepad)
+        }
+        else
+        {
+            find(KEYS, name).map(Button::Key)
+        }
+    }
+}
+
+fn read_button(field: Field) -> Result<Button, FieldError>
+{
+    let name = read_string(field.clone())?;
+    Button::parse(&name).ok_or_else(|| field.invalid(format!("unknown button `{}`", name)))
+}
+
+// a "Gamepad LeftStickX" string, or a [positive, negative] pair of buttons
+fn read_axis_binding(field: Field) -> Result<AxisBinding, FieldError>
+{
+    if let Value::String(name) = &field.json.value
+    {
+        return name
+            .strip_prefix("Gamepad ")
+            .and_then(|name| find(GAMEPAD_AXES, name))
+            .map(AxisBinding::Gamepad)
+            .ok_or_else(|| field.invalid(format!("unknown gamepad axis `{}`", name)));
+    }
+    let buttons = read_array(field.clone(), read_button)?;
+    match buttons[..]
+    {
+        [positive, negative] => Ok(AxisBinding::Buttons { positive, negative }),
+        _ => Err(field.expected("a gamepad axis or a [positive, negative] pair of buttons")),
+    }
+}
+
+// which buttons and axes drive each named action and axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings
+{
+    actions: HashMap<String, Vec<Button>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+}
+
+impl Default for Bindings
+{
+    fn default() -> Self
+    {
+        let keys = |positive, negative| AxisBinding::Buttons {
+            positive: Button::Key(positive),
+            negative: Button::Key(negative),
+        };
+        let mut bindings = Self {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+        };
+        bindings.bind_axis(MOVE_FORWARD, keys(VirtualKeyCode::W, VirtualKeyCode::S));
+        bindings.bind_axis(MOVE_FORWARD, AxisBinding::Gamepad(Axis::LeftStickY));
+        bindings.bind_axis(MOVE_RIGHT, keys(VirtualKeyCode::D, VirtualKeyCode::A));
+        bindings.bind_axis(MOVE_RIGHT, AxisBinding::Gamepad(Axis::LeftStickX));
+        bindings.bind_axis(MOVE_UP, keys(VirtualKeyCode::E, VirtualKeyCode::Q));
+        bindings.bind_axis(LOOK_RIGHT, AxisBinding::Gamepad(Axis::RightStickX));
+        bindings.bind_axis(LOOK_UP, AxisBinding::Gamepad(Axis::RightStickY));
+        bindings.bind_axis(PUSH_X, keys(VirtualKeyCode::L, VirtualKeyCode::J));
+        bindings.bind_axis(PUSH_Y, keys(VirtualKeyCode::I, VirtualKeyCode::K));
+        bindings.bind_axis(PUSH_Z, keys(VirtualKeyCode::U, VirtualKeyCode::O));
+        bindings.bind(JUMP, Button::Key(VirtualKeyCode::Space));
+        bindings.bind(JUMP, Button::Gamepad(gilrs::Button::South));
+        bindings.bind(SWITCH_CAMERA, Button::Key(VirtualKeyCode::C));
+        bindings.bind(SWITCH_CAMERA, Button::Gamepad(gilrs::Button::North));
+        bindings.bind(QUIT, Button::Key(VirtualKeyCode::Escape));
+        bindings.bind(TOGGLE_GUI, Button::Key(VirtualKeyCode::F1));
+        bindings
+    }
+}
+
+impl Bindings
+{
+    pub fn load(path: &str) -> Result<Self, BindingsError>
+    {
+        let source = fs::read_to_string(path).map_err(|source| BindingsError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Self::parse(&source).map_err(|error| BindingsError::InFile {
+            path: path.to_string(),
+            source: Box::new(error),
+        })
+    }
+
+    // {"actions": {"jump": ["Space"]}, "axes": {"move_right": [["D", "A"]]}},
+    // anything left out of the file keeps no bindings
+    pub fn parse(source: &str) -> Result<Self, BindingsError>
+    {
+        let json = json::parse(source)?;
+        let root = Object::new(&json, "bindings", &["actions", "axes"])?;
+        let mut bindings = Self {
+            actions: HashMap::new(),
+            axes: HashMap::new(),
+        };
+        if let Some(actions) = root.optional("actions")
+        {
+            for (name, field) in Object::any_keys(actions.json, &actions.path)?.fields()
+            {
+                bindings
+                    .actions
+                    .insert(name.to_string(), read_array(field, read_button)?);
+            }
+        }
+        if let Some(axes) = root.optional("axes")
+        {
+            for (name, field) in Object::any_keys(axes.json, &axes.path)?.fields()
+            {
+                bindings
+                    .axes
+                    .insert(name.to_string(), read_array(field, read_axis_binding)?);
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn bind(
+        &mut self,
+        action: &str,
+        button: Button,
+    )
+    {
+        self.actions
+            .entry(action.to_string())
+            .or_default()
+            .push(button);
+    }
+
+    pub fn bind_axis(
+        &mut self,
+        axis: &str,
+        binding: AxisBinding,
+    )
+    {
+        self.axes.entry(axis.to_string()).or_default().push(binding);
+    }
+
+    pub fn action(
+        &self,
+        name: &str,
+    ) -> &[Button]
+    {
+        self.actions.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn axis(
+        &self,
+        name: &str,
+    ) -> &[AxisBinding]
+    {
+        self.axes.get(name).map_or(&[], Vec::as_slice)
+    }
+}
+
+// what is held right now and what changed this frame. the window and gamepad
+// events go in, or synthetic ones through press, release and set_axis, and
+// end_frame starts the next frame.
+pub struct Input
+{
+    pub bindings: Bindings,
+    held: HashSet<Button>,
+    // went down or up since the last end_frame, so a tap inside one frame
+    // still counts as pressed and released
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    gamepad_axes: HashMap<Axis, f32>,
+}
+
+impl Input
+{
+    pub fn new(bindings: Bindings) -> Self
+    {
+        Self {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            gamepad_axes: HashMap::new(),
+        }
+    }
+
+    pub fn press(
+        &mut self,
+        button: Button,
+    )
+    {
+        // key repeat sends presses for a key that is already down
+        if self.held.insert(button)
+        {
+            self.pressed.insert(button);
+        }
+    }
+
+    pub fn release(
+        &mut self,
+        button: Button,
+    )
+    {
+        if self.held.remove(&button)
+        {
+            self.released.insert(button);
+        }
+    }
+
+    pub fn set_axis(
+        &mut self,
+        axis: Axis,
+        value: f32,
+    )
+    {
+        self.gamepad_axes.insert(axis, value);
+    }
+
+    pub fn end_frame(&mut self)
+    {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    // keys come from device events so they work while the cursor is grabbed
+    pub fn device_event(
+        &mut self,
+        event: &DeviceEvent,
+    )
+    {
+        if let DeviceEvent::Key(keyboard_input) = event
+        {
+            if let Some(key) = keyboard_input.virtual_keycode
+            {
+                self.button_event(Button::Key(key), keyboard_input.state);
+            }
+        }
+    }
+
+    pub fn window_event(
+        &mut self,
+        event: &WindowEvent,
+    )
+    {
+        if let WindowEvent::MouseInput { state, button, .. } = event
+        {
+            self.button_event(Button::Mouse(*button), *state);
+        }
+    }
+
+    // every connected gamepad drives the same buttons and axes
+    pub fn gamepad_event(
+        &mut self,
+        event: EventType,
+    )
+    {
+        match event
+        {
+            EventType::ButtonPressed(button, _) => self.press(Button::Gamepad(button)),
+            EventType::ButtonReleased(button, _) => self.release(Button::Gamepad(button)),
+            EventType::AxisChanged(axis, value, _) => self.set_axis(axis, value),
+            EventType::Disconnected =>
+            {
+                let buttons: Vec<_> = self
+                    .held
+                    .iter()
+                    .copied()
+                    .filter(|button| matches!(button, Button::Gamepad(_)))
+                    .collect();
+                for button in buttons
+                {
+                    self.release(button);
+                }
+                self.gamepad_axes.clear();
+            }
+            _ =>
+            {}
+        }
+    }
+
+    fn button_event(
+        &mut self,
+        button: Button,
+        state: ElementState,
+    )
+    {
+        match state
+        {
+            ElementState::Pressed => self.press(button),
+            ElementState::Released => self.release(button),
+        }
+    }
+
+    pub fn held(
+        &self,
+        action: &str,
+    ) -> bool
+    {
+        self.bindings
+            .action(action)
+            .iter()
+            .any(|button| self.held.contains(button))
+    }
+
+    // went down this frame
+    pub fn pressed(
+        &self,
+        action: &str,
+    ) -> bool
+    {
+        self.bindings
+            .action(action)
+            .iter()
+            .any(|button| self.pressed.contains(button))
+    }
+
+    // went up this frame
+    pub fn released(
+        &self,
+        action: &str,
+    ) -> bool
+    {
+        self.bindings
+            .action(action)
+            .iter()
+            .any(|button| self.released.contains(button))
+    }
+
+    // the bindings added up, between -1 and 1
+    pub fn axis(
+        &self,
+        name: &str,
+    ) -> f32
+    {
+        let button = |button| self.held.contains(button) as i32 as f32;
+        let total: f32 = self
+            .bindings
+            .axis(name)
+            .iter()
+            .map(|binding| match binding
+            {
+                AxisBinding::Buttons { positive, negative } => button(positive) - button(negative),
+                AxisBinding::Gamepad(axis) =>
+                {
+                    let value = self.gamepad_axes.get(axis).copied().unwrap_or(0.0);
+                    if value.abs() < DEAD_ZONE
+                    {
+                        0.0
+                    }
+                    else
+                    {
+                        value
+                    }
+                }
+            })
+            .sum();
+        total.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use gilrs::{Axis, EventType};
+    use winit::event::{MouseButton, VirtualKeyCode};
+
+    use super::{
+        AxisBinding, Bindings, BindingsError, Button, Input, DEFAULT_BINDINGS, JUMP, MOVE_FORWARD,
+        MOVE_RIGHT, QUIT,
+    };
+
+    #[test]
+    fn test_edges()
+    {
+        let mut input = Input::new(Bindings::default());
+        let space = Button::Key(VirtualKeyCode::Space);
+        input.press(space);
+        assert!(input.pressed(JUMP) && input.held(JUMP) && !input.released(JUMP));
+        input.end_frame();
+        // repeats don't press again
+        input.press(space);
+        assert!(!input.pressed(JUMP) && input.held(JUMP));
+        input.release(space);
+        assert!(input.released(JUMP) && !input.held(JUMP));
+        input.end_frame();
+        assert!(!input.released(JUMP));
+
+        // down and up inside one frame
+        input.press(space);
+        input.release(space);
+        assert!(input.pressed(JUMP) && input.released(JUMP) && !input.held(JUMP));
+        assert!(!input.held("no such action"));
+    }
+
+    #[test]
+    fn test_axes()
+    {
+        let mut input = Input::new(Bindings::default());
+        input.press(Button::Key(VirtualKeyCode::W));
+        assert_eq!(input.axis(MOVE_FORWARD), 1.0);
+        input.press(Button::Key(VirtualKeyCode::S));
+        assert_eq!(input.axis(MOVE_FORWARD), 0.0);
+
+        input.set_axis(Axis::LeftStickX, -0.5);
+        assert_eq!(input.axis(MOVE_RIGHT), -0.5);
+        input.set_axis(Axis::LeftStickX, 0.1);
+        assert_eq!(input.axis(MOVE_RIGHT), 0.0);
+        // the keyboard and the stick together still stop at 1
+        input.set_axis(Axis::LeftStickX, 0.8);
+        input.press(Button::Key(VirtualKeyCode::D));
+        assert_eq!(input.axis(MOVE_RIGHT), 1.0);
+
+        input.press(Button::Gamepad(gilrs::Button::South));
+        assert!(input.pressed(JUMP));
+        // losing the gamepad lets go of its buttons and sticks
+        input.gamepad_event(EventType::Disconnected);
+        assert!(!input.held(JUMP));
+        // only the key is left
+        input.release(Button::Key(VirtualKeyCode::D));
+        assert_eq!(input.axis(MOVE_RIGHT), 0.0);
+    }
+
+    #[test]
+    fn test_parse_bindings()
+    {
+        let bindings = Bindings::parse(
+            r#"{
+                "actions": {"quit": ["Q", "Mouse Right", "Gamepad Start"]},
+                "axes": {"move_forward": [["Up", "Down"], "Gamepad RightStickY"]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.action(QUIT),
+            [
+                Button::Key(VirtualKeyCode::Q),
+                Button::Mouse(MouseButton::Right),
+                Button::Gamepad(gilrs::Button::Start),
+            ]
+        );
+        assert_eq!(
+            bindings.axis(MOVE_FORWARD),
+            [
+                AxisBinding::Buttons {
+                    positive: Button::Key(VirtualKeyCode::Up),
+                    negative: Button::Key(VirtualKeyCode::Down),
+                },
+                AxisBinding::Gamepad(Axis::RightStickY),
+            ]
+        );
+        assert!(bindings.action(JUMP).is_empty());
+
+        let error = |source: &str| match Bindings::parse(source).unwrap_err()
+        {
+            BindingsError::Invalid(error) => error.to_string(),
+            error => panic!("{}", error),
+        };
+        assert_eq!(
+            error(r#"{"actions": {"jump": ["Hyper"]}}"#),
+            "1:23: bindings.actions.jump[0]: unknown button `Hyper`"
+        );
+        assert_eq!(
+            error(r#"{"axes": {"move_up": [["E"]]}}"#),
+            "1:23: bindings.axes.move_up[0]: expected a gamepad axis or a [positive, negative] pair of buttons, found an array"
+        );
+    }
+
+    #[test]
+    fn test_default_bindings_file()
+    {
+        assert_eq!(
+            Bindings::load(DEFAULT_BINDINGS).unwrap(),
+            Bindings::default()
+        );
+    }
+}
@@ -0,0 +1,815 @@
+use std::fmt::Write;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // kept as a list so saving writes keys back in the order they were read
+    Object(Vec<(String, Json)>),
+}
+
+// a value plus where it started in the source, 1-based. values built in code
+// sit at 0:0.
+#[derive(Debug, Clone)]
+pub struct Json
+{
+    pub value: Value,
+    pub line: usize,
+    pub column: usize,
+}
+
+// positions don't take part in comparisons, a saved and reloaded document is
+// equal to the one it came from.
+impl PartialEq for Json
+{
+    fn eq(
+        &self,
+        other: &Self,
+    ) -> bool
+    {
+        self.value == other.value
+    }
+}
+
+impl From<Value> for Json
+{
+    fn from(value: Value) -> Self
+    {
+        Self {
+            value,
+            line: 0,
+            column: 0,
+        }
+    }
+}
+
+impl Json
+{
+    pub fn type_name(&self) -> &'static str
+    {
+        match self.value
+        {
+            Value::Null => "null",
+            Value::Bool(_) => "a bool",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("{line}:{column}: {message}")]
+pub struct JsonError
+{
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+pub fn parse(source: &str) -> Result<Json, JsonError>
+{
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        line: 1,
+        column: 1,
+    };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek()
+    {
+        Some(c) => Err(parser.error(format!("unexpected `{}` after the document", c))),
+        None => Ok(value),
+    }
+}
+
+struct Parser
+{
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser
+{
+    fn error(
+        &self,
+        message: String,
+    ) -> JsonError
+    {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.chars.get(self.position).copied()
+    }
+
+    fn bump(&mut self) -> Option<char>
+    {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n'
+        {
+            self.line += 1;
+            self.column = 1;
+        }
+        else
+        {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(
+        &mut self,
+        expected: char,
+    ) -> Result<(), JsonError>
+    {
+        match self.peek()
+        {
+            Some(c) if c == expected =>
+            {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.error(format!(
+                "expected `{}`, found the end of the file",
+                expected
+            ))),
+        }
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r'))
+        {
+            self.bump();
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError>
+    {
+        let (line, column) = (self.line, self.column);
+        let value = match self.peek()
+        {
+            Some('{') => self.object()?,
+            Some('[') => self.array()?,
+            Some('"') => Value::String(self.string()?),
+            Some('-' | '0'..='9') => self.number()?,
+            Some(c) if c.is_alphabetic() => self.keyword()?,
+            Some(c) => return Err(self.error(format!("unexpected `{}`", c))),
+            None => return Err(self.error("unexpected end of file".to_string())),
+        };
+        Ok(Json {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn keyword(&mut self) -> Result<Value, JsonError>
+    {
+        let error = self.error(String::new());
+        let mut word = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric())
+        {
+            word.push(c);
+            self.bump();
+        }
+        match word.as_str()
+        {
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            "null" => Ok(Value::Null),
+            _ => Err(JsonError {
+                message: format!("unknown keyword `{}`", word),
+                ..error
+            }),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonError>
+    {
+        let error = self.error(String::new());
+        let mut text = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+        {
+            text.push(c);
+            self.bump();
+        }
+        // too large numbers parse as infinity, which couldn't be saved again
+        match text.parse::<f64>()
+        {
+            Ok(number) if number.is_finite() => Ok(Value::Number(number)),
+            Ok(_) => Err(JsonError {
+                message: format!("number `{}` is too large", text),
+                ..error
+            }),
+            Err(_) => Err(JsonError {
+                message: format!("invalid number `{}`", text),
+                ..error
+            }),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError>
+    {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop
+        {
+            let error = self.error(String::new());
+            match self.bump()
+            {
+                Some('"') => return Ok(text),
+                Some('\\') => text.push(self.escape()?),
+                Some('\n') | None =>
+                {
+                    return Err(JsonError {
+                        message: "unterminated string".to_string(),
+                        ..error
+                    })
+                }
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, JsonError>
+    {
+        let error = self.error(String::new());
+        let c = match self.bump()
+        {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') =>
+            {
+                let mut code = 0;
+                for _ in 0..4
+                {
+                    let digit = self.bump().and_then(|c| c.to_digit(16));
+                    code = code * 16
+                        + digit.ok_or_else(|| JsonError {
+                            message: "\\u needs four hex digits".to_string(),
+                            ..error.clone()
+                        })?;
+                }
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ =>
+            {
+                return Err(JsonError {
+                    message: "invalid escape".to_string(),
+                    ..error
+                })
+            }
+        };
+        Ok(c)
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError>
+    {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']')
+        {
+            self.bump();
+            return Ok(Value::Array(items));
+        }
+        loop
+        {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek()
+            {
+                Some(',') =>
+                {
+                    self.bump();
+                }
+                Some(']') =>
+                {
+                    self.bump();
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]` in array".to_string())),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError>
+    {
+        self.expect('{')?;
+        let mut fields: Vec<(String, Json)> = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}')
+        {
+            self.bump();
+            return Ok(Value::Object(fields));
+        }
+        loop
+        {
+            self.skip_whitespace();
+            let error = self.error(String::new());
+            let key = self.string()?;
+            if fields.iter().any(|(existing, _)| *existing == key)
+            {
+                return Err(JsonError {
+                    message: format!("duplicate key `{}`", key),
+                    ..error
+                });
+            }
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek()
+            {
+                Some(',') =>
+                {
+                    self.bump();
+                }
+                Some('}') =>
+                {
+                    self.bump();
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(self.error("expected `,` or `}` in object".to_string())),
+            }
+        }
+    }
+}
+
+// a value and the dotted path to it, for error messages.
+#[derive(Clone)]
+pub struct Field<'a>
+{
+    pub json: &'a Json,
+    pub path: String,
+}
+
+impl Field<'_>
+{
+    pub fn invalid(
+        &self,
+        message: impl Into<String>,
+    ) -> FieldError
+    {
+        FieldError {
+            line: self.json.line,
+            column: self.json.column,
+            field: self.path.clone(),
+            message: message.into(),
+        }
+    }
+
+    pub fn expected(
+        &self,
+        what: &str,
+    ) -> FieldError
+    {
+        self.invalid(format!(
+            "expected {}, found {}",
+            what,
+            self.json.type_name()
+        ))
+    }
+}
+
+// an object, optionally with its keys checked against the ones a format
+// allows.
+pub struct Object<'a>
+{
+    fields: &'a [(String, Json)],
+    path: String,
+    json: &'a Json,
+}
+
+impl<'a> Object<'a>
+{
+    pub fn new(
+        json: &'a Json,
+        path: &str,
+        allowed: &[&str],
+    ) -> Result<Self, FieldError>
+    {
+        let object = Self::any_keys(json, path)?;
+        if let Some((key, value)) = object
+            .fields
+            .iter()
+            .find(|(key, _)| !allowed.contains(&key.as_str()))
+        {
+            return Err(Field {
+                json: value,
+                path: format!("{}.{}", path, key),
+            }
+            .invalid(format!(
+                "unknown field, expected one of {}",
+                allowed.join(", ")
+            )));
+        }
+        Ok(object)
+    }
+
+    // for formats with extension points, unknown keys are ignored
+    pub fn any_keys(
+        json: &'a Json,
+        path: &str,
+    ) -> Result<Self, FieldError>
+    {
+        let field = Field {
+            json,
+            path: path.to_string(),
+        };
+        let Value::Object(fields) = &json.value
+        else
+        {
+            return Err(field.expected("an object"));
+        };
+        Ok(Self {
+            fields,
+            path: path.to_string(),
+            json,
+        })
+    }
+
+    // every key with its value, in the order they were written
+    pub fn fields(&self) -> impl Iterator<Item = (&'a str, Field<'a>)> + '_
+    {
+        self.fields.iter().map(|(key, json)| {
+            (
+                key.as_str(),
+                Field {
+                    json,
+                    path: format!("{}.{}", self.path, key),
+                },
+            )
+        })
+    }
+
+    pub fn optional(
+        &self,
+        key: &str,
+    ) -> Option<Field<'a>>
+    {
+        self.fields
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, json)| Field {
+                json,
+                path: format!("{}.{}", self.path, key),
+            })
+    }
+
+    pub fn required(
+        &self,
+        key: &str,
+    ) -> Result<Field<'a>, FieldError>
+    {
+        self.optional(key).ok_or_else(|| {
+            Field {
+                json: self.json,
+                path: self.path.clone(),
+            }
+            .invalid(format!("missing field `{}`", key))
+        })
+    }
+}
+
+// read is called with every item, its error only has to be able to hold a
+// FieldError so callers can read items into their own types.
+pub fn read_array<T, E: From<FieldError>>(
+    field: Field,
+    read: impl Fn(Field) -> Result<T, E>,
+) -> Result<Vec<T>, E>
+{
+    let Value::Array(items) = &field.json.value
+    else
+    {
+        return Err(field.expected("an array").into());
+    };
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, json)| {
+            read(Field {
+                json,
+                path: format!("{}[{}]", field.path, i),
+            })
+        })
+        .collect()
+}
+
+pub fn read_f32(field: Field) -> Result<f32, FieldError>
+{
+    match field.json.value
+    {
+        Value::Number(n) if (n as f32).is_finite() => Ok(n as f32),
+        Value::Number(n) => Err(field.invalid(format!("{:e} is too large for an f32", n))),
+        _ => Err(field.expected("a number")),
+    }
+}
+
+pub fn read_bool(field: Field) -> Result<bool, FieldError>
+{
+    match field.json.value
+    {
+        Value::Bool(b) => Ok(b),
+        _ => Err(field.expected("a bool")),
+    }
+}
+
+pub fn read_string(field: Field) -> Result<String, FieldError>
+{
+    match &field.json.value
+    {
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(field.expected("a string")),
+    }
+}
+
+pub fn read_floats<const N: usize>(field: Field) -> Result<[f32; N], FieldError>
+{
+    let values: Vec<f32> = read_array(field.clone(), read_f32)?;
+    values.try_into().map_err(|values: Vec<f32>| {
+        field.invalid(format!("expected {} numbers, found {}", N, values.len()))
+    })
+}
+
+pub fn read_usize(field: Field) -> Result<usize, FieldError>
+{
+    match field.json.value
+    {
+        Value::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+        _ => Err(field.expected("a non-negative integer")),
+    }
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("{line}:{column}: {field}: {message}")]
+pub struct FieldError
+{
+    pub line: usize,
+    pub column: usize,
+    pub field: String,
+    pub message: String,
+}
+
+// json has no NaN or infinity, saving one is refused rather than writing a
+// file that can't be read back.
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("{field}: {value} can't be saved as a json number")]
+pub struct NumberError
+{
+    pub field: String,
+    pub value: f64,
+}
+
+// four space indents, arrays of plain numbers stay on one line so vectors
+// read as [1, 2, 3].
+pub fn to_string_pretty(json: &Json) -> Result<String, NumberError>
+{
+    let mut out = String::new();
+    write_value(&mut out, json, 0, "")?;
+    out.push('\n');
+    Ok(out)
+}
+
+// path is the dotted path to the value, for errors
+fn write_value(
+    out: &mut String,
+    json: &Json,
+    indent: usize,
+    path: &str,
+) -> Result<(), NumberError>
+{
+    let pad = |depth: usize| "    ".repeat(depth);
+    let item_path = |i: usize| format!("{}[{}]", path, i);
+    match &json.value
+    {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{}", b).unwrap(),
+        Value::Number(n) if !n.is_finite() =>
+        {
+            return Err(NumberError {
+                field: path.to_string(),
+                value: *n,
+            });
+        }
+        Value::Number(n) => write!(out, "{}", n).unwrap(),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) if items.is_empty() => out.push_str("[]"),
+        Value::Array(items)
+            if items
+                .iter()
+                .all(|item| matches!(item.value, Value::Number(_))) =>
+        {
+            out.push('[');
+            for (i, item) in items.iter().enumerate()
+            {
+                if i > 0
+                {
+                    out.push_str(", ");
+                }
+                write_value(out, item, indent, &item_path(i))?;
+            }
+            out.push(']');
+        }
+        Value::Array(items) =>
+        {
+            out.push_str("[\n");
+            for (i, item) in items.iter().enumerate()
+            {
+                out.push_str(&pad(indent + 1));
+                write_value(out, item, indent + 1, &item_path(i))?;
+                out.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push(']');
+        }
+        Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+        Value::Object(fields) =>
+        {
+            out.push_str("{\n");
+            for (i, (key, value)) in fields.iter().enumerate()
+            {
+                out.push_str(&pad(indent + 1));
+                write_string(out, key);
+                out.push_str(": ");
+                let path = match path
+                {
+                    "" => key.clone(),
+                    path => format!("{}.{}", path, key),
+                };
+                write_value(out, value, indent + 1, &path)?;
+                out.push_str(if i + 1 < fields.len() { ",\n" } else { "\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn write_string(
+    out: &mut String,
+    s: &str,
+)
+{
+    out.push('"');
+    for c in s.chars()
+    {
+        match c
+        {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::{parse, to_string_pretty, JsonError, NumberError, Value};
+
+    #[test]
+    fn test_parse_values()
+    {
+        let json = parse(r#"{"a": [1, -2.5e1, true, null], "b": "x\"A\n"}"#).unwrap();
+        let Value::Object(fields) = &json.value
+        else
+        {
+            panic!("not an object");
+        };
+        assert_eq!(fields[0].0, "a");
+        let Value::Array(items) = &fields[0].1.value
+        else
+        {
+            panic!("not an array");
+        };
+        assert_eq!(items[1].value, Value::Number(-25.0));
+        assert_eq!(items[2].value, Value::Bool(true));
+        assert_eq!(fields[1].1.value, Value::String("x\"A\n".to_string()));
+    }
+
+    #[test]
+    fn test_positions()
+    {
+        let json = parse("{\n  \"a\": 1,\n  \"b\": [true]\n}").unwrap();
+        let Value::Object(fields) = &json.value
+        else
+        {
+            panic!("not an object");
+        };
+        assert_eq!((fields[1].1.line, fields[1].1.column), (3, 8));
+    }
+
+    #[test]
+    fn test_errors_point_at_the_problem()
+    {
+        assert_eq!(
+            parse("{\n  \"a\": 1\n  \"b\": 2\n}"),
+            Err(JsonError {
+                line: 3,
+                column: 3,
+                message: "expected `,` or `}` in object".to_string()
+            })
+        );
+        assert_eq!(
+            parse("[1, tru]").unwrap_err().message,
+            "unknown keyword `tru`"
+        );
+        assert_eq!(parse("{\"a\": 1, \"a\": 2}").unwrap_err().column, 10);
+        assert!(parse("\"open").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert_eq!(
+            parse("[1, 1e400]"),
+            Err(JsonError {
+                line: 1,
+                column: 5,
+                message: "number `1e400` is too large".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_round_trip()
+    {
+        let source = r#"{"name": "tab\there", "v": [1, 0.1, -3], "nested": [{"x": {}}, []]}"#;
+        let json = parse(source).unwrap();
+        let saved = to_string_pretty(&json).unwrap();
+        assert_eq!(parse(&saved).unwrap(), json);
+        assert!(saved.contains("\"v\": [1, 0.1, -3]"));
+    }
+
+    #[test]
+    fn test_non_finite_numbers_are_refused()
+    {
+        let mut json = parse(r#"{"nested": [{"x": [1, 2]}]}"#).unwrap();
+        let Value::Object(fields) = &mut json.value
+        else
+        {
+            unreachable!()
+        };
+        let Value::Array(items) = &mut fields[0].1.value
+        else
+        {
+            unreachable!()
+        };
+        let Value::Object(fields) = &mut items[0].value
+        else
+        {
+            unreachable!()
+        };
+        fields[0].1 = Value::Array(vec![
+            Value::Number(1.0).into(),
+            Value::Number(f64::NAN).into(),
+        ])
+        .into();
+
+        let error = to_string_pretty(&json).unwrap_err();
+        assert_eq!(error.field, "nested[0].x[1]");
+        assert!(error.value.is_nan());
+
+        let infinite = Value::Number(f64::INFINITY).into();
+        assert_eq!(
+            to_string_pretty(&infinite),
+            Err(NumberError {
+                field: String::new(),
+                value: f64::INFINITY
+            })
+        );
+    }
+}
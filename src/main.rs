@@ -1,97 +1,391 @@
-use std::time::SystemTime;
+use std::time::Instant;
 
+use assets::{Assets, SHADER_POLL_INTERVAL};
+use audio::{Audio, CollisionSounds, IMPACT_SOUND};
 use camera::Camera;
-use game::CubeGameState;
-use window_utils::{build_gl_state, handle_window_event, track_user_input};
-use winit::event::{Event, VirtualKeyCode};
+use camera_controller::{
+    CameraController, ControllerSet, Follow, FreeFly, Orbit, Walker, STICK_LOOK_SPEED,
+};
+use cli::Options;
+use components::{Light, PlayerControlled};
+use debug_gui::DebugGui;
+use game::{CubeGameState, Impacts, PlayerInput};
+use game_loop::{Clock, Time};
+use gilrs::Gilrs;
+use input::{Bindings, Input, LOOK_RIGHT, LOOK_UP, QUIT, SWITCH_CAMERA, TOGGLE_GUI};
+use replay::Replay;
+use scene::Scene;
+use skinned_renderer::SkinnedRenderer;
+use terrian::{HeightField, TerrianRenderer, TERRAIN_WIDTH};
+use uniform_buffer::{FrameData, UniformBuffer, FRAME_BINDING};
+use window_utils::{build_gl_state, handle_window_event, track_user_input, WINDOW_TITLE};
+use winit::event::Event;
 use winit::event_loop::EventLoopBuilder;
 
 use glutin::prelude::*;
 
 use crate::colliding_renderer::CubeRenderer;
 
+mod animation;
+mod assets;
+mod audio;
+mod broad_phase;
 mod camera;
+mod camera_controller;
+mod cli;
 mod colliding_renderer;
 mod collision;
+mod components;
+mod convex;
 mod debug_gui;
+mod ecs;
 mod game;
+mod game_loop;
+mod gltf;
+mod headless;
+mod input;
+mod json;
+mod mesh;
+mod physics;
+mod preprocessor;
 mod program;
 mod renderer;
+mod replay;
+mod scene;
 mod shader;
+mod skinned_renderer;
 mod terrian;
 mod texture;
 mod tutorial_renderer;
+mod uniform_buffer;
 mod window_utils;
 
 fn main()
 {
+    let args: Vec<String> = std::env::args().collect();
+    let options = Options::parse(&args).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        std::process::exit(2);
+    });
+    let replay = options.replay.as_ref().map(|path| {
+        Replay::load(path).unwrap_or_else(|error| {
+            eprintln!("{}: {}", path, error);
+            std::process::exit(1);
+        })
+    });
+    // a replay brings the scene it was recorded in
+    let scene_path = replay
+        .as_ref()
+        .map_or(options.scene.as_str(), |replay| replay.scene.as_str());
+    let scene = Scene::load(scene_path).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+
+    let headless_state = match (&replay, options.headless)
+    {
+        (Some(replay), _) =>
+        {
+            let state = replay.play(&scene).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+            headless::write_states(&state, replay.ticks(), &mut std::io::stdout().lock())
+                .expect("can't write body states");
+            Some(state)
+        }
+        (None, Some(ticks)) => Some(
+            headless::run(&scene, ticks, &mut std::io::stdout().lock())
+                .expect("can't write body states"),
+        ),
+        (None, None) => None,
+    };
+    if let Some(state) = headless_state
+    {
+        if let Some(path) = &options.save_scene
+        {
+            state.to_scene(&scene).save(path).expect("can't save scene");
+        }
+        return;
+    }
+
+    let bindings = Bindings::load(&options.bindings).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let mut input = Input::new(bindings);
+    // playing without a gamepad is fine
+    let mut gamepads = Gilrs::new()
+        .map_err(|error| eprintln!("no gamepad support: {}", error))
+        .ok();
+
     let event_loop = EventLoopBuilder::new().build();
 
     let state = build_gl_state(&event_loop);
 
-    let renderer = CubeRenderer::new();
+    let mut assets = Assets::new(&options.asset_root);
+    let renderer = CubeRenderer::new(&mut assets).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    let mut gui = DebugGui::new(&mut assets).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    // and without sound
+    let mut audio = Audio::new()
+        .map_err(|error| eprintln!("no audio: {}", error))
+        .ok();
+    let collision_sounds = assets
+        .sound(IMPACT_SOUND)
+        .map(CollisionSounds::new)
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+
+    let mut terrain = scene.terrain.as_ref().map(|terrain| {
+        TerrianRenderer::new(terrain, &mut assets).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        })
+    });
+
+    let frame_uniforms = unsafe { UniformBuffer::new(FRAME_BINDING) };
+
+    let mut game_state = CubeGameState::from_scene(&scene);
 
-    let mut game_state = CubeGameState::new();
+    let mut skinned_renderer = SkinnedRenderer::new(&mut assets).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    unsafe { skinned_renderer.load_models(&mut assets, &game_state) }.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
 
-    let mut camera = Camera::new();
-    let mut current_time = SystemTime::now();
-    let mut now_keys = [false; 255];
+    let mut camera = Camera::new(&scene.camera);
+    if let Some((_, _, window)) = &state
+    {
+        let size = window.inner_size();
+        camera.resize(size.width, size.height);
+    }
+    camera.reverse_z = unsafe { camera::set_depth_mode(options.reverse_z) };
+    if options.reverse_z && !camera.reverse_z
+    {
+        eprintln!("--reverse-z needs glClipControl, using regular depth");
+    }
+    // the debug gui can flip it, the depth test has to follow
+    let mut depth_reverse_z = camera.reverse_z;
 
-    let dt = 1.0 / 60.0; // 60 fps
+    // the switch_camera action cycles through these
+    let ground =
+        scene
+            .terrain
+            .as_ref()
+            .and_then(|terrain| match assets.image(&terrain.height_map)
+            {
+                Ok(height_map) => Some(HeightField::from_image(&height_map, TERRAIN_WIDTH)),
+                Err(error) =>
+                {
+                    eprintln!("{}", error);
+                    None
+                }
+            });
+    let mut controllers: Vec<Box<dyn CameraController>> = vec![
+        Box::new(FreeFly { speed: 500.0 }),
+        Box::new(Orbit::new(
+            camera.camera_position + camera.front() * 10.0,
+            10.0,
+        )),
+        Box::new(Walker::new(ground)),
+    ];
+    let player = game_state
+        .world
+        .components::<PlayerControlled>()
+        .iter()
+        .map(|(entity, _)| entity)
+        .next();
+    if let Some(player) = player
+    {
+        controllers.push(Box::new(Follow::new(player)));
+    }
+    let mut controllers = ControllerSet::new(controllers);
+    let mut clock = Clock::new();
+    let mut shader_poll = Instant::now();
+    let mut title = WINDOW_TITLE.to_string();
+    let mut recording = options.record.as_ref().map(|_| {
+        let fixed_delta = game_state.world.resource::<Time>().fixed_delta;
+        Replay::new(&options.scene, fixed_delta)
+    });
+    gui.set_editable(recording.is_none());
 
     event_loop.run(move |event, _, control_flow| {
         control_flow.set_poll();
 
-        // time delta is calc here doing the main in the MainEventsCleared processing
-        // really throws off the time values.
-        let new_time = SystemTime::now();
-        let delta_time_duration = new_time
-            .duration_since(current_time)
-            .expect("can't get delta_time");
-        let mut frame_time = delta_time_duration.as_secs_f32();
-        current_time = new_time;
-
         match event
         {
-            Event::DeviceEvent { event, .. } => track_user_input(event, &mut camera, &mut now_keys),
+            Event::DeviceEvent { event, .. } if !gui.blocks(&event) =>
+            {
+                track_user_input(event, &mut camera, controllers.active(), &mut input)
+            }
             Event::WindowEvent { event, .. } =>
             {
-                handle_window_event(event, control_flow, &state, &renderer)
+                if let Some((_, _, window)) = &state
+                {
+                    gui.handle_window_event(window, &event);
+                }
+                input.window_event(&event);
+                handle_window_event(event, control_flow, &state, &renderer, &mut camera)
             }
             // this is the main loop of the game engine!
             Event::MainEventsCleared =>
             {
-                // let try some physic??
-                camera.apply_gravity(frame_time);
+                let frame_time = clock.tick().as_secs_f32();
 
+                if let Some(gamepads) = &mut gamepads
+                {
+                    while let Some(event) = gamepads.next_event()
+                    {
+                        input.gamepad_event(event.event);
+                    }
+                }
+
+                let player_input = PlayerInput::from_input(&input);
+                let steps = game_state.step_frame(frame_time, player_input);
+                if let Some(recording) = &mut recording
+                {
+                    recording.record(frame_time, steps, player_input);
+                }
+                let delta_time = game_state.world.resource::<Time>().delta;
+
+                if camera.reverse_z != depth_reverse_z
+                {
+                    camera.reverse_z = unsafe { camera::set_depth_mode(camera.reverse_z) };
+                    depth_reverse_z = camera.reverse_z;
+                }
                 unsafe {
                     gl::ClearColor(0.2, 0.3, 0.3, 0.7);
                     gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
                 }
 
-                camera.handle_keyboard_input(&now_keys, frame_time);
+                if input.pressed(SWITCH_CAMERA)
+                {
+                    controllers.next();
+                }
+                if let (true, Some((_, _, window))) = (input.pressed(TOGGLE_GUI), &state)
+                {
+                    gui.set_open(window, !gui.is_open());
+                }
+                // sticks turn the camera the way the mouse does, up is positive
+                let look = STICK_LOOK_SPEED * delta_time;
+                let (look_x, look_y) = (input.axis(LOOK_RIGHT), input.axis(LOOK_UP));
+                if look_x != 0.0 || look_y != 0.0
+                {
+                    controllers
+                        .active()
+                        .mouse_motion(&mut camera, look_x * look, -look_y * look);
+                }
+                controllers
+                    .active()
+                    .update(&mut camera, &input, delta_time, &game_state.world);
 
-                game_state.handle_keyboard_input(&now_keys);
+                if let Some(audio) = &mut audio
+                {
+                    audio.set_listener(&camera);
+                    collision_sounds.play(audio, &game_state.world.resource::<Impacts>().0);
+                    audio.update();
+                }
 
-                while frame_time > 0.0
+                if shader_poll.elapsed() >= SHADER_POLL_INTERVAL
                 {
-                    let delta_time = frame_time.min(dt);
-                    frame_time = frame_time - delta_time;
-                    game_state.integrate(delta_time);
+                    shader_poll = Instant::now();
+                    for error in unsafe { assets.reload_shaders() }
+                        .into_iter()
+                        .filter_map(Result::err)
+                    {
+                        eprintln!("{}", error);
+                    }
+                    gui.set_errors(&assets);
                 }
 
-                renderer.draw_state(&game_state, &camera); //
+                // the title names the camera and shows a failing program until every one
+                // of them builds, the overlay has the whole log
+                let mut next_title =
+                    format!("{} - {} camera", WINDOW_TITLE, controllers.active().name());
+                if let Some(error) = assets.errors().map(|(_, error)| error).min()
+                {
+                    next_title.push_str(" - ");
+                    next_title.push_str(error.lines().next().unwrap_or_default());
+                }
+                if next_title != title
+                {
+                    if let Some((_, _, window)) = &state
+                    {
+                        window.set_title(&next_title);
+                    }
+                    title = next_title;
+                }
+
+                let lights: Vec<_> = game_state
+                    .world
+                    .components::<Light>()
+                    .iter()
+                    .map(|(_, light)| *light)
+                    .collect();
+                let elapsed = game_state.world.resource::<Time>().elapsed as f32;
+                let frame = FrameData::new(&camera, elapsed, &lights);
+                unsafe { frame_uniforms.upload(&frame.std140()) };
+
+                if let Some(terrain) = &mut terrain
+                {
+                    terrain.update(&camera);
+                    terrain.draw(&camera);
+                }
+                renderer.draw_state(&game_state); //
+                skinned_renderer.update(&game_state, delta_time);
+                skinned_renderer.draw_state(&game_state);
 
                 if let Some((gl_context, gl_surface, window)) = &state
                 {
+                    let controller = controllers.active().name();
+                    gui.draw(
+                        window,
+                        frame_time,
+                        steps,
+                        &mut camera,
+                        controller,
+                        &game_state.world,
+                    );
                     window.request_redraw();
                     gl_surface.swap_buffers(gl_context).unwrap();
                 }
 
-                if now_keys[VirtualKeyCode::Escape as usize]
+                if input.held(QUIT)
                 {
                     control_flow.set_exit();
                 }
+
+                input.end_frame();
+            }
+            // however the window was closed
+            Event::LoopDestroyed =>
+            {
+                if let Some(path) = &options.save_scene
+                {
+                    if let Err(error) = game_state.to_scene(&scene).save(path)
+                    {
+                        eprintln!("can't save scene to {}: {}", path, error);
+                    }
+                }
+                if let (Some(recording), Some(path)) = (&recording, &options.record)
+                {
+                    match recording.save(path)
+                    {
+                        Ok(()) => println!("recorded {} ticks to {}", recording.ticks(), path),
+                        Err(error) => eprintln!("can't save replay to {}: {}", path, error),
+                    }
+                }
             }
             _ =>
             {}
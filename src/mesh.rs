@@ -0,0 +1,675 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use glm::{vec3, Vec3};
+use thiserror::Error;
+
+use crate::{
+    animation::SkinWeights,
+    program::Program,
+    renderer::{self, IndexBuffer, VertexArray},
+};
+
+// floats per vertex: position, normal, uv
+pub const STRIDE: i32 = 8;
+// then four joint indices and four weights
+pub const SKINNED_STRIDE: i32 = STRIDE + 8;
+// relative to the asset root
+pub const CUBE_OBJ: &str = "meshes/cube.obj";
+
+#[derive(Debug, Error)]
+pub enum MeshError
+{
+    #[error("can't read {path}: {source}")]
+    Io
+    {
+        path: String, source: io::Error
+    },
+    #[error("{file}:{line}: {message}")]
+    Parse
+    {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material
+{
+    pub name: String,
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+    pub diffuse_texture: Option<String>,
+}
+
+impl Material
+{
+    fn new(name: &str) -> Self
+    {
+        Self {
+            name: name.to_string(),
+            ambient: vec3(0.0, 0.0, 0.0),
+            diffuse: vec3(0.8, 0.8, 0.8),
+            specular: vec3(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            diffuse_texture: None,
+        }
+    }
+}
+
+// a run of indices drawn with one material
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submesh
+{
+    pub material: Option<String>,
+    pub first_index: usize,
+    pub index_count: usize,
+}
+
+// a parsed mesh in cpu memory, vertices interleaved as described by STRIDE.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData
+{
+    pub vertices: Vec<f32>,
+    pub indices: Vec<i32>,
+    pub submeshes: Vec<Submesh>,
+    pub materials: Vec<Material>,
+}
+
+impl MeshData
+{
+    // mtllib and map_Kd paths are resolved next to the obj file.
+    pub fn load_obj(path: &str) -> Result<Self, MeshError>
+    {
+        let directory = Path::new(path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let source = read_file(Path::new(path))?;
+        let mut mesh = Self::parse_obj(path, &source, |library| {
+            read_file(&directory.join(library)).map(|source| (directory.join(library), source))
+        })?;
+        for material in mesh.materials.iter_mut()
+        {
+            if let Some(texture) = &material.diffuse_texture
+            {
+                material.diffuse_texture = Some(directory.join(texture).to_string_lossy().into());
+            }
+        }
+        Ok(mesh)
+    }
+
+    // read_mtl is handed every mtllib name and returns the file's path (for
+    // error messages) and contents.
+    pub fn parse_obj(
+        file: &str,
+        source: &str,
+        mut read_mtl: impl FnMut(&str) -> Result<(PathBuf, String), MeshError>,
+    ) -> Result<Self, MeshError>
+    {
+        let mut positions: Vec<Vec3> = vec![];
+        let mut normals: Vec<Vec3> = vec![];
+        let mut uvs: Vec<[f32; 2]> = vec![];
+        let mut mesh = MeshData::default();
+        let mut unique: HashMap<(usize, Option<usize>, Option<usize>), i32> = HashMap::new();
+        // vertices without a normal in the file get the average of the faces
+        // around them
+        let mut generated_normals: HashMap<i32, Vec3> = HashMap::new();
+        let mut material: Option<String> = None;
+
+        for (number, line) in source.lines().enumerate()
+        {
+            let error = |message: String| MeshError::Parse {
+                file: file.to_string(),
+                line: number + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            let Some(keyword) = words.next()
+            else
+            {
+                continue;
+            };
+            let rest: Vec<&str> = words.collect();
+            match keyword
+            {
+                // exporters can add a w or a vertex color, only the position is kept
+                "v" =>
+                {
+                    let v = parse_floats(&rest, 3, 7).map_err(error)?;
+                    positions.push(vec3(v[0], v[1], v[2]));
+                }
+                "vn" => normals.push(parse_vec3(&rest).map_err(error)?),
+                "vt" =>
+                {
+                    let uv = parse_floats(&rest, 2, 3).map_err(error)?;
+                    uvs.push([uv[0], uv[1]]);
+                }
+                "f" =>
+                {
+                    if rest.len() < 3
+                    {
+                        return Err(error(format!(
+                            "a face needs at least 3 vertices, found {}",
+                            rest.len()
+                        )));
+                    }
+                    let mut corners = vec![];
+                    for corner in &rest
+                    {
+                        let key = parse_corner(corner, positions.len(), uvs.len(), normals.len())
+                            .map_err(error)?;
+                        let index = *unique.entry(key).or_insert_with(|| {
+                            let (position, uv, normal) = key;
+                            let p = positions[position];
+                            let n = normal.map_or(vec3(0.0, 0.0, 0.0), |n| normals[n]);
+                            let t = uv.map_or([0.0, 0.0], |t| uvs[t]);
+                            let index = (mesh.vertices.len() / STRIDE as usize) as i32;
+                            mesh.vertices
+                                .extend_from_slice(&[p.x, p.y, p.z, n.x, n.y, n.z, t[0], t[1]]);
+                            index
+                        });
+                        corners.push((index, key));
+                    }
+
+                    // polygons are split into a fan around the first corner
+                    for i in 1..corners.len() - 1
+                    {
+                        let triangle = [corners[0], corners[i], corners[i + 1]];
+                        let [a, b, c] = triangle.map(|(_, (position, _, _))| positions[position]);
+                        // not normalized, so bigger faces count for more
+                        let face_normal = glm::cross(b - a, c - a);
+                        for (index, (_, _, normal)) in triangle
+                        {
+                            mesh.indices.push(index);
+                            if normal.is_none()
+                            {
+                                let sum = generated_normals
+                                    .entry(index)
+                                    .or_insert(vec3(0.0, 0.0, 0.0));
+                                *sum = *sum + face_normal;
+                            }
+                        }
+                    }
+                    push_submesh(&mut mesh, &material);
+                }
+                "usemtl" =>
+                {
+                    let name = rest.join(" ");
+                    if !mesh.materials.iter().any(|material| material.name == name)
+                    {
+                        return Err(error(format!("material `{}` was never defined", name)));
+                    }
+                    material = Some(name);
+                }
+                "mtllib" =>
+                {
+                    for library in &rest
+                    {
+                        let (path, source) = read_mtl(library)?;
+                        mesh.materials
+                            .extend(parse_mtl(&path.to_string_lossy(), &source)?);
+                    }
+                }
+                // groups, objects and smoothing groups don't change the layout, and
+                // lines, curves and the rest aren't drawn
+                _ =>
+                {}
+            }
+        }
+
+        for (index, normal) in generated_normals
+        {
+            let normal = if glm::length(normal) > 0.0
+            {
+                glm::normalize(normal)
+            }
+            else
+            {
+                normal
+            };
+            let start = index as usize * STRIDE as usize + 3;
+            mesh.vertices[start..start + 3].copy_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+
+        Ok(mesh)
+    }
+
+    pub fn vertex_count(&self) -> usize
+    {
+        self.vertices.len() / STRIDE as usize
+    }
+
+    // replaces every normal with the area weighted average of the faces
+    // around the vertex, for formats where normals are optional.
+    pub fn generate_normals(&mut self)
+    {
+        let stride = STRIDE as usize;
+        let position = |vertices: &[f32], index: i32| {
+            let start = index as usize * stride;
+            vec3(vertices[start], vertices[start + 1], vertices[start + 2])
+        };
+        let mut sums = vec![vec3(0.0, 0.0, 0.0); self.vertex_count()];
+        for triangle in self.indices.chunks_exact(3)
+        {
+            let [a, b, c] = [0, 1, 2].map(|i| position(&self.vertices, triangle[i]));
+            let face_normal = glm::cross(b - a, c - a);
+            for index in triangle
+            {
+                sums[*index as usize] = sums[*index as usize] + face_normal;
+            }
+        }
+        for (i, sum) in sums.into_iter().enumerate()
+        {
+            let normal = if glm::length(sum) > 0.0
+            {
+                glm::normalize(sum)
+            }
+            else
+            {
+                sum
+            };
+            self.vertices[i * stride + 3..i * stride + 6]
+                .copy_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+    }
+}
+
+pub fn parse_mtl(
+    file: &str,
+    source: &str,
+) -> Result<Vec<Material>, MeshError>
+{
+    let mut materials: Vec<Material> = vec![];
+    for (number, line) in source.lines().enumerate()
+    {
+        let error = |message: String| MeshError::Parse {
+            file: file.to_string(),
+            line: number + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next()
+        else
+        {
+            continue;
+        };
+        let rest: Vec<&str> = words.collect();
+        if keyword == "newmtl"
+        {
+            materials.push(Material::new(&rest.join(" ")));
+            continue;
+        }
+        let Some(material) = materials.last_mut()
+        else
+        {
+            return Err(error(format!("`{}` before any newmtl", keyword)));
+        };
+        match keyword
+        {
+            "Ka" => material.ambient = parse_vec3(&rest).map_err(error)?,
+            "Kd" => material.diffuse = parse_vec3(&rest).map_err(error)?,
+            "Ks" => material.specular = parse_vec3(&rest).map_err(error)?,
+            "Ns" => material.shininess = parse_floats(&rest, 1, 1).map_err(error)?[0],
+            // the last word is the file, anything before it is map options
+            "map_Kd" =>
+            {
+                material.diffuse_texture = Some(
+                    rest.last()
+                        .ok_or_else(|| error("map_Kd needs a file name".to_string()))?
+                        .to_string(),
+                )
+            }
+            // the rest of the mtl spec isn't used by the renderers
+            _ =>
+            {}
+        }
+    }
+    Ok(materials)
+}
+
+fn read_file(path: &Path) -> Result<String, MeshError>
+{
+    fs::read_to_string(path).map_err(|source| MeshError::Io {
+        path: path.to_string_lossy().into(),
+        source,
+    })
+}
+
+fn push_submesh(
+    mesh: &mut MeshData,
+    material: &Option<String>,
+)
+{
+    let end = mesh.indices.len();
+    match mesh.submeshes.last_mut()
+    {
+        Some(last) if last.material == *material => last.index_count = end - last.first_index,
+        last =>
+        {
+            let first_index = last.map_or(0, |last| last.first_index + last.index_count);
+            mesh.submeshes.push(Submesh {
+                material: material.clone(),
+                first_index,
+                index_count: end - first_index,
+            });
+        }
+    }
+}
+
+fn parse_floats(
+    words: &[&str],
+    min: usize,
+    max: usize,
+) -> Result<Vec<f32>, String>
+{
+    if words.len() < min || words.len() > max
+    {
+        return Err(format!(
+            "expected {} numbers, found {}",
+            if min == max
+            {
+                min.to_string()
+            }
+            else
+            {
+                format!("{} to {}", min, max)
+            },
+            words.len()
+        ));
+    }
+    words
+        .iter()
+        .map(|word| {
+            word.parse()
+                .map_err(|_| format!("invalid number `{}`", word))
+        })
+        .collect()
+}
+
+fn parse_vec3(words: &[&str]) -> Result<Vec3, String>
+{
+    let v = parse_floats(words, 3, 3)?;
+    Ok(vec3(v[0], v[1], v[2]))
+}
+
+// "v", "v/vt", "v//vn" or "v/vt/vn", 1-based or negative from the end.
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), String>
+{
+    let resolve = |text: &str, count: usize, what: &str| -> Result<usize, String> {
+        let index: i64 = text
+            .parse()
+            .map_err(|_| format!("invalid {} index `{}` in `{}`", what, text, corner))?;
+        let resolved = if index < 0
+        {
+            count as i64 + index
+        }
+        else
+        {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64
+        {
+            return Err(format!(
+                "{} index {} is out of range, there are {}",
+                what, index, count
+            ));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut parts = corner.split('/');
+    let position = resolve(parts.next().unwrap_or_default(), position_count, "position")?;
+    let uv = match parts.next()
+    {
+        Some("") | None => None,
+        Some(text) => Some(resolve(text, uv_count, "uv")?),
+    };
+    let normal = match parts.next()
+    {
+        Some("") | None => None,
+        Some(text) => Some(resolve(text, normal_count, "normal")?),
+    };
+    if parts.next().is_some()
+    {
+        return Err(format!("too many `/` in `{}`", corner));
+    }
+    Ok((position, uv, normal))
+}
+
+// a mesh uploaded to the gpu, attribute 0 is the position, 1 the normal and 2
+// the uv.
+pub struct Mesh
+{
+    pub vertex_array: VertexArray,
+    pub index_buffer: IndexBuffer,
+}
+
+impl Mesh
+{
+    pub unsafe fn new(data: &MeshData) -> Self
+    {
+        let mut vertex_array = VertexArray::new(&data.vertices, STRIDE);
+        vertex_array.add_vert_att_ptr(3);
+        vertex_array.add_vert_att_ptr(3);
+        vertex_array.add_vert_att_ptr(2);
+        let index_buffer = IndexBuffer::new(&data.indices);
+
+        Self {
+            vertex_array,
+            index_buffer,
+        }
+    }
+
+    // a mesh for the skinning shader, attribute 3 holds the joint indices and
+    // 4 their weights.
+    pub unsafe fn new_skinned(
+        data: &MeshData,
+        skin: &SkinWeights,
+    ) -> Self
+    {
+        let mut vertices = Vec::with_capacity(data.vertex_count() * SKINNED_STRIDE as usize);
+        for (i, vertex) in data.vertices.chunks_exact(STRIDE as usize).enumerate()
+        {
+            vertices.extend_from_slice(vertex);
+            vertices.extend_from_slice(&skin.joints[i]);
+            vertices.extend_from_slice(&skin.weights[i]);
+        }
+        let mut vertex_array = VertexArray::new(&vertices, SKINNED_STRIDE);
+        for size in [3, 3, 2, 4, 4]
+        {
+            vertex_array.add_vert_att_ptr(size);
+        }
+        let index_buffer = IndexBuffer::new(&data.indices);
+
+        Self {
+            vertex_array,
+            index_buffer,
+        }
+    }
+
+    pub unsafe fn draw(
+        &self,
+        program: &Program,
+    )
+    {
+        renderer::draw(&self.vertex_array, &self.index_buffer, program);
+    }
+}
+
+impl Drop for Mesh
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vertex_array.vbo);
+            gl::DeleteBuffers(1, &self.index_buffer.id);
+            gl::DeleteVertexArrays(1, &self.vertex_array.vao);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::path::PathBuf;
+
+    use glm::vec3;
+
+    use super::{parse_mtl, MeshData, MeshError, CUBE_OBJ, STRIDE};
+    use crate::assets::ASSET_ROOT;
+
+    fn parse(source: &str) -> Result<MeshData, MeshError>
+    {
+        MeshData::parse_obj("test.obj", source, |library| {
+            Ok((
+                PathBuf::from(library),
+                "newmtl red\nKd 1 0 0\nNs 32\nmap_Kd -s 1 1 1 red.png\n\nnewmtl blue\nKd 0 0 1\n"
+                    .to_string(),
+            ))
+        })
+    }
+
+    fn vertex(
+        mesh: &MeshData,
+        index: i32,
+    ) -> &[f32]
+    {
+        let start = index as usize * STRIDE as usize;
+        &mesh.vertices[start..start + STRIDE as usize]
+    }
+
+    #[test]
+    fn test_cube_file_dedups_to_24_vertices()
+    {
+        let path = format!("{}/{}", ASSET_ROOT, CUBE_OBJ);
+        let mesh = MeshData::load_obj(&path).unwrap();
+        assert_eq!(mesh.vertex_count(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        assert_eq!(mesh.materials.len(), 1);
+
+        // every triangle winds counter clockwise seen from outside
+        for triangle in mesh.indices.chunks(3)
+        {
+            let [a, b, c] = [0, 1, 2].map(|i| {
+                let v = vertex(&mesh, triangle[i]);
+                vec3(v[0], v[1], v[2])
+            });
+            let normal = vertex(&mesh, triangle[0]);
+            let winding = glm::cross(b - a, c - a);
+            assert!(glm::dot(winding, vec3(normal[3], normal[4], normal[5])) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_shared_corners_are_reused()
+    {
+        let mesh = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\nf 1//1 3//1 4//1\n",
+        )
+        .unwrap();
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn test_quads_and_negative_indices()
+    {
+        let mesh =
+            parse("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0.5 0.25\nf -4/1 -3/1 -2/1 -1/1\n")
+                .unwrap();
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(&vertex(&mesh, 1)[6..], &[0.5, 0.25]);
+    }
+
+    #[test]
+    fn test_missing_normals_are_generated()
+    {
+        let mesh = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 4\n").unwrap();
+        // corners 1 and 2 touch a face facing -z and one facing -y, corner 3
+        // only the first
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        for (index, expected) in [
+            (0, vec3(0.0, -h, -h)),
+            (2, vec3(0.0, -h, -h)),
+            (1, vec3(0.0, 0.0, -1.0)),
+        ]
+        {
+            let n = vertex(&mesh, index);
+            assert!(glm::length(vec3(n[3], n[4], n[5]) - expected) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_materials_split_submeshes()
+    {
+        let mesh = parse(
+            "mtllib colors.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl red\nf 1 2 3\nf 3 2 1\nusemtl blue\nf 1 3 2\n",
+        )
+        .unwrap();
+        assert_eq!(mesh.submeshes.len(), 2);
+        assert_eq!(mesh.submeshes[0].material.as_deref(), Some("red"));
+        assert_eq!(
+            (mesh.submeshes[0].first_index, mesh.submeshes[0].index_count),
+            (0, 6)
+        );
+        assert_eq!(
+            (mesh.submeshes[1].first_index, mesh.submeshes[1].index_count),
+            (6, 3)
+        );
+        assert_eq!(mesh.materials[0].diffuse, vec3(1.0, 0.0, 0.0));
+        assert_eq!(mesh.materials[0].shininess, 32.0);
+        assert_eq!(
+            mesh.materials[0].diffuse_texture.as_deref(),
+            Some("red.png")
+        );
+    }
+
+    #[test]
+    fn test_extra_vertex_values_and_statements_are_skipped()
+    {
+        let mesh =
+            parse("v 0 0 0 1\nv 1 0 0 0.5 0.5 0.5\nv 0 1 0\nvp 1 2\nl 1 2\nf 1 2 3\n").unwrap();
+        assert_eq!(mesh.vertex_count(), 3);
+        assert_eq!(&vertex(&mesh, 1)[..3], &[1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_errors_name_the_line()
+    {
+        let error = |source: &str| parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("v 0 0 0\nv 1 0\n"),
+            "test.obj:2: expected 3 to 7 numbers, found 2"
+        );
+        assert_eq!(
+            error("v 0 0 0\nf 1 2 3\n"),
+            "test.obj:2: position index 2 is out of range, there are 1"
+        );
+        assert_eq!(
+            error("v 0 0 0\nf 1 1\n"),
+            "test.obj:2: a face needs at least 3 vertices, found 2"
+        );
+        assert_eq!(
+            error("v 0 0 0\nusemtl green\nf 1 1 1\n"),
+            "test.obj:2: material `green` was never defined"
+        );
+        assert_eq!(
+            parse_mtl("a.mtl", "Kd 1 1 1\n").unwrap_err().to_string(),
+            "a.mtl:1: `Kd` before any newmtl"
+        );
+    }
+}
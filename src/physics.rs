@@ -0,0 +1,977 @@
+use std::{collections::HashMap, ops::Mul};
+
+use glm::{mat3, mat4, vec3, Mat3, Mat4, Vec3};
+
+use crate::{
+    broad_phase::{Aabb, SweepAndPrune},
+    collision::{self, ContactPoint, Obb},
+};
+
+pub const DEFAULT_TIME_STEP: f32 = 1.0 / 60.0;
+pub const DEFAULT_GRAVITY: Vec3 = glm::Vector3 {
+    x: 0.0,
+    y: -9.81,
+    z: 0.0,
+};
+
+// how far bodies may sink into each other before the solver starts pushing
+// them apart, keeps resting contacts from flickering in and out.
+const PENETRATION_SLOP: f32 = 0.01;
+// fraction of the remaining penetration corrected every step.
+const BAUMGARTE: f32 = 0.2;
+// closing speeds below this don't bounce, otherwise resting bodies never
+// settle.
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat
+{
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quat
+{
+    pub fn identity() -> Self
+    {
+        Self {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    // angle is in radians
+    pub fn from_axis_angle(
+        axis: Vec3,
+        angle: f32,
+    ) -> Self
+    {
+        let axis = glm::normalize(axis);
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Self {
+            w: cos,
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+        }
+    }
+
+    // the rotation of an orthonormal matrix, picking whichever of w, x, y or z
+    // is largest to divide by so it stays stable near 180 degree turns.
+    pub fn from_mat3(m: Mat3) -> Self
+    {
+        let (m00, m01, m02) = (m.c0.x, m.c1.x, m.c2.x);
+        let (m10, m11, m12) = (m.c0.y, m.c1.y, m.c2.y);
+        let (m20, m21, m22) = (m.c0.z, m.c1.z, m.c2.z);
+        let trace = m00 + m11 + m22;
+        let q = if trace > 0.0
+        {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self {
+                w: 0.25 * s,
+                x: (m21 - m12) / s,
+                y: (m02 - m20) / s,
+                z: (m10 - m01) / s,
+            }
+        }
+        else if m00 > m11 && m00 > m22
+        {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Self {
+                w: (m21 - m12) / s,
+                x: 0.25 * s,
+                y: (m01 + m10) / s,
+                z: (m02 + m20) / s,
+            }
+        }
+        else if m11 > m22
+        {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Self {
+                w: (m02 - m20) / s,
+                x: (m01 + m10) / s,
+                y: 0.25 * s,
+                z: (m12 + m21) / s,
+            }
+        }
+        else
+        {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Self {
+                w: (m10 - m01) / s,
+                x: (m02 + m20) / s,
+                y: (m12 + m21) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.normalize()
+    }
+
+    pub fn normalize(self) -> Self
+    {
+        let length = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if length == 0.0
+        {
+            return Self::identity();
+        }
+        Self {
+            w: self.w / length,
+            x: self.x / length,
+            y: self.y / length,
+            z: self.z / length,
+        }
+    }
+
+    pub fn rotate(
+        &self,
+        v: Vec3,
+    ) -> Vec3
+    {
+        let q = vec3(self.x, self.y, self.z);
+        let t = glm::cross(q, v) * 2.0;
+        v + t * self.w + glm::cross(q, t)
+    }
+
+    pub fn to_mat3(self) -> Mat3
+    {
+        let Self { w, x, y, z } = self;
+        #[rustfmt::skip]
+        let rotation = mat3(
+            1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z),       2.0 * (x * z - w * y),
+            2.0 * (x * y - w * z),       1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x),
+            2.0 * (x * z + w * y),       2.0 * (y * z - w * x),       1.0 - 2.0 * (x * x + y * y),
+        );
+        rotation
+    }
+
+    pub fn to_mat4(self) -> Mat4
+    {
+        let r = self.to_mat3();
+        #[rustfmt::skip]
+        let rotation = mat4(
+            r.c0.x, r.c0.y, r.c0.z, 0.0,
+            r.c1.x, r.c1.y, r.c1.z, 0.0,
+            r.c2.x, r.c2.y, r.c2.z, 0.0,
+            0.0,    0.0,    0.0,    1.0,
+        );
+        rotation
+    }
+
+    // normalized lerp, close enough to slerp for the small angles between two
+    // physics steps. takes the short way round.
+    pub fn nlerp(
+        self,
+        other: Quat,
+        t: f32,
+    ) -> Self
+    {
+        let dot = self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z;
+        let other_t = if dot < 0.0 { -t } else { t };
+        Self {
+            w: self.w * (1.0 - t) + other.w * other_t,
+            x: self.x * (1.0 - t) + other.x * other_t,
+            y: self.y * (1.0 - t) + other.y * other_t,
+            z: self.z * (1.0 - t) + other.z * other_t,
+        }
+        .normalize()
+    }
+
+    // constant speed interpolation for keyframes that can be far apart, falls
+    // back to nlerp when the two are nearly the same.
+    pub fn slerp(
+        self,
+        other: Quat,
+        t: f32,
+    ) -> Self
+    {
+        let dot = self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z;
+        if dot.abs() > 0.9995
+        {
+            return self.nlerp(other, t);
+        }
+        let angle = dot.abs().acos();
+        let a = ((1.0 - t) * angle).sin() / angle.sin();
+        let b = (t * angle).sin() / angle.sin() * dot.signum();
+        Self {
+            w: self.w * a + other.w * b,
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+        }
+        .normalize()
+    }
+
+    // advances the orientation by an angular velocity (radians per second)
+    // over delta_time, dq/dt = 0.5 * w * q
+    pub fn integrate(
+        self,
+        angular_velocity: Vec3,
+        delta_time: f32,
+    ) -> Self
+    {
+        let spin = Quat {
+            w: 0.0,
+            x: angular_velocity.x,
+            y: angular_velocity.y,
+            z: angular_velocity.z,
+        } * self;
+        let half_dt = 0.5 * delta_time;
+        Self {
+            w: self.w + spin.w * half_dt,
+            x: self.x + spin.x * half_dt,
+            y: self.y + spin.y * half_dt,
+            z: self.z + spin.z * half_dt,
+        }
+        .normalize()
+    }
+}
+
+impl Mul for Quat
+{
+    type Output = Quat;
+
+    fn mul(
+        self,
+        rhs: Quat,
+    ) -> Quat
+    {
+        Quat {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BodyHandle(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct RigidBody
+{
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub linear_velocity: Vec3,
+    pub angular_velocity: Vec3,
+    // applied every step until changed
+    pub force: Vec3,
+    pub torque: Vec3,
+    pub mass: f32,
+    pub inverse_mass: f32,
+    // both tensors are in body space
+    pub inertia_tensor: Mat3,
+    pub inverse_inertia_tensor: Mat3,
+    pub half_extents: Vec3,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl RigidBody
+{
+    // a mass of zero makes the body static.
+    pub fn new_box(
+        position: Vec3,
+        half_extents: Vec3,
+        mass: f32,
+    ) -> Self
+    {
+        let zero = mat3(0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let mut body = Self {
+            position,
+            orientation: Quat::identity(),
+            linear_velocity: vec3(0.0, 0.0, 0.0),
+            angular_velocity: vec3(0.0, 0.0, 0.0),
+            force: vec3(0.0, 0.0, 0.0),
+            torque: vec3(0.0, 0.0, 0.0),
+            mass: 0.0,
+            inverse_mass: 0.0,
+            inertia_tensor: zero,
+            inverse_inertia_tensor: zero,
+            half_extents,
+            restitution: 0.2,
+            friction: 0.5,
+        };
+        body.set_mass(mass);
+        body
+    }
+
+    // the inertia follows, as for a solid box. zero or less makes it static.
+    pub fn set_mass(
+        &mut self,
+        mass: f32,
+    )
+    {
+        let zero = mat3(0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+        let (inverse_mass, inertia_tensor, inverse_inertia_tensor) = if mass > 0.0
+        {
+            let size = self.half_extents * 2.0;
+            let ix = mass / 12.0 * (size.y * size.y + size.z * size.z);
+            let iy = mass / 12.0 * (size.x * size.x + size.z * size.z);
+            let iz = mass / 12.0 * (size.x * size.x + size.y * size.y);
+            (
+                1.0 / mass,
+                mat3(ix, 0.0, 0.0, 0.0, iy, 0.0, 0.0, 0.0, iz),
+                mat3(1.0 / ix, 0.0, 0.0, 0.0, 1.0 / iy, 0.0, 0.0, 0.0, 1.0 / iz),
+            )
+        }
+        else
+        {
+            (0.0, zero, zero)
+        };
+        self.mass = mass.max(0.0);
+        self.inverse_mass = inverse_mass;
+        self.inertia_tensor = inertia_tensor;
+        self.inverse_inertia_tensor = inverse_inertia_tensor;
+    }
+
+    pub fn new_static_box(
+        position: Vec3,
+        half_extents: Vec3,
+    ) -> Self
+    {
+        Self::new_box(position, half_extents, 0.0)
+    }
+
+    pub fn is_static(&self) -> bool
+    {
+        self.inverse_mass == 0.0
+    }
+
+    pub fn inverse_inertia_world(&self) -> Mat3
+    {
+        let rotation = self.orientation.to_mat3();
+        rotation * self.inverse_inertia_tensor * glm::transpose(&rotation)
+    }
+
+    pub fn velocity_at(
+        &self,
+        point: Vec3,
+    ) -> Vec3
+    {
+        self.linear_velocity + glm::cross(self.angular_velocity, point - self.position)
+    }
+
+    // the box's local x, y and z axes in world space
+    pub fn axes(&self) -> [Vec3; 3]
+    {
+        let rotation = self.orientation.to_mat3();
+        [rotation.c0, rotation.c1, rotation.c2]
+    }
+
+    pub fn obb(&self) -> Obb
+    {
+        Obb {
+            center: self.position,
+            axes: self.axes(),
+            half_extents: self.half_extents,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Contact
+{
+    pub body_a: BodyHandle,
+    pub body_b: BodyHandle,
+    // points from body_a towards body_b
+    pub normal: Vec3,
+    pub points: Vec<ContactPoint>,
+    // how fast the bodies were closing along the normal before the solver
+    // stopped them, 0 if they weren't
+    pub approach_speed: f32,
+}
+
+// per contact point solver state, rebuilt every step.
+struct ContactConstraint
+{
+    body_a: usize,
+    body_b: usize,
+    feature: u64,
+    normal: Vec3,
+    tangents: [Vec3; 2],
+    r_a: Vec3,
+    r_b: Vec3,
+    // orientations don't change while the solver runs, so the world space
+    // inertia is computed once per step instead of once per impulse
+    inverse_mass_a: f32,
+    inverse_mass_b: f32,
+    inverse_inertia_a: Mat3,
+    inverse_inertia_b: Mat3,
+    normal_mass: f32,
+    tangent_mass: [f32; 2],
+    target_velocity: f32,
+    friction: f32,
+    normal_impulse: f32,
+    tangent_impulse: [f32; 2],
+}
+
+#[derive(Debug)]
+pub struct PhysicsWorld
+{
+    bodies: Vec<RigidBody>,
+    // proxy ids match body indices
+    broad_phase: SweepAndPrune,
+    contacts: Vec<Contact>,
+    // accumulated (normal, tangent) impulses from the last step, keyed by
+    // body pair and contact feature
+    impulse_cache: HashMap<(usize, usize, u64), (f32, [f32; 2])>,
+    pub gravity: Vec3,
+    pub time_step: f32,
+    pub solver_iterations: usize,
+}
+
+impl PhysicsWorld
+{
+    pub fn new() -> Self
+    {
+        Self {
+            bodies: vec![],
+            broad_phase: SweepAndPrune::new(),
+            contacts: vec![],
+            impulse_cache: HashMap::new(),
+            gravity: DEFAULT_GRAVITY,
+            time_step: DEFAULT_TIME_STEP,
+            solver_iterations: 10,
+        }
+    }
+
+    pub fn add_body(
+        &mut self,
+        body: RigidBody,
+    ) -> BodyHandle
+    {
+        self.broad_phase.insert(Aabb::from_obb(&body.obb()));
+        self.bodies.push(body);
+        BodyHandle(self.bodies.len() - 1)
+    }
+
+    pub fn body(
+        &self,
+        handle: BodyHandle,
+    ) -> &RigidBody
+    {
+        &self.bodies[handle.0]
+    }
+
+    pub fn body_mut(
+        &mut self,
+        handle: BodyHandle,
+    ) -> &mut RigidBody
+    {
+        &mut self.bodies[handle.0]
+    }
+
+    pub fn bodies(&self) -> &[RigidBody]
+    {
+        &self.bodies
+    }
+
+    // contacts found during the last fixed step
+    pub fn contacts(&self) -> &[Contact]
+    {
+        &self.contacts
+    }
+
+    pub fn step_fixed(&mut self)
+    {
+        let dt = self.time_step;
+
+        // semi-implicit euler, velocities first...
+        for body in self.bodies.iter_mut().filter(|body| !body.is_static())
+        {
+            body.linear_velocity =
+                body.linear_velocity + (self.gravity + body.force * body.inverse_mass) * dt;
+            body.angular_velocity =
+                body.angular_velocity + body.inverse_inertia_world() * body.torque * dt;
+        }
+
+        self.contacts = self.find_contacts();
+        let mut constraints = self.prepare_constraints(dt);
+        let mut velocities: Vec<Velocity> = self
+            .bodies
+            .iter()
+            .map(|body| Velocity {
+                linear: body.linear_velocity,
+                angular: body.angular_velocity,
+            })
+            .collect();
+        for constraint in &constraints
+        {
+            warm_start(constraint, &mut velocities);
+        }
+        for _ in 0..self.solver_iterations
+        {
+            for constraint in constraints.iter_mut()
+            {
+                solve_constraint(constraint, &mut velocities);
+            }
+        }
+        for (body, velocity) in self.bodies.iter_mut().zip(velocities)
+        {
+            body.linear_velocity = velocity.linear;
+            body.angular_velocity = velocity.angular;
+        }
+        self.impulse_cache = constraints
+            .iter()
+            .map(|constraint| {
+                (
+                    (constraint.body_a, constraint.body_b, constraint.feature),
+                    (constraint.normal_impulse, constraint.tangent_impulse),
+                )
+            })
+            .collect();
+
+        // ...then positions with the corrected velocities
+        for body in self.bodies.iter_mut().filter(|body| !body.is_static())
+        {
+            body.position = body.position + body.linear_velocity * dt;
+            body.orientation = body.orientation.integrate(body.angular_velocity, dt);
+        }
+    }
+
+    fn find_contacts(&mut self) -> Vec<Contact>
+    {
+        for (i, body) in self.bodies.iter().enumerate()
+        {
+            self.broad_phase.update(i, Aabb::from_obb(&body.obb()));
+        }
+
+        let mut contacts = vec![];
+        for (i, j) in self.broad_phase.pairs()
+        {
+            let (a, b) = (&self.bodies[i], &self.bodies[j]);
+            if a.is_static() && b.is_static()
+            {
+                continue;
+            }
+            if let Some(manifold) = collision::test_obb_collision(&a.obb(), &b.obb())
+            {
+                let approach_speed = manifold
+                    .points
+                    .iter()
+                    .map(|point| {
+                        let relative =
+                            b.velocity_at(point.position) - a.velocity_at(point.position);
+                        -glm::dot(relative, manifold.normal)
+                    })
+                    .fold(0.0, f32::max);
+                contacts.push(Contact {
+                    body_a: BodyHandle(i),
+                    body_b: BodyHandle(j),
+                    normal: manifold.normal,
+                    points: manifold.points,
+                    approach_speed,
+                });
+            }
+        }
+        contacts
+    }
+
+    fn prepare_constraints(
+        &self,
+        dt: f32,
+    ) -> Vec<ContactConstraint>
+    {
+        let mut constraints = vec![];
+        for contact in &self.contacts
+        {
+            let a = &self.bodies[contact.body_a.0];
+            let b = &self.bodies[contact.body_b.0];
+            let normal = contact.normal;
+            let tangents = tangent_basis(normal);
+            let restitution = a.restitution.max(b.restitution);
+            let friction = (a.friction * b.friction).sqrt();
+            let inverse_inertia_a = a.inverse_inertia_world();
+            let inverse_inertia_b = b.inverse_inertia_world();
+            let effective_mass = |r_a: Vec3, r_b: Vec3, direction: Vec3| {
+                let angular_a = glm::cross(inverse_inertia_a * glm::cross(r_a, direction), r_a);
+                let angular_b = glm::cross(inverse_inertia_b * glm::cross(r_b, direction), r_b);
+                let k =
+                    a.inverse_mass + b.inverse_mass + glm::dot(angular_a + angular_b, direction);
+                if k > 0.0
+                {
+                    1.0 / k
+                }
+                else
+                {
+                    0.0
+                }
+            };
+
+            for point in &contact.points
+            {
+                let r_a = point.position - a.position;
+                let r_b = point.position - b.position;
+
+                let relative_velocity =
+                    b.velocity_at(point.position) - a.velocity_at(point.position);
+                let closing_velocity = glm::dot(relative_velocity, normal);
+
+                let mut target_velocity =
+                    BAUMGARTE / dt * (point.penetration - PENETRATION_SLOP).max(0.0);
+                if closing_velocity < -RESTITUTION_THRESHOLD
+                {
+                    target_velocity = target_velocity.max(-restitution * closing_velocity);
+                }
+
+                let (normal_impulse, tangent_impulse) = self
+                    .impulse_cache
+                    .get(&(contact.body_a.0, contact.body_b.0, point.feature))
+                    .copied()
+                    .unwrap_or((0.0, [0.0, 0.0]));
+
+                constraints.push(ContactConstraint {
+                    body_a: contact.body_a.0,
+                    body_b: contact.body_b.0,
+                    feature: point.feature,
+                    normal,
+                    tangents,
+                    r_a,
+                    r_b,
+                    inverse_mass_a: a.inverse_mass,
+                    inverse_mass_b: b.inverse_mass,
+                    inverse_inertia_a,
+                    inverse_inertia_b,
+                    normal_mass: effective_mass(r_a, r_b, normal),
+                    tangent_mass: [
+                        effective_mass(r_a, r_b, tangents[0]),
+                        effective_mass(r_a, r_b, tangents[1]),
+                    ],
+                    target_velocity,
+                    friction,
+                    normal_impulse,
+                    tangent_impulse,
+                });
+            }
+        }
+        constraints
+    }
+}
+
+// the solver only touches velocities, working on a packed copy of them
+// keeps the inner loop out of the rest of the body data.
+#[derive(Debug, Clone, Copy)]
+struct Velocity
+{
+    linear: Vec3,
+    angular: Vec3,
+}
+
+// reapplies last step's impulses so the solver starts close to the answer,
+// without it stacks need far more iterations to stop jittering.
+fn warm_start(
+    constraint: &ContactConstraint,
+    velocities: &mut [Velocity],
+)
+{
+    let impulse = constraint.normal * constraint.normal_impulse
+        + constraint.tangents[0] * constraint.tangent_impulse[0]
+        + constraint.tangents[1] * constraint.tangent_impulse[1];
+    let mut a = velocities[constraint.body_a];
+    let mut b = velocities[constraint.body_b];
+    apply_pair_impulse(constraint, &mut a, &mut b, impulse);
+    velocities[constraint.body_a] = a;
+    velocities[constraint.body_b] = b;
+}
+
+fn solve_constraint(
+    constraint: &mut ContactConstraint,
+    velocities: &mut [Velocity],
+)
+{
+    let mut a = velocities[constraint.body_a];
+    let mut b = velocities[constraint.body_b];
+    let relative_velocity = |a: &Velocity, b: &Velocity| {
+        (b.linear + glm::cross(b.angular, constraint.r_b))
+            - (a.linear + glm::cross(a.angular, constraint.r_a))
+    };
+
+    // friction is bounded by the normal impulse from the previous iteration
+    for i in 0..2
+    {
+        let tangent = constraint.tangents[i];
+        let velocity = glm::dot(relative_velocity(&a, &b), tangent);
+        let max_friction = constraint.friction * constraint.normal_impulse;
+        let old_impulse = constraint.tangent_impulse[i];
+        let new_impulse = (old_impulse - velocity * constraint.tangent_mass[i])
+            .clamp(-max_friction, max_friction);
+        constraint.tangent_impulse[i] = new_impulse;
+        apply_pair_impulse(
+            constraint,
+            &mut a,
+            &mut b,
+            tangent * (new_impulse - old_impulse),
+        );
+    }
+
+    let velocity = glm::dot(relative_velocity(&a, &b), constraint.normal);
+    let old_impulse = constraint.normal_impulse;
+    let new_impulse =
+        (old_impulse + (constraint.target_velocity - velocity) * constraint.normal_mass).max(0.0);
+    constraint.normal_impulse = new_impulse;
+    apply_pair_impulse(
+        constraint,
+        &mut a,
+        &mut b,
+        constraint.normal * (new_impulse - old_impulse),
+    );
+
+    velocities[constraint.body_a] = a;
+    velocities[constraint.body_b] = b;
+}
+
+// impulse is applied positively to body_b and negatively to body_a
+fn apply_pair_impulse(
+    constraint: &ContactConstraint,
+    a: &mut Velocity,
+    b: &mut Velocity,
+    impulse: Vec3,
+)
+{
+    a.linear = a.linear - impulse * constraint.inverse_mass_a;
+    a.angular = a.angular - constraint.inverse_inertia_a * glm::cross(constraint.r_a, impulse);
+    b.linear = b.linear + impulse * constraint.inverse_mass_b;
+    b.angular = b.angular + constraint.inverse_inertia_b * glm::cross(constraint.r_b, impulse);
+}
+
+fn tangent_basis(normal: Vec3) -> [Vec3; 2]
+{
+    let helper = if normal.x.abs() > 0.57
+    {
+        vec3(0.0, 1.0, 0.0)
+    }
+    else
+    {
+        vec3(1.0, 0.0, 0.0)
+    };
+    let first = glm::normalize(glm::cross(normal, helper));
+    let second = glm::cross(normal, first);
+    [first, second]
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec3;
+
+    use super::{PhysicsWorld, Quat, RigidBody};
+
+    fn world_with_floor() -> PhysicsWorld
+    {
+        let mut world = PhysicsWorld::new();
+        world.add_body(RigidBody::new_static_box(
+            vec3(0.0, -0.5, 0.0),
+            vec3(10.0, 0.5, 10.0),
+        ));
+        world
+    }
+
+    #[test]
+    fn test_free_fall_is_semi_implicit()
+    {
+        let mut world = PhysicsWorld::new();
+        let handle = world.add_body(RigidBody::new_box(
+            vec3(0.0, 0.0, 0.0),
+            vec3(0.5, 0.5, 0.5),
+            1.0,
+        ));
+
+        world.step_fixed();
+
+        let dt = world.time_step;
+        let body = world.body(handle);
+        assert!((body.linear_velocity.y - world.gravity.y * dt).abs() < 1e-6);
+        assert!((body.position.y - world.gravity.y * dt * dt).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_static_body_does_not_move()
+    {
+        let mut world = world_with_floor();
+        for _ in 0..60
+        {
+            world.step_fixed();
+        }
+        assert_eq!(world.bodies()[0].position, vec3(0.0, -0.5, 0.0));
+    }
+
+    #[test]
+    fn test_box_rests_on_floor()
+    {
+        let mut world = world_with_floor();
+        let handle = world.add_body(RigidBody::new_box(
+            vec3(0.0, 2.0, 0.0),
+            vec3(0.5, 0.5, 0.5),
+            1.0,
+        ));
+
+        for _ in 0..240
+        {
+            world.step_fixed();
+        }
+
+        let body = world.body(handle);
+        assert!((body.position.y - 0.5).abs() < 0.05, "{:?}", body.position);
+        assert!(glm::length(body.linear_velocity) < 0.05);
+        assert!(!world.contacts().is_empty());
+    }
+
+    #[test]
+    fn test_stacked_boxes_come_to_rest()
+    {
+        let mut world = world_with_floor();
+        let handles: Vec<_> = (0..3)
+            .map(|i| {
+                world.add_body(RigidBody::new_box(
+                    vec3(0.0, 0.5 + i as f32 * 1.0, 0.0),
+                    vec3(0.5, 0.5, 0.5),
+                    1.0,
+                ))
+            })
+            .collect();
+
+        for _ in 0..300
+        {
+            world.step_fixed();
+        }
+
+        for (i, handle) in handles.iter().enumerate()
+        {
+            let body = world.body(*handle);
+            let expected = 0.5 + i as f32 * 1.0;
+            assert!(
+                (body.position.y - expected).abs() < 0.1,
+                "box {} at {:?}",
+                i,
+                body.position
+            );
+            assert!(body.position.x.abs() < 0.05 && body.position.z.abs() < 0.05);
+            assert!(glm::length(body.linear_velocity) < 0.1);
+            assert!(glm::length(body.angular_velocity) < 0.1);
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture bench_
+    #[test]
+    #[ignore]
+    fn bench_thousands_of_boxes()
+    {
+        let mut world = PhysicsWorld::new();
+        world.add_body(RigidBody::new_static_box(
+            vec3(0.0, -0.5, 0.0),
+            vec3(100.0, 0.5, 100.0),
+        ));
+        for x in 0..20
+        {
+            for y in 0..5
+            {
+                for z in 0..20
+                {
+                    world.add_body(RigidBody::new_box(
+                        vec3(
+                            x as f32 * 2.0 - 20.0,
+                            0.5 + y as f32 * 1.5,
+                            z as f32 * 2.0 - 20.0,
+                        ),
+                        vec3(0.5, 0.5, 0.5),
+                        1.0,
+                    ));
+                }
+            }
+        }
+
+        let steps = 120;
+        let start = std::time::Instant::now();
+        for _ in 0..steps
+        {
+            world.step_fixed();
+        }
+        let per_step = start.elapsed().as_secs_f32() / steps as f32;
+        println!(
+            "{} bodies: {:.3} ms per step, {} contacts",
+            world.bodies().len(),
+            per_step * 1000.0,
+            world.contacts().len()
+        );
+        assert!(per_step < world.time_step, "slower than real time");
+    }
+
+    #[test]
+    fn test_quat_nlerp_takes_short_path()
+    {
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(vec3(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_2);
+        let negated = Quat {
+            w: -b.w,
+            x: -b.x,
+            y: -b.y,
+            z: -b.z,
+        };
+
+        let expected = Quat::from_axis_angle(vec3(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_4);
+        let v = vec3(1.0, 0.0, 0.0);
+        for half in [a.nlerp(b, 0.5), a.nlerp(negated, 0.5)]
+        {
+            assert!(glm::length(half.rotate(v) - expected.rotate(v)) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_quat_slerp_keeps_constant_speed()
+    {
+        let up = vec3(0.0, 1.0, 0.0);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(up, 2.0);
+        let quarter = a.slerp(b, 0.25);
+        let expected = Quat::from_axis_angle(up, 0.5);
+        let v = vec3(1.0, 0.0, 0.0);
+        assert!(glm::length(quarter.rotate(v) - expected.rotate(v)) < 1e-5);
+    }
+
+    #[test]
+    fn test_quat_from_mat3_round_trips()
+    {
+        let v = vec3(0.3, -0.5, 0.8);
+        for (axis, angle) in [
+            (vec3(0.0, 1.0, 0.0), 0.5),
+            (vec3(1.0, 0.0, 0.0), 3.1),
+            (vec3(0.0, 1.0, 0.0), 3.1),
+            (vec3(0.0, 0.0, 1.0), -3.1),
+            (vec3(1.0, 2.0, 3.0), 2.0),
+        ]
+        {
+            let q = Quat::from_axis_angle(axis, angle);
+            let back = Quat::from_mat3(q.to_mat3());
+            assert!(glm::length(back.rotate(v) - q.rotate(v)) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_quat_rotation()
+    {
+        let q = Quat::from_axis_angle(vec3(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_2);
+        let rotated = q.rotate(vec3(1.0, 0.0, 0.0));
+        assert!(glm::length(rotated - vec3(0.0, 0.0, -1.0)) < 1e-6);
+
+        let from_matrix = q.to_mat3() * vec3(1.0, 0.0, 0.0);
+        assert!(glm::length(from_matrix - rotated) < 1e-6);
+    }
+
+    #[test]
+    fn test_quat_integrate_matches_axis_angle()
+    {
+        let mut q = Quat::identity();
+        for _ in 0..1000
+        {
+            q = q.integrate(vec3(0.0, 0.0, 1.0), 0.001);
+        }
+        let expected = Quat::from_axis_angle(vec3(0.0, 0.0, 1.0), 1.0);
+        assert!((q.w - expected.w).abs() < 1e-3);
+        assert!((q.z - expected.z).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PreprocessError
+{
+    #[error("can't read shader {path}: {source}")]
+    Read
+    {
+        path: String, source: io::Error
+    },
+    #[error("{path}:{line}: {message}")]
+    Directive
+    {
+        path: String,
+        line: usize,
+        message: String,
+    },
+}
+
+// glsl ready to hand to gl, and where each of its lines came from
+#[derive(Debug)]
+pub struct Source
+{
+    pub code: String,
+    // every file that went into the code, the preprocessed one first
+    pub files: Vec<PathBuf>,
+    // index into files and line number, for each line of code
+    lines: Vec<(usize, usize)>,
+}
+
+// inlines `#include "path"` lines, with paths relative to the including file,
+// and puts `#define name value` lines for the defines after `#version`. every
+// include is inlined, whether it already was or not, since only the glsl
+// preprocessor knows which #ifdef branches are live. files that can end up
+// included twice need include guards.
+pub fn preprocess(
+    path: &Path,
+    defines: &[(&str, &str)],
+) -> Result<Source, PreprocessError>
+{
+    let path = fs::canonicalize(path).map_err(|source| PreprocessError::Read {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let mut output = Output::default();
+    output.include(&path, &mut vec![])?;
+
+    // #version has to come before anything but comments
+    let version = output
+        .text
+        .iter()
+        .position(|line| line.trim_start().starts_with("#version"));
+    let at = version.map_or(0, |line| line + 1);
+    let origin = (
+        0,
+        output
+            .lines
+            .get(at.saturating_sub(1))
+            .map_or(1, |line| line.1),
+    );
+    let define_lines = defines
+        .iter()
+        .map(|(name, value)| format!("#define {} {}", name, value).trim_end().to_string());
+    output.text.splice(at..at, define_lines);
+    output.lines.splice(at..at, defines.iter().map(|_| origin));
+
+    let mut code = output.text.join("\n");
+    code.push('\n');
+    Ok(Source {
+        code,
+        files: output.files,
+        lines: output.lines,
+    })
+}
+
+#[derive(Default)]
+struct Output
+{
+    text: Vec<String>,
+    lines: Vec<(usize, usize)>,
+    files: Vec<PathBuf>,
+}
+
+impl Output
+{
+    fn include(
+        &mut self,
+        path: &Path,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), PreprocessError>
+    {
+        let code = fs::read_to_string(path).map_err(|source| PreprocessError::Read {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let file = match self.files.iter().position(|file| file == path)
+        {
+            Some(file) => file,
+            None =>
+            {
+                self.files.push(path.to_path_buf());
+                self.files.len() - 1
+            }
+        };
+        stack.push(path.to_path_buf());
+        for (number, line) in code.lines().enumerate()
+        {
+            let number = number + 1;
+            let error = |message: String| PreprocessError::Directive {
+                path: path.display().to_string(),
+                line: number,
+                message,
+            };
+            let directive = line.trim_start();
+            if let Some(rest) = directive.strip_prefix("#include")
+            {
+                let name = include_name(rest)
+                    .ok_or_else(|| error("expected #include \"path\"".to_string()))?;
+                let included = path.parent().unwrap_or(Path::new("")).join(name);
+                let included = fs::canonicalize(&included)
+                    .map_err(|source| error(format!("can't include {}: {}", name, source)))?;
+                if stack.contains(&included)
+                {
+                    return Err(error(format!("{} ends up including itself", name)));
+                }
+                self.include(&included, stack)?;
+            }
+            else if directive.starts_with("#version") && stack.len() > 1
+            {
+                return Err(error("#version in an included file".to_string()));
+            }
+            else
+            {
+                self.text.push(line.to_string());
+                self.lines.push((file, number));
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+fn include_name(rest: &str) -> Option<&str>
+{
+    let rest = rest.trim();
+    rest.strip_prefix('"')?
+        .strip_suffix('"')
+        .filter(|name| !name.is_empty())
+}
+
+impl Source
+{
+    // rewrites the locations gl puts at the start of log lines, "0:12(5)" from
+    // mesa, "0(12)" from nvidia and "ERROR: 0:12:" from amd, into file:line
+    pub fn map_log(
+        &self,
+        log: &str,
+    ) -> String
+    {
+        log.lines()
+            .map(|line| self.map_log_line(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn map_log_line(
+        &self,
+        line: &str,
+    ) -> String
+    {
+        let start = ["ERROR: ", "WARNING: "]
+            .iter()
+            .find(|prefix| line.starts_with(*prefix))
+            .map_or(0, |prefix| prefix.len());
+        let rest = &line[start..];
+        let string = digits(rest);
+        let after = &rest[string..];
+        let (number, end) = if let Some(after) = after.strip_prefix(':')
+        {
+            (&after[..digits(after)], string + 1 + digits(after))
+        }
+        else if let Some(after) = after.strip_prefix('(')
+        {
+            let number = &after[..digits(after)];
+            if string == 0 || !after[number.len()..].starts_with(')')
+            {
+                return line.to_string();
+            }
+            (number, string + number.len() + 2)
+        }
+        else
+        {
+            return line.to_string();
+        };
+        let location = number
+            .parse::<usize>()
+            .ok()
+            .filter(|_| string > 0)
+            .and_then(|number| self.lines.get(number.checked_sub(1)?));
+        match location
+        {
+            Some(&(file, original)) => format!(
+                "{}{}:{}{}",
+                &line[..start],
+                self.files[file].display(),
+                original,
+                &rest[end..]
+            ),
+            None => line.to_string(),
+        }
+    }
+}
+
+fn digits(text: &str) -> usize
+{
+    text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len()
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::{fs, path::PathBuf};
+
+    use super::{preprocess, PreprocessError};
+
+    fn shader_dir(
+        name: &str,
+        files: &[(&str, &str)],
+    ) -> PathBuf
+    {
+        let root =
+            std::env::temp_dir().join(format!("rustgl-preprocess-{}-{}", name, std::process::id()));
+        for (path, code) in files
+        {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, code).unwrap();
+        }
+        fs::canonicalize(root).unwrap()
+    }
+
+    #[test]
+    fn test_includes_and_defines()
+    {
+        let root = shader_dir(
+            "include",
+            &[
+                (
+                    "lighting/colors_frag.glsl",
+                    "#version 330 core\n#include \"../common/a.glsl\"\nvoid main() {}\n",
+                ),
+                ("common/a.glsl", "#include \"b.glsl\"\nfloat a;\n"),
+                ("common/b.glsl", "float b;\n"),
+            ],
+        );
+        let source = preprocess(
+            &root.join("lighting/colors_frag.glsl"),
+            &[("SKINNED", ""), ("MAX_JOINTS", "64")],
+        )
+        .unwrap();
+        assert_eq!(
+            source.code,
+            "#version 330 core\n#define SKINNED\n#define MAX_JOINTS 64\nfloat b;\nfloat a;\nvoid main() {}\n"
+        );
+        assert_eq!(
+            source.files,
+            [
+                root.join("lighting/colors_frag.glsl"),
+                root.join("common/a.glsl"),
+                root.join("common/b.glsl"),
+            ]
+        );
+
+        // an include under an #ifdef doesn't hide a later one, the guard is
+        // what keeps it to one copy when both are live
+        let root = shader_dir(
+            "twice",
+            &[
+                (
+                    "main.glsl",
+                    "#ifdef SKINNED\n#include \"a.glsl\"\n#endif\n#include \"a.glsl\"\n",
+                ),
+                ("a.glsl", "#ifndef A\n#define A\nfloat a;\n#endif\n"),
+            ],
+        );
+        let source = preprocess(&root.join("main.glsl"), &[]).unwrap();
+        let a = "#ifndef A\n#define A\nfloat a;\n#endif\n";
+        assert_eq!(source.code, format!("#ifdef SKINNED\n{}#endif\n{}", a, a));
+        assert_eq!(source.files, [root.join("main.glsl"), root.join("a.glsl")]);
+        // lines of the second copy still map back to a.glsl
+        assert_eq!(
+            source.map_log("0:9(1): error"),
+            format!("{}:3(1): error", root.join("a.glsl").display())
+        );
+    }
+
+    #[test]
+    fn test_log_lines_map_back_to_files()
+    {
+        let root = shader_dir(
+            "log",
+            &[
+                (
+                    "main.glsl",
+                    "#version 330 core\n#include \"a.glsl\"\nvoid main() {}\n",
+                ),
+                ("a.glsl", "float a;\nfloat b;\n"),
+            ],
+        );
+        let source = preprocess(&root.join("main.glsl"), &[("SKINNED", "")]).unwrap();
+        let main = root.join("main.glsl").display().to_string();
+        let a = root.join("a.glsl").display().to_string();
+        // line 4 is `float b;`, line 5 `void main() {}`
+        assert_eq!(
+            source.map_log("0:4(7): error: `b' redeclared"),
+            format!("{}:2(7): error: `b' redeclared", a)
+        );
+        assert_eq!(
+            source.map_log("0(5) : error C0000: syntax error\nERROR: 0:2: bad define"),
+            format!(
+                "{}:3 : error C0000: syntax error\nERROR: {}:1: bad define",
+                main, main
+            )
+        );
+        // anything that isn't a known line is left alone
+        assert_eq!(source.map_log("0:99(1): error"), "0:99(1): error");
+        assert_eq!(source.map_log("link failed"), "link failed");
+    }
+
+    #[test]
+    fn test_errors()
+    {
+        let root = shader_dir(
+            "errors",
+            &[
+                ("missing.glsl", "float a;\n#include \"nowhere.glsl\"\n"),
+                ("loop.glsl", "#include \"loop2.glsl\"\n"),
+                ("loop2.glsl", "#include \"loop.glsl\"\n"),
+                ("syntax.glsl", "#include <a.glsl>\n"),
+                ("version.glsl", "#include \"versioned.glsl\"\n"),
+                ("versioned.glsl", "#version 330 core\n"),
+            ],
+        );
+        let error = |name: &str| match preprocess(&root.join(name), &[]).unwrap_err()
+        {
+            PreprocessError::Directive { line, message, .. } => (line, message),
+            error => panic!("{}", error),
+        };
+        let (line, message) = error("missing.glsl");
+        assert_eq!(line, 2);
+        assert!(
+            message.starts_with("can't include nowhere.glsl"),
+            "{}",
+            message
+        );
+        assert_eq!(
+            error("loop.glsl"),
+            (1, "loop.glsl ends up including itself".to_string())
+        );
+        assert_eq!(
+            error("syntax.glsl"),
+            (1, "expected #include \"path\"".to_string())
+        );
+        assert_eq!(
+            error("version.glsl"),
+            (1, "#version in an included file".to_string())
+        );
+        assert!(matches!(
+            preprocess(&root.join("gone.glsl"), &[]),
+            Err(PreprocessError::Read { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resource_shaders_preprocess()
+    {
+        for path in [
+            "basic_vert.glsl",
+            "basic_frag.glsl",
+            "vertex.glsl",
+            "fragment.glsl",
+            "lighting/colors_vert.glsl",
+            "lighting/colors_frag.glsl",
+            "lighting/light_cube_vert.glsl",
+            "lighting/light_cube_frag.glsl",
+            "gui/gui_vert.glsl",
+            "gui/gui_frag.glsl",
+        ]
+        {
+            let path = PathBuf::from("./resources/shaders").join(path);
+            let source = preprocess(&path, &[("SKINNED", ""), ("MAX_JOINTS", "64")]).unwrap();
+            assert!(
+                source.code.starts_with("#version 330 core"),
+                "{}",
+                path.display()
+            );
+        }
+    }
+}
@@ -1,23 +1,59 @@
-use std::ffi::CString;
+use std::{
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    ffi::CString,
+};
 
 use gl::{
-    types::{GLint, GLuint},
+    types::{GLchar, GLenum, GLint, GLsizei, GLuint},
     FALSE,
 };
 use glm::Mat4;
 
-use crate::shader::{Shader, ShaderError};
+use crate::{
+    shader::{Shader, ShaderError},
+    uniform_buffer::BLOCK_BINDINGS,
+};
+
+const SAMPLERS: &[GLenum] = &[
+    gl::SAMPLER_2D,
+    gl::SAMPLER_3D,
+    gl::SAMPLER_CUBE,
+    gl::SAMPLER_2D_SHADOW,
+    gl::SAMPLER_2D_ARRAY,
+];
+
+// an active uniform or vertex attribute, arrays have a size above 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variable
+{
+    pub location: GLint,
+    pub kind: GLenum,
+    pub size: GLint,
+}
+
+// what the linker kept, looked up by name
+#[derive(Debug, Default)]
+struct Reflection
+{
+    uniforms: HashMap<String, Variable>,
+    blocks: HashMap<String, GLuint>,
+}
 
 pub struct Program
 {
-    pub id: GLuint,
+    // cells so a shared program can be relinked in place
+    id: Cell<GLuint>,
+    reflection: RefCell<Reflection>,
+    // names already reported, so a bad name in the draw loop shows once
+    reported: RefCell<HashSet<String>>,
 }
 
 impl Drop for Program
 {
     fn drop(&mut self)
     {
-        unsafe { gl::DeleteProgram(self.id) }
+        unsafe { gl::DeleteProgram(self.id()) }
     }
 }
 
@@ -26,29 +62,40 @@ impl Program
     pub unsafe fn new(shaders: &[Shader]) -> Result<Self, ShaderError>
     {
         let program = Self {
-            id: gl::CreateProgram(),
+            id: Cell::new(gl::CreateProgram()),
+            reflection: RefCell::default(),
+            reported: RefCell::default(),
         };
+        let id = program.id();
 
         for shader in shaders
         {
-            gl::AttachShader(program.id, shader.id);
+            gl::AttachShader(id, shader.id);
         }
-        gl::LinkProgram(program.id);
+        gl::LinkProgram(id);
 
         let mut success: GLint = 0;
-        gl::GetProgramiv(program.id, gl::LINK_STATUS, &mut success);
+        gl::GetProgramiv(id, gl::LINK_STATUS, &mut success);
 
         if success == 1
         {
+            program.reflection.replace(reflect(id));
+            for (name, binding) in BLOCK_BINDINGS
+            {
+                if let Some(index) = program.reflection.borrow().blocks.get(*name)
+                {
+                    gl::UniformBlockBinding(id, *index, *binding);
+                }
+            }
             Ok(program)
         }
         else
         {
             let mut error_size: GLint = 0;
-            gl::GetProgramiv(program.id, gl::INFO_LOG_LENGTH, &mut error_size);
+            gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut error_size);
             let mut message = Vec::with_capacity(error_size as usize);
             gl::GetProgramInfoLog(
-                program.id,
+                id,
                 error_size,
                 &mut error_size,
                 message.as_mut_ptr() as *mut _,
@@ -60,32 +107,99 @@ impl Program
         }
     }
 
+    pub fn id(&self) -> GLuint
+    {
+        self.id.get()
+    }
+
+    // links the shaders into a new program and swaps it in. on failure this
+    // program is left as it was. uniforms set on the old one aren't carried
+    // over.
+    pub unsafe fn relink(
+        &self,
+        shaders: &[Shader],
+    ) -> Result<(), ShaderError>
+    {
+        let linked = Self::new(shaders)?;
+        // the new program's drop deletes the old one
+        linked.id.set(self.id.replace(linked.id()));
+        self.reflection.swap(&linked.reflection);
+        self.reported.borrow_mut().clear();
+        Ok(())
+    }
+
     pub unsafe fn bind(&self)
     {
-        gl::UseProgram(self.id)
+        gl::UseProgram(self.id())
+    }
+
+    pub fn uniform(
+        &self,
+        name: &str,
+    ) -> Option<Variable>
+    {
+        self.reflection.borrow().uniforms.get(name).copied()
     }
 
-    pub unsafe fn set_uniform_int(
+    // the active uniform to set, if it has one of the kinds. debug builds
+    // report names that aren't active and kinds that don't match.
+    unsafe fn active_uniform(
         &self,
         name: &str,
-        value: i32,
+        kinds: &[GLenum],
+    ) -> Option<Variable>
+    {
+        debug_assert!(
+            {
+                let mut current = 0;
+                gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut current);
+                current as GLuint == self.id()
+            },
+            "setting uniform `{}` on a program that isn't bound",
+            name
+        );
+        let uniform = self.uniform(name);
+        if cfg!(debug_assertions)
+        {
+            match uniform
+            {
+                None => self.report(name, "isn't an active uniform".to_string()),
+                Some(uniform) if !kinds.contains(&uniform.kind) =>
+                {
+                    self.report(name, format!("has type {:#x}", uniform.kind))
+                }
+                Some(_) =>
+                {}
+            }
+        }
+        uniform.filter(|uniform| kinds.contains(&uniform.kind))
+    }
+
+    fn report(
+        &self,
+        name: &str,
+        problem: String,
     )
     {
-        self.bind();
-        let c_str = CString::new(name).unwrap();
-        gl::Uniform1i(gl::GetUniformLocation(self.id, c_str.as_ptr()), value);
+        if self.reported.borrow_mut().insert(name.to_string())
+        {
+            eprintln!("program {}: `{}` {}", self.id(), name, problem);
+        }
     }
 
-    pub unsafe fn set_uniform_mat4(
+    // the setters expect the program to be bound
+
+    // unit 0 is TEXTURE0, as in Texture::activate
+    pub unsafe fn set_uniform_sampler(
         &self,
         name: &str,
-        value: Mat4,
+        unit: u32,
     )
     {
-        self.bind();
-        let c_str = CString::new(name).unwrap();
-        let uniform = gl::GetUniformLocation(self.id, c_str.as_ptr() as *const i8);
-        gl::UniformMatrix4fv(uniform, 1, FALSE, value.as_array().as_ptr() as *const _);
+        if let Some(uniform) = self.active_uniform(name, SAMPLERS)
+        {
+            gl::Uniform1i(uniform.location, unit as GLint);
+        }
     }
 
     pub unsafe fn set_uniform_vec3(
@@ -94,9 +208,148 @@ impl Program
         value: glm::Vector3<f32>,
     )
     {
-        self.bind();
-        let c_str = CString::new(name).unwrap();
-        let uniform = gl::GetUniformLocation(self.id, c_str.as_ptr() as *const i8);
-        gl::Uniform3f(uniform, value.x, value.y, value.z);
+        if let Some(uniform) = self.active_uniform(name, &[gl::FLOAT_VEC3])
+        {
+            gl::Uniform3f(uniform.location, value.x, value.y, value.z);
+        }
+    }
+
+    pub unsafe fn set_uniform_mat4(
+        &self,
+        name: &str,
+        value: Mat4,
+    )
+    {
+        if let Some(uniform) = self.active_uniform(name, &[gl::FLOAT_MAT4])
+        {
+            gl::UniformMatrix4fv(
+                uniform.location,
+                1,
+                FALSE,
+                value.as_array().as_ptr() as *const _,
+            );
+        }
+    }
+
+    // values past the end of the uniform array are left out
+    pub unsafe fn set_uniform_mat4_array(
+        &self,
+        name: &str,
+        values: &[Mat4],
+    )
+    {
+        if let Some(uniform) = self.active_uniform(name, &[gl::FLOAT_MAT4])
+        {
+            gl::UniformMatrix4fv(
+                uniform.location,
+                count(values, uniform),
+                FALSE,
+                values.as_ptr() as *const _,
+            );
+        }
+    }
+}
+
+fn count<T>(
+    values: &[T],
+    uniform: Variable,
+) -> GLsizei
+{
+    values.len().min(uniform.size as usize) as GLsizei
+}
+
+type GetActive =
+    unsafe fn(GLuint, GLuint, GLsizei, *mut GLsizei, *mut GLint, *mut GLenum, *mut GLchar);
+
+type GetLocation = unsafe fn(GLuint, *const GLchar) -> GLint;
+
+unsafe fn variables(
+    id: GLuint,
+    count: GLenum,
+    get_active: GetActive,
+    get_location: GetLocation,
+) -> HashMap<String, Variable>
+{
+    let mut total = 0;
+    gl::GetProgramiv(id, count, &mut total);
+    let mut variables = HashMap::new();
+    for index in 0..total as GLuint
+    {
+        let mut name = [0u8; 256];
+        let (mut length, mut size, mut kind) = (0, 0, 0);
+        get_active(
+            id,
+            index,
+            name.len() as GLsizei,
+            &mut length,
+            &mut size,
+            &mut kind,
+            name.as_mut_ptr() as *mut GLchar,
+        );
+        let name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+        let c_name = CString::new(name.as_str()).unwrap();
+        let location = get_location(id, c_name.as_ptr());
+        // block members and built ins like gl_VertexID have no location
+        if location >= 0
+        {
+            let variable = Variable {
+                location,
+                kind,
+                size,
+            };
+            variables.insert(base_name(&name).to_string(), variable);
+        }
+    }
+    variables
+}
+
+unsafe fn reflect(id: GLuint) -> Reflection
+{
+    let mut reflection = Reflection {
+        uniforms: variables(
+            id,
+            gl::ACTIVE_UNIFORMS,
+            gl::GetActiveUniform,
+            gl::GetUniformLocation,
+        ),
+        blocks: HashMap::new(),
+    };
+
+    let mut blocks = 0;
+    gl::GetProgramiv(id, gl::ACTIVE_UNIFORM_BLOCKS, &mut blocks);
+    for index in 0..blocks as GLuint
+    {
+        let mut name = [0u8; 256];
+        let mut length = 0;
+        gl::GetActiveUniformBlockName(
+            id,
+            index,
+            name.len() as GLsizei,
+            &mut length,
+            name.as_mut_ptr() as *mut GLchar,
+        );
+        let name = String::from_utf8_lossy(&name[..length as usize]).into_owned();
+        reflection.blocks.insert(name, index);
+    }
+    reflection
+}
+
+// arrays are reported as their first element, "joints[0]"
+fn base_name(name: &str) -> &str
+{
+    name.strip_suffix("[0]").unwrap_or(name)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::base_name;
+
+    #[test]
+    fn test_base_name()
+    {
+        assert_eq!(base_name("joints[0]"), "joints");
+        assert_eq!(base_name("lights[0].color"), "lights[0].color");
+        assert_eq!(base_name("model"), "model");
     }
 }
@@ -87,6 +87,91 @@ impl VertexArray
         self.offset = self.offset + size as usize;
         self.number_of_att_ptr = self.number_of_att_ptr + 1;
     }
+
+    // four normalized bytes, like an rgba8 color, packed into the slot of one
+    // float
+    pub unsafe fn add_byte_color_att_ptr(&mut self)
+    {
+        gl::VertexAttribPointer(
+            self.number_of_att_ptr as GLuint,
+            4,
+            gl::UNSIGNED_BYTE,
+            gl::TRUE,
+            self.stride * std::mem::size_of::<f32>() as gl::types::GLsizei,
+            (self.offset * std::mem::size_of::<f32>()) as *const _,
+        );
+        gl::EnableVertexAttribArray(self.number_of_att_ptr as GLuint);
+        self.offset += 1;
+        self.number_of_att_ptr += 1;
+    }
+
+    // attributes that advance once per instance, read from the instance buffer
+    // and numbered after the per vertex ones. a mat4 is four attributes of 4.
+    pub unsafe fn add_instance_att_ptrs(
+        &mut self,
+        instances: &InstanceBuffer,
+        sizes: &[i32],
+    )
+    {
+        self.bind();
+        gl::BindBuffer(gl::ARRAY_BUFFER, instances.id);
+        let stride = sizes.iter().sum::<i32>() * std::mem::size_of::<f32>() as i32;
+        let mut offset = 0;
+        for size in sizes
+        {
+            let index = self.number_of_att_ptr as GLuint;
+            gl::VertexAttribPointer(
+                index,
+                *size,
+                gl::FLOAT,
+                0,
+                stride,
+                (offset * std::mem::size_of::<f32>()) as *const _,
+            );
+            gl::EnableVertexAttribArray(index);
+            gl::VertexAttribDivisor(index, 1);
+            offset += *size as usize;
+            self.number_of_att_ptr += 1;
+        }
+    }
+}
+
+// per instance data, refilled every frame
+pub struct InstanceBuffer
+{
+    pub id: GLuint,
+}
+
+impl InstanceBuffer
+{
+    pub unsafe fn new() -> Self
+    {
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        Self { id }
+    }
+
+    pub unsafe fn upload(
+        &self,
+        data: &[f32],
+    )
+    {
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(data) as gl::types::GLsizeiptr,
+            data.as_ptr() as *const _,
+            gl::STREAM_DRAW,
+        );
+    }
+}
+
+impl Drop for InstanceBuffer
+{
+    fn drop(&mut self)
+    {
+        unsafe { gl::DeleteBuffers(1, &self.id) }
+    }
 }
 
 pub struct IndexBuffer
@@ -126,29 +211,38 @@ impl IndexBuffer
     }
 }
 
-pub unsafe fn draw_without_ibo(
+pub unsafe fn draw(
     vao: &VertexArray,
+    ibo: &IndexBuffer,
     program: &Program,
 )
 {
     program.bind();
     vao.bind();
-    gl::DrawArrays(gl::TRIANGLES, 0, 36);
+    ibo.bind();
+    gl::DrawElements(
+        gl::TRIANGLES,
+        ibo.get_count(),
+        gl::UNSIGNED_INT,
+        std::ptr::null(),
+    );
 }
 
-pub unsafe fn draw(
+pub unsafe fn draw_instanced(
     vao: &VertexArray,
     ibo: &IndexBuffer,
     program: &Program,
+    instances: i32,
 )
 {
     program.bind();
     vao.bind();
     ibo.bind();
-    gl::DrawElements(
+    gl::DrawElementsInstanced(
         gl::TRIANGLES,
         ibo.get_count(),
         gl::UNSIGNED_INT,
         std::ptr::null(),
+        instances,
     );
 }
@@ -0,0 +1,381 @@
+use std::{fmt, fs, io, str::FromStr};
+
+use glm::vec3;
+use thiserror::Error;
+
+use crate::{
+    game::{CubeGameState, PlayerInput},
+    game_loop::{Time, MIN_FIXED_DELTA},
+    scene::Scene,
+};
+
+pub const REPLAY_HEADER: &str = "# rustgl replay";
+
+#[derive(Debug, Error)]
+pub enum ReplayError
+{
+    #[error("can't read replay {path}: {source}")]
+    Io
+    {
+        path: String, source: io::Error
+    },
+    #[error("{line}: {message}")]
+    Parse
+    {
+        line: usize, message: String
+    },
+    #[error("replay diverged at frame {frame}: recorded {recorded} ticks, replayed {replayed}")]
+    Diverged
+    {
+        frame: usize,
+        recorded: u32,
+        replayed: u32,
+    },
+}
+
+// one frame of the game loop. frame_time is the wall time as measured, before
+// Time::advance clamps it, and ticks is how many fixed steps it ran, every one
+// of them with the same input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame
+{
+    pub frame_time: f32,
+    pub ticks: u32,
+    pub input: PlayerInput,
+}
+
+// everything that went into a run of a scene, enough to step it again to the
+// same bits. saved as text:
+//
+//     # rustgl replay
+//     scene ./resources/scenes/demo.json
+//     fixed_delta 0.016666668
+//     frame 0.016 1 0 0 0
+//
+// with a "frame <frame_time> <ticks> <push x> <push y> <push z>" line per
+// frame. floats are written in full, so they read back as the same f32.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay
+{
+    pub scene: String,
+    pub fixed_delta: f32,
+    pub frames: Vec<Frame>,
+}
+
+impl Replay
+{
+    pub fn new(
+        scene: &str,
+        fixed_delta: f32,
+    ) -> Self
+    {
+        Self {
+            scene: scene.to_string(),
+            fixed_delta,
+            frames: vec![],
+        }
+    }
+
+    pub fn record(
+        &mut self,
+        frame_time: f32,
+        ticks: u32,
+        input: PlayerInput,
+    )
+    {
+        self.frames.push(Frame {
+            frame_time,
+            ticks,
+            input,
+        });
+    }
+
+    // a file's tick counts can add up past u32
+    pub fn ticks(&self) -> u64
+    {
+        self.frames.iter().map(|frame| frame.ticks as u64).sum()
+    }
+
+    pub fn load(path: &str) -> Result<Self, ReplayError>
+    {
+        let source = fs::read_to_string(path).map_err(|source| ReplayError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Self::parse(&source)
+    }
+
+    pub fn save(
+        &self,
+        path: &str,
+    ) -> io::Result<()>
+    {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError>
+    {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        let error = |line: usize, message: String| ReplayError::Parse { line, message };
+        if lines.next().map(|(_, line)| line.trim()) != Some(REPLAY_HEADER)
+        {
+            return Err(error(1, format!("expected `{}`", REPLAY_HEADER)));
+        }
+
+        let mut scene = None;
+        let mut fixed_delta = None;
+        let mut frames = vec![];
+        for (number, line) in lines
+        {
+            let mut words = line.split_whitespace();
+            let Some(key) = words.next()
+            else
+            {
+                continue;
+            };
+            let mut next = |what: &str| {
+                words
+                    .next()
+                    .ok_or_else(|| error(number, format!("{} expects {}", key, what)))
+            };
+            match key
+            {
+                _ if key.starts_with('#') => continue,
+                // paths can have spaces, the rest of the line is the path
+                "scene" =>
+                {
+                    next("a path")?;
+                    scene = Some(line.trim_start()[key.len()..].trim().to_string());
+                    continue;
+                }
+                "fixed_delta" =>
+                {
+                    let value: f32 = parse_word(number, next("a number")?)?;
+                    // anything less would hang the game loop, see Time::advance
+                    if !(value >= MIN_FIXED_DELTA && value.is_finite())
+                    {
+                        return Err(error(
+                            number,
+                            format!(
+                                "fixed_delta must be a number of seconds no less than {}, got {}",
+                                MIN_FIXED_DELTA, value
+                            ),
+                        ));
+                    }
+                    fixed_delta = Some(value);
+                }
+                "frame" =>
+                {
+                    let frame_time = parse_word(number, next("a frame time")?)?;
+                    let ticks = parse_word(number, next("a tick count")?)?;
+                    let mut push = [0.0; 3];
+                    for value in &mut push
+                    {
+                        *value = parse_word(number, next("three push values")?)?;
+                    }
+                    frames.push(Frame {
+                        frame_time,
+                        ticks,
+                        input: PlayerInput {
+                            push: vec3(push[0], push[1], push[2]),
+                        },
+                    });
+                }
+                other => return Err(error(number, format!("unknown line `{}`", other))),
+            }
+            if let Some(extra) = words.next()
+            {
+                return Err(error(number, format!("unexpected `{}`", extra)));
+            }
+        }
+
+        let missing = |what: &str| error(1, format!("missing `{}` line", what));
+        Ok(Self {
+            scene: scene.ok_or_else(|| missing("scene"))?,
+            fixed_delta: fixed_delta.ok_or_else(|| missing("fixed_delta"))?,
+            frames,
+        })
+    }
+
+    // steps the scene through every recorded frame. a frame running a
+    // different number of ticks than it did when recorded means the timing
+    // code changed since, and the rest wouldn't line up.
+    pub fn play(
+        &self,
+        scene: &Scene,
+    ) -> Result<CubeGameState, ReplayError>
+    {
+        let mut state = CubeGameState::from_scene(scene);
+        state.world.insert_resource(Time::new(self.fixed_delta));
+        for (index, frame) in self.frames.iter().enumerate()
+        {
+            let ticks = state.step_frame(frame.frame_time, frame.input);
+            if ticks != frame.ticks
+            {
+                return Err(ReplayError::Diverged {
+                    frame: index,
+                    recorded: frame.ticks,
+                    replayed: ticks,
+                });
+            }
+        }
+        Ok(state)
+    }
+}
+
+fn parse_word<T: FromStr>(
+    line: usize,
+    word: &str,
+) -> Result<T, ReplayError>
+{
+    word.parse().map_err(|_| ReplayError::Parse {
+        line,
+        message: format!("`{}` isn't a valid number", word),
+    })
+}
+
+impl fmt::Display for Replay
+{
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result
+    {
+        writeln!(f, "{}", REPLAY_HEADER)?;
+        writeln!(f, "scene {}", self.scene)?;
+        writeln!(f, "fixed_delta {}", self.fixed_delta)?;
+        for frame in &self.frames
+        {
+            let push = frame.input.push;
+            writeln!(
+                f,
+                "frame {} {} {} {} {}",
+                frame.frame_time, frame.ticks, push.x, push.y, push.z
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec3;
+
+    use super::{Replay, ReplayError};
+    use crate::{
+        game::{CubeGameState, PlayerInput},
+        game_loop::Time,
+        headless::body_states,
+        scene::{Scene, DEFAULT_SCENE},
+    };
+
+    // a few seconds of uneven frames, pushing the player around, as the game
+    // loop would record it
+    fn record(scene: &Scene) -> (Replay, CubeGameState)
+    {
+        let mut state = CubeGameState::from_scene(scene);
+        let fixed_delta = state.world.resource::<Time>().fixed_delta;
+        let mut replay = Replay::new(DEFAULT_SCENE, fixed_delta);
+        for frame in 0..300
+        {
+            let frame_time = [0.0071, 0.016, 0.0213, 0.033][frame % 4];
+            // a stall that gets clamped
+            let frame_time = if frame == 150 { 0.4 } else { frame_time };
+            let input = match frame / 50
+            {
+                1 => PlayerInput {
+                    push: vec3(1.0, 0.0, 0.0),
+                },
+                3 => PlayerInput {
+                    push: vec3(-0.5, 1.0, 0.25),
+                },
+                _ => PlayerInput::none(),
+            };
+            let ticks = state.step_frame(frame_time, input);
+            replay.record(frame_time, ticks, input);
+        }
+        (replay, state)
+    }
+
+    #[test]
+    fn test_replay_matches_recording()
+    {
+        let scene = Scene::load(DEFAULT_SCENE).unwrap();
+        let (replay, recorded) = record(&scene);
+        let reloaded = Replay::parse(&replay.to_string()).unwrap();
+        assert_eq!(reloaded, replay);
+
+        let replayed = reloaded.play(&scene).unwrap();
+        assert_eq!(body_states(&replayed), body_states(&recorded));
+        // the pushes made a difference
+        assert_ne!(
+            body_states(&replayed),
+            body_states(
+                &Replay {
+                    frames: vec![],
+                    ..replay.clone()
+                }
+                .play(&scene)
+                .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_divergence_is_reported()
+    {
+        let scene = Scene::load(DEFAULT_SCENE).unwrap();
+        let (mut replay, _) = record(&scene);
+        replay.frames[20].ticks += 1;
+        match replay.play(&scene)
+        {
+            Err(ReplayError::Diverged { frame, .. }) => assert_eq!(frame, 20),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_ticks_add_up_past_u32()
+    {
+        let frame = format!("frame 0.016 {} 0 0 0\n", u32::MAX);
+        let source = format!(
+            "# rustgl replay\nscene a.json\nfixed_delta 0.01\n{}{}",
+            frame, frame
+        );
+        assert_eq!(Replay::parse(&source).unwrap().ticks(), 2 * u32::MAX as u64);
+    }
+
+    #[test]
+    fn test_parse_errors()
+    {
+        let error = |source: &str| Replay::parse(source).unwrap_err().to_string();
+        assert_eq!(error("frame 1 1 0 0 0"), "1: expected `# rustgl replay`");
+        assert_eq!(
+            error("# rustgl replay\nscene a.json\n"),
+            "1: missing `fixed_delta` line"
+        );
+        assert_eq!(
+            error("# rustgl replay\nscene a.json\nfixed_delta 0.01\nframe 0.1 x 0 0 0\n"),
+            "4: `x` isn't a valid number"
+        );
+        assert_eq!(
+            error("# rustgl replay\nframe 0.1 1 0 0\n"),
+            "2: frame expects three push values"
+        );
+        assert_eq!(
+            error("# rustgl replay\nfixed_delta 0.01 0.02\n"),
+            "2: unexpected `0.02`"
+        );
+        for bad in ["0", "-0.01", "NaN", "inf", "1e-30"]
+        {
+            let message = error(&format!("# rustgl replay\nfixed_delta {}\n", bad));
+            assert!(message.starts_with("2: fixed_delta must be"), "{}", message);
+        }
+        let replay = Replay::parse("# rustgl replay\nscene my scenes/a.json\nfixed_delta 0.01\n");
+        assert_eq!(replay.unwrap().scene, "my scenes/a.json");
+    }
+}
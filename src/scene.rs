@@ -0,0 +1,768 @@
+use std::{fs, io};
+
+use glm::{vec3, Vec3};
+use thiserror::Error;
+
+use crate::{
+    camera::DEFAULT_FOV,
+    components::{Light, LightKind},
+    json::{
+        self, read_array, read_bool, read_f32, read_floats, read_string, Field, FieldError, Json,
+        JsonError, NumberError, Object, Value,
+    },
+    physics::Quat,
+};
+
+pub const DEFAULT_SCENE: &str = "./resources/scenes/demo.json";
+// the only mesh the cube renderer knows how to draw
+pub const CUBE_MESH: &str = "cube";
+
+// a mesh path to a gltf file, drawn by the skinned renderer
+pub fn is_gltf_mesh(mesh: &str) -> bool
+{
+    mesh.ends_with(".gltf") || mesh.ends_with(".glb")
+}
+
+#[derive(Debug, Error)]
+pub enum SceneError
+{
+    #[error("can't read scene file {path}: {source}")]
+    Io
+    {
+        path: String, source: io::Error
+    },
+    #[error("{0}")]
+    Syntax(#[from] JsonError),
+    #[error("{0}")]
+    Invalid(#[from] FieldError),
+    #[error("{0}")]
+    Write(#[from] NumberError),
+    #[error("{path}:{source}")]
+    InFile
+    {
+        path: String,
+        source: Box<SceneError>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene
+{
+    pub camera: CameraStart,
+    pub terrain: Option<TerrainDesc>,
+    pub lights: Vec<Light>,
+    pub entities: Vec<EntityDesc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraStart
+{
+    pub position: Vec3,
+    // degrees, same convention as Camera
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl Default for CameraStart
+{
+    fn default() -> Self
+    {
+        Self {
+            position: vec3(0.0, 0.0, 3.0),
+            yaw: -90.0,
+            pitch: 0.0,
+            fov: DEFAULT_FOV,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+// paths are relative to the asset root
+pub struct TerrainDesc
+{
+    pub height_map: String,
+    pub normal_map: String,
+    pub texture: String,
+}
+
+impl Default for TerrainDesc
+{
+    fn default() -> Self
+    {
+        Self {
+            height_map: "map/my_height_map.png".to_string(),
+            normal_map: "map/normal_map.png".to_string(),
+            texture: "map/desert_mountains.png".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDesc
+{
+    pub name: Option<String>,
+    pub transform: TransformDesc,
+    pub mesh: String,
+    pub material: MaterialDesc,
+    pub collider: Option<ColliderDesc>,
+    // needs a collider, a collider without a body is static
+    pub body: Option<BodyDesc>,
+    pub player: bool,
+}
+
+impl Default for EntityDesc
+{
+    fn default() -> Self
+    {
+        Self {
+            name: None,
+            transform: TransformDesc::default(),
+            mesh: CUBE_MESH.to_string(),
+            material: MaterialDesc::default(),
+            collider: None,
+            body: None,
+            player: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransformDesc
+{
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for TransformDesc
+{
+    fn default() -> Self
+    {
+        Self {
+            position: vec3(0.0, 0.0, 0.0),
+            rotation: Quat::identity(),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDesc
+{
+    pub color: Vec3,
+    pub colliding_color: Vec3,
+    pub texture: Option<String>,
+}
+
+impl Default for MaterialDesc
+{
+    fn default() -> Self
+    {
+        Self {
+            color: vec3(0.0, 1.0, 0.0),
+            colliding_color: vec3(1.0, 0.0, 0.0),
+            texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColliderDesc
+{
+    Box
+    {
+        half_extents: Vec3
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyDesc
+{
+    // zero makes the body static
+    pub mass: f32,
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl Default for BodyDesc
+{
+    fn default() -> Self
+    {
+        Self {
+            mass: 1.0,
+            restitution: 0.2,
+            friction: 0.5,
+        }
+    }
+}
+
+impl Scene
+{
+    pub fn load(path: &str) -> Result<Self, SceneError>
+    {
+        let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Self::parse(&source).map_err(|error| SceneError::InFile {
+            path: path.to_string(),
+            source: Box::new(error),
+        })
+    }
+
+    pub fn parse(source: &str) -> Result<Self, SceneError>
+    {
+        let json = json::parse(source)?;
+        let root = Object::new(&json, "scene", &["camera", "terrain", "lights", "entities"])?;
+
+        let camera = match root.optional("camera")
+        {
+            Some(field) => read_camera(field)?,
+            None => CameraStart::default(),
+        };
+        let terrain = root.optional("terrain").map(read_terrain).transpose()?;
+        let lights = match root.optional("lights")
+        {
+            Some(field) => read_array(field, read_light)?,
+            None => vec![],
+        };
+        let entities = read_array(root.required("entities")?, read_entity)?;
+
+        Ok(Self {
+            camera,
+            terrain,
+            lights,
+            entities,
+        })
+    }
+
+    pub fn save(
+        &self,
+        path: &str,
+    ) -> Result<(), SceneError>
+    {
+        fs::write(path, self.to_json_string()?).map_err(|source| SceneError::Io {
+            path: path.to_string(),
+            source,
+        })
+    }
+
+    pub fn to_json_string(&self) -> Result<String, SceneError>
+    {
+        let mut fields = vec![("camera", write_camera(&self.camera))];
+        if let Some(terrain) = &self.terrain
+        {
+            fields.push(("terrain", write_terrain(terrain)));
+        }
+        fields.push((
+            "lights",
+            Value::Array(self.lights.iter().map(write_light).collect()).into(),
+        ));
+        fields.push((
+            "entities",
+            Value::Array(self.entities.iter().map(write_entity).collect()).into(),
+        ));
+        Ok(json::to_string_pretty(&object(fields))?)
+    }
+}
+
+fn read_vec3(field: Field) -> Result<Vec3, SceneError>
+{
+    let [x, y, z] = read_floats(field)?;
+    Ok(vec3(x, y, z))
+}
+
+// stored as [w, x, y, z]
+fn read_quat(field: Field) -> Result<Quat, SceneError>
+{
+    let [w, x, y, z] = read_floats(field)?;
+    Ok(Quat { w, x, y, z }.normalize())
+}
+
+fn read_camera(field: Field) -> Result<CameraStart, SceneError>
+{
+    let camera = Object::new(
+        field.json,
+        &field.path,
+        &["position", "yaw", "pitch", "fov"],
+    )?;
+    let default = CameraStart::default();
+    Ok(CameraStart {
+        position: camera
+            .optional("position")
+            .map_or(Ok(default.position), read_vec3)?,
+        yaw: camera.optional("yaw").map_or(Ok(default.yaw), read_f32)?,
+        pitch: camera
+            .optional("pitch")
+            .map_or(Ok(default.pitch), read_f32)?,
+        fov: match camera.optional("fov")
+        {
+            Some(field) =>
+            {
+                let fov = read_f32(field.clone())?;
+                if !(fov > 0.0 && fov < 180.0)
+                {
+                    return Err(field
+                        .invalid("fov has to be between 0 and 180 degrees")
+                        .into());
+                }
+                fov
+            }
+            None => default.fov,
+        },
+    })
+}
+
+fn read_terrain(field: Field) -> Result<TerrainDesc, SceneError>
+{
+    let terrain = Object::new(
+        field.json,
+        &field.path,
+        &["height_map", "normal_map", "texture"],
+    )?;
+    Ok(TerrainDesc {
+        height_map: read_string(terrain.required("height_map")?)?,
+        normal_map: read_string(terrain.required("normal_map")?)?,
+        texture: read_string(terrain.required("texture")?)?,
+    })
+}
+
+fn read_light(field: Field) -> Result<Light, SceneError>
+{
+    let light = Object::new(
+        field.json,
+        &field.path,
+        &["kind", "position", "direction", "color", "intensity"],
+    )?;
+    let kind_field = light.required("kind")?;
+    let kind = match read_string(kind_field.clone())?.as_str()
+    {
+        "point" => LightKind::Point {
+            position: read_vec3(light.required("position")?)?,
+        },
+        "directional" => LightKind::Directional {
+            direction: read_vec3(light.required("direction")?)?,
+        },
+        other =>
+        {
+            return Err(kind_field
+                .invalid(format!(
+                    "unknown light kind `{}`, expected point or directional",
+                    other
+                ))
+                .into())
+        }
+    };
+    Ok(Light {
+        kind,
+        color: light
+            .optional("color")
+            .map_or(Ok(vec3(1.0, 1.0, 1.0)), read_vec3)?,
+        intensity: light.optional("intensity").map_or(Ok(1.0), read_f32)?,
+    })
+}
+
+fn read_entity(field: Field) -> Result<EntityDesc, SceneError>
+{
+    let entity = Object::new(
+        field.json,
+        &field.path,
+        &[
+            "name",
+            "transform",
+            "mesh",
+            "material",
+            "collider",
+            "body",
+            "player",
+        ],
+    )?;
+    let default = EntityDesc::default();
+
+    let transform = match entity.optional("transform")
+    {
+        Some(field) =>
+        {
+            let transform =
+                Object::new(field.json, &field.path, &["position", "rotation", "scale"])?;
+            let default = TransformDesc::default();
+            TransformDesc {
+                position: transform
+                    .optional("position")
+                    .map_or(Ok(default.position), read_vec3)?,
+                rotation: transform
+                    .optional("rotation")
+                    .map_or(Ok(default.rotation), read_quat)?,
+                scale: transform
+                    .optional("scale")
+                    .map_or(Ok(default.scale), read_vec3)?,
+            }
+        }
+        None => default.transform,
+    };
+
+    let material = match entity.optional("material")
+    {
+        Some(field) =>
+        {
+            let material = Object::new(
+                field.json,
+                &field.path,
+                &["color", "colliding_color", "texture"],
+            )?;
+            let default = MaterialDesc::default();
+            MaterialDesc {
+                color: material
+                    .optional("color")
+                    .map_or(Ok(default.color), read_vec3)?,
+                colliding_color: material
+                    .optional("colliding_color")
+                    .map_or(Ok(default.colliding_color), read_vec3)?,
+                texture: material.optional("texture").map(read_string).transpose()?,
+            }
+        }
+        None => default.material,
+    };
+
+    let collider = match entity.optional("collider")
+    {
+        Some(field) =>
+        {
+            let collider = Object::new(field.json, &field.path, &["shape", "half_extents"])?;
+            let shape_field = collider.required("shape")?;
+            match read_string(shape_field.clone())?.as_str()
+            {
+                "box" =>
+                {
+                    let field = collider.required("half_extents")?;
+                    let half_extents = read_vec3(field.clone())?;
+                    // a flat box has no inertia to invert
+                    if !half_extents
+                        .as_array()
+                        .iter()
+                        .all(|extent| extent.is_finite() && *extent > 0.0)
+                    {
+                        return Err(field.invalid("half extents have to be positive").into());
+                    }
+                    Some(ColliderDesc::Box { half_extents })
+                }
+                other =>
+                {
+                    return Err(shape_field
+                        .invalid(format!(
+                            "unsupported shape `{}`, only box colliders are simulated",
+                            other
+                        ))
+                        .into())
+                }
+            }
+        }
+        None => None,
+    };
+
+    let body = match entity.optional("body")
+    {
+        Some(field) =>
+        {
+            if collider.is_none()
+            {
+                return Err(field.invalid("a body needs a collider").into());
+            }
+            let body = Object::new(
+                field.json,
+                &field.path,
+                &["mass", "restitution", "friction"],
+            )?;
+            let default = BodyDesc::default();
+            let mass = match body.optional("mass")
+            {
+                Some(field) if read_f32(field.clone())? < 0.0 =>
+                {
+                    return Err(field.invalid("mass can't be negative").into())
+                }
+                Some(field) => read_f32(field)?,
+                None => default.mass,
+            };
+            Some(BodyDesc {
+                mass,
+                restitution: body
+                    .optional("restitution")
+                    .map_or(Ok(default.restitution), read_f32)?,
+                friction: body
+                    .optional("friction")
+                    .map_or(Ok(default.friction), read_f32)?,
+            })
+        }
+        None => None,
+    };
+
+    Ok(EntityDesc {
+        name: entity.optional("name").map(read_string).transpose()?,
+        transform,
+        mesh: match entity.optional("mesh")
+        {
+            Some(field) =>
+            {
+                let mesh = read_string(field.clone())?;
+                if mesh != CUBE_MESH && !is_gltf_mesh(&mesh)
+                {
+                    return Err(field
+                        .invalid(format!(
+                            "unsupported mesh `{}`, only `{}` and gltf models are drawn",
+                            mesh, CUBE_MESH
+                        ))
+                        .into());
+                }
+                mesh
+            }
+            None => default.mesh,
+        },
+        material,
+        collider,
+        body,
+        player: entity
+            .optional("player")
+            .map_or(Ok(default.player), read_bool)?,
+    })
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json
+{
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+    .into()
+}
+
+// goes through the shortest decimal that reads back as the same f32, so saved
+// files say 1.1 rather than 1.100000023841858.
+fn number(value: f32) -> Json
+{
+    Value::Number(value.to_string().parse().unwrap()).into()
+}
+
+fn numbers(values: &[f32]) -> Json
+{
+    Value::Array(values.iter().map(|value| number(*value)).collect()).into()
+}
+
+fn string(value: &str) -> Json
+{
+    Value::String(value.to_string()).into()
+}
+
+fn write_vec3(v: Vec3) -> Json
+{
+    numbers(&[v.x, v.y, v.z])
+}
+
+fn write_camera(camera: &CameraStart) -> Json
+{
+    object(vec![
+        ("position", write_vec3(camera.position)),
+        ("yaw", number(camera.yaw)),
+        ("pitch", number(camera.pitch)),
+        ("fov", number(camera.fov)),
+    ])
+}
+
+fn write_terrain(terrain: &TerrainDesc) -> Json
+{
+    object(vec![
+        ("height_map", string(&terrain.height_map)),
+        ("normal_map", string(&terrain.normal_map)),
+        ("texture", string(&terrain.texture)),
+    ])
+}
+
+fn write_light(light: &Light) -> Json
+{
+    let mut fields = match light.kind
+    {
+        LightKind::Point { position } => vec![
+            ("kind", string("point")),
+            ("position", write_vec3(position)),
+        ],
+        LightKind::Directional { direction } => vec![
+            ("kind", string("directional")),
+            ("direction", write_vec3(direction)),
+        ],
+    };
+    fields.push(("color", write_vec3(light.color)));
+    fields.push(("intensity", number(light.intensity)));
+    object(fields)
+}
+
+fn write_entity(entity: &EntityDesc) -> Json
+{
+    let mut fields = vec![];
+    if let Some(name) = &entity.name
+    {
+        fields.push(("name", string(name)));
+    }
+    let TransformDesc {
+        position,
+        rotation: q,
+        scale,
+    } = &entity.transform;
+    fields.push((
+        "transform",
+        object(vec![
+            ("position", write_vec3(*position)),
+            ("rotation", numbers(&[q.w, q.x, q.y, q.z])),
+            ("scale", write_vec3(*scale)),
+        ]),
+    ));
+    fields.push(("mesh", string(&entity.mesh)));
+
+    let mut material = vec![
+        ("color", write_vec3(entity.material.color)),
+        (
+            "colliding_color",
+            write_vec3(entity.material.colliding_color),
+        ),
+    ];
+    if let Some(texture) = &entity.material.texture
+    {
+        material.push(("texture", string(texture)));
+    }
+    fields.push(("material", object(material)));
+
+    if let Some(ColliderDesc::Box { half_extents }) = &entity.collider
+    {
+        fields.push((
+            "collider",
+            object(vec![
+                ("shape", string("box")),
+                ("half_extents", write_vec3(*half_extents)),
+            ]),
+        ));
+    }
+    if let Some(body) = &entity.body
+    {
+        fields.push((
+            "body",
+            object(vec![
+                ("mass", number(body.mass)),
+                ("restitution", number(body.restitution)),
+                ("friction", number(body.friction)),
+            ]),
+        ));
+    }
+    fields.push(("player", Value::Bool(entity.player).into()));
+    object(fields)
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec3;
+
+    use super::{ColliderDesc, Scene, DEFAULT_SCENE};
+
+    #[test]
+    fn test_demo_scene_loads()
+    {
+        let scene = Scene::load(DEFAULT_SCENE).unwrap();
+        assert_eq!(scene.entities.len(), 11);
+        assert!(scene.entities[0].player);
+        assert_eq!(scene.entities[0].transform.position, vec3(2.5, -3.0, -1.0));
+        assert_eq!(scene.entities[9].body.as_ref().unwrap().mass, 0.0);
+        assert_eq!(
+            scene.entities[9].collider,
+            Some(ColliderDesc::Box {
+                half_extents: vec3(10.0, 0.5, 10.0)
+            })
+        );
+        assert!(scene.terrain.is_some());
+    }
+
+    #[test]
+    fn test_round_trip()
+    {
+        let scene = Scene::load(DEFAULT_SCENE).unwrap();
+        let saved = scene.to_json_string().unwrap();
+        assert_eq!(Scene::parse(&saved).unwrap(), scene);
+        // the demo file is kept in the saver's own layout
+        assert_eq!(saved, std::fs::read_to_string(DEFAULT_SCENE).unwrap());
+    }
+
+    #[test]
+    fn test_blown_up_scene_is_not_saved()
+    {
+        let mut scene = Scene::load(DEFAULT_SCENE).unwrap();
+        scene.entities[1].transform.position.y = f32::NAN;
+        let error = scene.to_json_string().unwrap_err().to_string();
+        assert!(
+            error.starts_with("entities[1].transform.position[1]:"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_defaults()
+    {
+        let scene = Scene::parse(r#"{"entities": [{}]}"#).unwrap();
+        assert_eq!(scene.entities[0].mesh, "cube");
+        assert_eq!(scene.entities[0].transform.scale, vec3(1.0, 1.0, 1.0));
+        assert!(scene.lights.is_empty() && scene.terrain.is_none());
+    }
+
+    fn error(source: &str) -> String
+    {
+        Scene::parse(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_error_messages()
+    {
+        assert_eq!(
+            error("{\n  \"entities\": [\n    {\"body\": {\"mass\": \"heavy\"}, \"collider\": {\"shape\": \"box\", \"half_extents\": [1, 1, 1]}}\n  ]\n}"),
+            "3:23: scene.entities[0].body.mass: expected a number, found a string"
+        );
+        assert_eq!(
+            error(r#"{"entities": [{"transform": {"position": [1, 2]}}]}"#),
+            "1:42: scene.entities[0].transform.position: expected 3 numbers, found 2"
+        );
+        assert_eq!(
+            error(r#"{"entities": [{"colour": [1, 0, 0]}]}"#),
+            "1:26: scene.entities[0].colour: unknown field, expected one of name, transform, mesh, material, collider, body, player"
+        );
+        assert_eq!(
+            error(r#"{"entities": [{"body": {}}]}"#),
+            "1:24: scene.entities[0].body: a body needs a collider"
+        );
+        assert_eq!(
+            error(r#"{"entities": [{"collider": {"shape": "box", "half_extents": [1, 0, 1]}}]}"#),
+            "1:61: scene.entities[0].collider.half_extents: half extents have to be positive"
+        );
+        assert_eq!(
+            error(r#"{"camera": {"yaw": 1e39}, "entities": []}"#),
+            "1:20: scene.camera.yaw: 1e39 is too large for an f32"
+        );
+        assert_eq!(
+            error(r#"{"camera": {"fov": 180}, "entities": []}"#),
+            "1:20: scene.camera.fov: fov has to be between 0 and 180 degrees"
+        );
+        assert_eq!(
+            error(r#"{"entities": [{"mesh": "meshes/rock.obj"}]}"#),
+            "1:24: scene.entities[0].mesh: unsupported mesh `meshes/rock.obj`, only `cube` and gltf models are drawn"
+        );
+        assert_eq!(error("{}"), "1:1: scene: missing field `entities`");
+        assert_eq!(error("{\"entities\": [}"), "1:15: unexpected `}`");
+    }
+
+    #[test]
+    fn test_load_reports_the_file()
+    {
+        let error = Scene::load("./resources/scenes/missing.json").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("can't read scene file ./resources/scenes/missing.json"));
+    }
+}
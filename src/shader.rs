@@ -1,13 +1,18 @@
-use std::{fs, io, string::FromUtf8Error};
+use std::{
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+};
 
 use gl::types::{GLenum, GLint, GLuint};
 use thiserror::Error;
 
+use crate::preprocessor::{preprocess, PreprocessError};
+
 #[derive(Debug, Error)]
 pub enum ShaderError
 {
-    #[error("Error while reading shader source file: {0}")]
-    ReadingFileError(#[from] io::Error),
+    #[error("Error while preprocessing shader: {0}")]
+    PreprocessError(#[from] PreprocessError),
     #[error("Error while compiling shader: {0}")]
     CompileError(String),
     #[error("Error while linking program: {0}")]
@@ -19,6 +24,8 @@ pub enum ShaderError
 pub struct Shader
 {
     pub id: GLuint,
+    // the file the shader was made from, then the ones it included
+    pub files: Vec<PathBuf>,
 }
 
 impl Shader
@@ -28,15 +35,27 @@ impl Shader
         shader_type: GLenum,
     ) -> Result<Self, ShaderError>
     {
-        let source_code = fs::read_to_string(path_to_source_code)?;
+        Self::with_defines(path_to_source_code, shader_type, &[])
+    }
+
+    // the defines are added as `#define name value` lines after #version, to
+    // build permutations of one file
+    pub unsafe fn with_defines(
+        path_to_source_code: &str,
+        shader_type: GLenum,
+        defines: &[(&str, &str)],
+    ) -> Result<Self, ShaderError>
+    {
+        let source = preprocess(Path::new(path_to_source_code), defines)?;
         let shader = Self {
             id: gl::CreateShader(shader_type),
+            files: source.files.clone(),
         };
         gl::ShaderSource(
             shader.id,
             1,
-            [source_code.as_ptr().cast()].as_ptr(),
-            std::ptr::null(),
+            [source.code.as_ptr().cast()].as_ptr(),
+            [source.code.len() as GLint].as_ptr(),
         );
         gl::CompileShader(shader.id);
         let mut success: GLint = 0;
@@ -59,7 +78,7 @@ impl Shader
 
             message.set_len(error_size as usize);
             let log = String::from_utf8(message)?;
-            Err(ShaderError::CompileError(log))
+            Err(ShaderError::CompileError(source.map_log(&log)))
         }
     }
 }
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use glm::{Mat4, Vec3};
+
+use crate::{
+    animation::{AnimationClip, AnimationPlayer, Pose, Skeleton, MAX_JOINTS},
+    assets::{AssetError, Assets, Handle},
+    components::{PreviousTransform, Renderable, Transform},
+    ecs::Entity,
+    game::CubeGameState,
+    game_loop::Time,
+    gltf::{GltfDocument, GltfError, GltfModel},
+    mesh::Mesh,
+    program::Program,
+    scene::is_gltf_mesh,
+};
+
+// the first skinned node of a gltf file, its skeleton and every clip
+// retargeted onto it
+pub struct SkinnedModel
+{
+    model: GltfModel,
+    mesh: usize,
+    skeleton: Skeleton,
+    clips: Vec<AnimationClip>,
+}
+
+impl SkinnedModel
+{
+    pub unsafe fn new(document: &GltfDocument) -> Result<Self, GltfError>
+    {
+        let (mesh, skin) = skinned_node(document).ok_or(GltfError::NoSkinnedMesh)?;
+        Ok(Self {
+            model: document.upload()?,
+            mesh,
+            skeleton: document.skeleton(skin),
+            clips: (0..document.animations.len())
+                .map(|animation| document.clip(animation, skin))
+                .collect(),
+        })
+    }
+}
+
+// a gltf file without skins, every mesh node drawn where the file puts it
+pub struct StaticModel
+{
+    model: GltfModel,
+    nodes: Vec<(usize, Mat4)>,
+}
+
+impl StaticModel
+{
+    pub unsafe fn new(document: &GltfDocument) -> Result<Self, GltfError>
+    {
+        Ok(Self {
+            model: document.upload()?,
+            nodes: document.mesh_nodes(),
+        })
+    }
+}
+
+// the mesh and skin of the first node that has both
+pub fn skinned_node(document: &GltfDocument) -> Option<(usize, usize)>
+{
+    document
+        .nodes
+        .iter()
+        .find_map(|node| Some((node.mesh?, node.skin?)))
+}
+
+// draws entities whose mesh is a gltf file, posed by a joint matrix per joint.
+// each entity loops the model's first clip. files without a skin are drawn
+// as they are with the plain program.
+pub struct SkinnedRenderer
+{
+    program: Handle<Program>,
+    static_program: Handle<Program>,
+    // by the mesh path entities use
+    models: HashMap<String, SkinnedModel>,
+    static_models: HashMap<String, StaticModel>,
+    players: HashMap<Entity, AnimationPlayer>,
+}
+
+impl SkinnedRenderer
+{
+    pub fn new(assets: &mut Assets) -> Result<Self, AssetError>
+    {
+        unsafe {
+            let max_joints = MAX_JOINTS.to_string();
+            let program = assets.program_with_defines(
+                "shaders/basic_vert.glsl",
+                "shaders/basic_frag.glsl",
+                &[("SKINNED", ""), ("MAX_JOINTS", &max_joints)],
+            )?;
+            let static_program =
+                assets.program("shaders/basic_vert.glsl", "shaders/basic_frag.glsl")?;
+
+            gl::Enable(gl::DEPTH_TEST);
+            Ok(Self {
+                program,
+                static_program,
+                models: HashMap::new(),
+                static_models: HashMap::new(),
+                players: HashMap::new(),
+            })
+        }
+    }
+
+    // uploads every gltf file the state's entities are drawn with
+    pub unsafe fn load_models(
+        &mut self,
+        assets: &mut Assets,
+        state: &CubeGameState,
+    ) -> Result<(), AssetError>
+    {
+        let renderables = state.world.components::<Renderable>();
+        for (_, renderable) in renderables.iter()
+        {
+            let path = &renderable.mesh;
+            if !is_gltf_mesh(path)
+                || self.models.contains_key(path)
+                || self.static_models.contains_key(path)
+            {
+                continue;
+            }
+            let document = assets.gltf(path)?;
+            let error = |source| AssetError::Gltf {
+                path: path.clone(),
+                source,
+            };
+            match skinned_node(&document)
+            {
+                Some(_) =>
+                {
+                    let model = SkinnedModel::new(&document).map_err(error)?;
+                    self.models.insert(path.clone(), model);
+                }
+                None =>
+                {
+                    let model = StaticModel::new(&document).map_err(error)?;
+                    self.static_models.insert(path.clone(), model);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // once a frame, animation is only for show so it runs on frame time
+    pub fn update(
+        &mut self,
+        state: &CubeGameState,
+        delta_time: f32,
+    )
+    {
+        let renderables = state.world.components::<Renderable>();
+        for (entity, renderable) in renderables.iter()
+        {
+            let Some(model) = self.models.get(&renderable.mesh)
+            else
+            {
+                continue;
+            };
+            if model.clips.is_empty()
+            {
+                continue;
+            }
+            self.players
+                .entry(entity)
+                .or_insert_with(|| AnimationPlayer::new(0))
+                .advance(delta_time, &model.clips);
+        }
+    }
+
+    pub fn draw_state(
+        &self,
+        state: &CubeGameState,
+    )
+    {
+        let renderables = state.world.components::<Renderable>();
+        let transforms = state.world.components::<Transform>();
+        let previous_transforms = state.world.components::<PreviousTransform>();
+        let alpha = state.world.resource::<Time>().alpha;
+        for (entity, renderable) in renderables.iter()
+        {
+            let Some(transform) = transforms.get(entity)
+            else
+            {
+                continue;
+            };
+            let transform = match previous_transforms.get(entity)
+            {
+                Some(previous) => previous.0.interpolate(transform, alpha),
+                None => *transform,
+            };
+            if let Some(model) = self.static_models.get(&renderable.mesh)
+            {
+                self.draw_static(model, transform.matrix(), &renderable.color);
+            }
+            let Some(model) = self.models.get(&renderable.mesh)
+            else
+            {
+                continue;
+            };
+            let pose = match self.players.get(&entity)
+            {
+                Some(player) => player.pose(&model.skeleton, &model.clips),
+                None => Pose::rest(&model.skeleton),
+            };
+            let joint_matrices = model.skeleton.joint_matrices(&pose);
+            for mesh in &model.model.meshes[model.mesh]
+            {
+                self.draw(mesh, transform.matrix(), &joint_matrices, &renderable.color);
+            }
+        }
+    }
+
+    fn draw_static(
+        &self,
+        model: &StaticModel,
+        matrix: Mat4,
+        color: &Vec3,
+    )
+    {
+        unsafe {
+            self.static_program.bind();
+            self.static_program.set_uniform_vec3("color", *color);
+            for (mesh, node) in &model.nodes
+            {
+                self.static_program
+                    .set_uniform_mat4("model", matrix * *node);
+                for primitive in &model.model.meshes[*mesh]
+                {
+                    primitive.draw(&self.static_program);
+                }
+            }
+        }
+    }
+
+    // joint_matrices come from Skeleton::joint_matrices, the gltf importer
+    // refuses skins with more than MAX_JOINTS joints.
+    pub fn draw(
+        &self,
+        mesh: &Mesh,
+        model: Mat4,
+        joint_matrices: &[Mat4],
+        color: &Vec3,
+    )
+    {
+        debug_assert!(joint_matrices.len() <= MAX_JOINTS);
+        unsafe {
+            self.program.bind();
+            self.program.set_uniform_mat4("model", model);
+            self.program
+                .set_uniform_mat4_array("joints", joint_matrices);
+            self.program.set_uniform_vec3("color", *color);
+
+            mesh.draw(&self.program);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use glm::vec3;
+
+    use super::skinned_node;
+    use crate::{
+        animation::{skin_position, AnimationPlayer},
+        assets::{Assets, ASSET_ROOT},
+        mesh::STRIDE,
+    };
+
+    #[test]
+    fn test_demo_model_sways()
+    {
+        let document = Assets::new(ASSET_ROOT).gltf("models/tail.gltf").unwrap();
+        let (mesh, skin) = skinned_node(&document).unwrap();
+        let skeleton = document.skeleton(skin);
+        let clips = vec![document.clip(0, skin)];
+        let primitive = &document.meshes[mesh].primitives[0];
+        let weights = primitive.skin.as_ref().unwrap();
+        // the last vertex is on the top ring, all on the tip joint
+        let top = primitive.data.vertex_count() - 1;
+        let vertex = &primitive.data.vertices[top * STRIDE as usize..];
+        let rest = vec3(vertex[0], vertex[1], vertex[2]);
+        assert_eq!(rest.y, 2.0);
+        assert_eq!(weights.weights[top], [0.0, 1.0, 0.0, 0.0]);
+
+        let mut player = AnimationPlayer::new(0);
+        let top_at = |player: &AnimationPlayer| {
+            let matrices = skeleton.joint_matrices(&player.pose(&skeleton, &clips));
+            skin_position(&matrices, weights.joints[top], weights.weights[top], rest)
+        };
+        assert!(glm::length(top_at(&player) - rest) < 1e-5);
+        // a quarter of the way through it leans towards -x, and back by half
+        player.advance(1.0, &clips);
+        assert!(top_at(&player).x < -0.5);
+        player.advance(1.0, &clips);
+        assert!(glm::length(top_at(&player) - rest) < 1e-5);
+    }
+}
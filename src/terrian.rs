@@ -1,77 +1,627 @@
-use std::time::SystemTime;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use glm::{vec3, Vec3};
+use image::DynamicImage;
 
 use crate::{
-    camera::Camera,
+    assets::{AssetError, Assets, Handle},
+    camera::{Camera, Frustum},
     program::Program,
     renderer,
-    shader::{Shader, ShaderError},
-    texture::Texture,
+    scene::TerrainDesc,
+    texture::{Texture, TextureOptions},
 };
 
-pub struct TerrianRenderer
+// the terrain is a square this wide on x and z, starting at the origin. it has
+// a vertex for every height map pixel, however many there are.
+pub const TERRAIN_WIDTH: f32 = 100.0;
+// world height of one step of a 16 bit height map
+pub const HEIGHT_SCALE: f32 = 1.0 / 4000.0;
+
+// every quadtree node is drawn as a grid of this many cells a side, so a
+// node's cells are twice as wide as its children's. the deepest nodes have a
+// vertex for every height map sample.
+pub const CHUNK_CELLS: u32 = 32;
+// a node splits while the camera is closer than this many times its width.
+// anything over √2 keeps neighbouring leaves within a level of each other,
+// which the seams rely on.
+const LOD_DISTANCE: f32 = 3.0;
+// meshes kept after they stop being drawn, for when the camera comes back
+const CACHED_CHUNKS: usize = 256;
+
+// edges of a node's grid, north is towards -z
+const NORTH: u8 = 1;
+const EAST: u8 = 2;
+const SOUTH: u8 = 4;
+const WEST: u8 = 8;
+
+// a node of the quadtree, x and z count nodes of its size from the origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeKey
+{
+    pub depth: u32,
+    pub x: u32,
+    pub z: u32,
+}
+
+const ROOT: NodeKey = NodeKey {
+    depth: 0,
+    x: 0,
+    z: 0,
+};
+
+impl NodeKey
+{
+    fn children(self) -> [NodeKey; 4]
+    {
+        let child = |x, z| NodeKey {
+            depth: self.depth + 1,
+            x: self.x * 2 + x,
+            z: self.z * 2 + z,
+        };
+        [child(0, 0), child(1, 0), child(0, 1), child(1, 1)]
+    }
+
+    fn parent(self) -> Option<NodeKey>
+    {
+        let depth = self.depth.checked_sub(1)?;
+        Some(NodeKey {
+            depth,
+            x: self.x / 2,
+            z: self.z / 2,
+        })
+    }
+}
+
+// a node to draw, and the edges where its neighbour is a level coarser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leaf
+{
+    pub key: NodeKey,
+    pub seams: u8,
+}
+
+// picks the nodes to draw for where the camera is, finer the closer they are
+#[derive(Debug, Clone, Copy)]
+pub struct Quadtree
+{
+    // levels below the root, the deepest has a vertex per sample
+    depth: u32,
+    // height field cells along each side
+    cells: u32,
+    spacing: f32,
+    min_height: f32,
+    max_height: f32,
+}
+
+impl Quadtree
+{
+    pub fn new(field: &HeightField) -> Self
+    {
+        let cells = field.size.max(2) as u32 - 1;
+        let mut depth = 0;
+        while CHUNK_CELLS << depth < cells
+        {
+            depth += 1;
+        }
+        let (min_height, max_height) = field
+            .heights
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), height| {
+                (min.min(*height), max.max(*height))
+            });
+        Self {
+            depth,
+            cells,
+            spacing: field.spacing,
+            min_height,
+            max_height,
+        }
+    }
+
+    // cells between neighbouring vertices of a node
+    fn step(
+        &self,
+        key: NodeKey,
+    ) -> u32
+    {
+        1 << (self.depth - key.depth)
+    }
+
+    // the node's first cell along x and z
+    fn origin(
+        &self,
+        key: NodeKey,
+    ) -> (u32, u32)
+    {
+        let span = CHUNK_CELLS * self.step(key);
+        (key.x * span, key.z * span)
+    }
+
+    fn width(
+        &self,
+        key: NodeKey,
+    ) -> f32
+    {
+        (CHUNK_CELLS * self.step(key)) as f32 * self.spacing
+    }
+
+    // the root is a power of two wide, nodes past the terrain's edge are left out
+    fn covers_terrain(
+        &self,
+        key: NodeKey,
+    ) -> bool
+    {
+        let (x, z) = self.origin(key);
+        x < self.cells && z < self.cells
+    }
+
+    // to the node's square, stretched over the whole terrain's height range
+    // so that neighbours compare fairly
+    fn distance(
+        &self,
+        key: NodeKey,
+        eye: Vec3,
+    ) -> f32
+    {
+        let width = self.width(key);
+        let min = vec3(key.x as f32 * width, self.min_height, key.z as f32 * width);
+        let max = vec3(min.x + width, self.max_height, min.z + width);
+        let closest = vec3(
+            eye.x.clamp(min.x, max.x),
+            eye.y.clamp(min.y, max.y),
+            eye.z.clamp(min.z, max.z),
+        );
+        glm::distance(eye, closest)
+    }
+
+    // the leaves tile the terrain without overlapping
+    pub fn select(
+        &self,
+        eye: Vec3,
+    ) -> Vec<Leaf>
+    {
+        let mut keys = vec![];
+        self.select_node(ROOT, eye, &mut keys);
+        let leaves: HashSet<_> = keys.iter().copied().collect();
+        keys.into_iter()
+            .map(|key| Leaf {
+                key,
+                seams: seams(key, &leaves),
+            })
+            .collect()
+    }
+
+    fn select_node(
+        &self,
+        key: NodeKey,
+        eye: Vec3,
+        keys: &mut Vec<NodeKey>,
+    )
+    {
+        if key.depth == self.depth || self.distance(key, eye) > self.width(key) * LOD_DISTANCE
+        {
+            keys.push(key);
+            return;
+        }
+        for child in key.children()
+        {
+            if self.covers_terrain(child)
+            {
+                self.select_node(child, eye, keys);
+            }
+        }
+    }
+}
+
+// neighbours are at most a level apart, so a coarser one is the parent of the
+// node that would be there at the same size
+fn seams(
+    key: NodeKey,
+    leaves: &HashSet<NodeKey>,
+) -> u8
+{
+    let mut seams = 0;
+    for (edge, x, z) in [(NORTH, 0, -1), (EAST, 1, 0), (SOUTH, 0, 1), (WEST, -1, 0)]
+    {
+        let (Some(x), Some(z)) = (key.x.checked_add_signed(x), key.z.checked_add_signed(z))
+        else
+        {
+            continue;
+        };
+        let neighbour = NodeKey {
+            depth: key.depth,
+            x,
+            z,
+        };
+        if neighbour
+            .parent()
+            .is_some_and(|parent| leaves.contains(&parent))
+        {
+            seams |= edge;
+        }
+    }
+    seams
+}
+
+// triangles over a node's grid. along seam edges every odd vertex is swapped
+// for the even one before it, so the edge runs straight between the vertices
+// the coarser neighbour has too and no gap opens between them.
+pub fn chunk_indices(seams: u8) -> Vec<i32>
+{
+    let last = CHUNK_CELLS;
+    let vertex = |mut col: u32, mut row: u32| {
+        let odd_col = col % 2 == 1;
+        let odd_row = row % 2 == 1;
+        if (row == 0 && seams & NORTH != 0 || row == last && seams & SOUTH != 0) && odd_col
+        {
+            col -= 1;
+        }
+        if (col == 0 && seams & WEST != 0 || col == last && seams & EAST != 0) && odd_row
+        {
+            row -= 1;
+        }
+        (row * (last + 1) + col) as i32
+    };
+
+    let mut output = vec![];
+    for row in 0..last
+    {
+        for col in 0..last
+        {
+            let a = vertex(col, row);
+            let b = vertex(col + 1, row);
+            let c = vertex(col, row + 1);
+            let d = vertex(col + 1, row + 1);
+            for triangle in [[a, d, c], [a, b, d]]
+            {
+                // folded corners leave some with no area
+                if triangle[0] != triangle[1]
+                    && triangle[1] != triangle[2]
+                    && triangle[0] != triangle[2]
+                {
+                    output.extend(triangle);
+                }
+            }
+        }
+    }
+    output
+}
+
+// what chunk meshes are made from, shared with the generator threads
+pub struct TerrainSamples
+{
+    heights: HeightField,
+    // normal map texels as stored, the shader normalizes them
+    normals: Vec<[f32; 3]>,
+    width: f32,
+}
+
+impl TerrainSamples
+{
+    pub fn from_images(
+        height_map: &DynamicImage,
+        normal_map: &DynamicImage,
+        width: f32,
+    ) -> Self
+    {
+        let heights = HeightField::from_image(height_map, width);
+        let normal_map = normal_map.to_rgba8();
+        let mut normals = Vec::with_capacity(heights.size * heights.size);
+        for row in 0..heights.size as u32
+        {
+            for col in 0..heights.size as u32
+            {
+                let pixel = normal_map
+                    .get_pixel(
+                        col.min(normal_map.width() - 1),
+                        row.min(normal_map.height() - 1),
+                    )
+                    .0;
+                normals.push([pixel[0] as f32, pixel[1] as f32, pixel[2] as f32]);
+            }
+        }
+        Self {
+            heights,
+            normals,
+            width,
+        }
+    }
+}
+
+// a node's (CHUNK_CELLS + 1)² vertices of position, uv and normal, and their
+// bounds
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkMesh
+{
+    pub vertices: Vec<f32>,
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+pub fn chunk_mesh(
+    samples: &TerrainSamples,
+    tree: &Quadtree,
+    key: NodeKey,
+) -> ChunkMesh
+{
+    let field = &samples.heights;
+    let step = tree.step(key);
+    let (x, z) = tree.origin(key);
+    let last = field.size as u32 - 1;
+    let mut vertices = Vec::with_capacity(((CHUNK_CELLS + 1) * (CHUNK_CELLS + 1) * 8) as usize);
+    let mut min = vec3(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = vec3(f32::MIN, f32::MIN, f32::MIN);
+    for row in 0..=CHUNK_CELLS
+    {
+        for col in 0..=CHUNK_CELLS
+        {
+            // past the edge of the terrain vertices fold onto it
+            let sample_col = (x + col * step).min(last) as usize;
+            let sample_row = (z + row * step).min(last) as usize;
+            let index = sample_row * field.size + sample_col;
+            let position = vec3(
+                sample_col as f32 * field.spacing,
+                field.heights[index],
+                sample_row as f32 * field.spacing,
+            );
+            vertices.extend([position.x, position.y, position.z]);
+            vertices.extend([position.x / samples.width, position.z / samples.width]);
+            vertices.extend(samples.normals[index]);
+            min = vec3(
+                min.x.min(position.x),
+                min.y.min(position.y),
+                min.z.min(position.z),
+            );
+            max = vec3(
+                max.x.max(position.x),
+                max.y.max(position.y),
+                max.z.max(position.z),
+            );
+        }
+    }
+    ChunkMesh { vertices, min, max }
+}
+
+// builds chunk meshes on worker threads so streaming in detail doesn't stall
+// the frame. gl calls have to stay on the main thread, so the vertices are
+// handed back through finished() to be uploaded there.
+pub struct ChunkGenerator
+{
+    requests: mpsc::Sender<NodeKey>,
+    finished: mpsc::Receiver<(NodeKey, ChunkMesh)>,
+    pending: HashSet<NodeKey>,
+}
+
+impl ChunkGenerator
+{
+    pub fn new(
+        samples: Arc<TerrainSamples>,
+        tree: Quadtree,
+    ) -> Self
+    {
+        let (requests, request_receiver) = mpsc::channel::<NodeKey>();
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+        let (finished_sender, finished) = mpsc::channel();
+        let workers = thread::available_parallelism().map_or(2, |count| count.get().min(4));
+        for _ in 0..workers
+        {
+            let request_receiver = request_receiver.clone();
+            let finished_sender = finished_sender.clone();
+            let samples = samples.clone();
+            // both ends close when the generator is dropped, which ends them
+            thread::spawn(move || {
+                loop
+                {
+                    let key = match request_receiver.lock().unwrap().recv()
+                    {
+                        Ok(key) => key,
+                        Err(_) => return,
+                    };
+                    let mesh = chunk_mesh(&samples, &tree, key);
+                    if finished_sender.send((key, mesh)).is_err()
+                    {
+                        return;
+                    }
+                }
+            });
+        }
+        Self {
+            requests,
+            finished,
+            pending: HashSet::new(),
+        }
+    }
+
+    // asking again for a chunk that's on its way does nothing
+    pub fn request(
+        &mut self,
+        key: NodeKey,
+    )
+    {
+        if self.pending.insert(key)
+        {
+            let _ = self.requests.send(key);
+        }
+    }
+
+    // the meshes done since the last call
+    pub fn finished(&mut self) -> Vec<(NodeKey, ChunkMesh)>
+    {
+        let finished: Vec<_> = self.finished.try_iter().collect();
+        for (key, _) in &finished
+        {
+            self.pending.remove(key);
+        }
+        finished
+    }
+}
+
+struct Chunk
 {
-    program: Program,
     vertex_array: renderer::VertexArray,
-    index_buffer: renderer::IndexBuffer,
-    texture: Texture,
+    min: Vec3,
+    max: Vec3,
+    // the last frame it was wanted
+    used: u64,
+}
+
+impl Chunk
+{
+    unsafe fn new(mesh: &ChunkMesh) -> Self
+    {
+        let mut vertex_array = renderer::VertexArray::new(&mesh.vertices, 8);
+        vertex_array.add_vert_att_ptr(3);
+        vertex_array.add_vert_att_ptr(2);
+        vertex_array.add_vert_att_ptr(3);
+        Self {
+            vertex_array,
+            min: mesh.min,
+            max: mesh.max,
+            used: 0,
+        }
+    }
+}
+
+impl Drop for Chunk
+{
+    fn drop(&mut self)
+    {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vertex_array.vbo);
+            gl::DeleteVertexArrays(1, &self.vertex_array.vao);
+        }
+    }
+}
+
+// draws the terrain as quadtree chunks, detailed near the camera and coarse
+// far from it. chunks are generated in the background as the camera moves,
+// and the chunks drawn only change once every one of the new ones is ready.
+pub struct TerrianRenderer
+{
+    program: Handle<Program>,
+    texture: Handle<Texture>,
     light_position: glm::Vec3,
+    tree: Quadtree,
+    generator: ChunkGenerator,
+    chunks: HashMap<NodeKey, Chunk>,
+    // one for each combination of seam edges
+    index_buffers: Vec<renderer::IndexBuffer>,
+    drawn: Vec<Leaf>,
+    frame: u64,
 }
 
 impl TerrianRenderer
 {
-    pub fn new() -> Result<Self, ShaderError>
+    pub fn new(
+        terrain: &TerrainDesc,
+        assets: &mut Assets,
+    ) -> Result<Self, AssetError>
     {
         unsafe {
-            let vertex_shader = Shader::new("./resources/shaders/vertex.glsl", gl::VERTEX_SHADER)?;
-            let fragment_shader =
-                Shader::new("./resources/shaders/fragment.glsl", gl::FRAGMENT_SHADER)?;
-            let program = Program::new(&[vertex_shader, fragment_shader])?;
+            let program = assets.program("shaders/vertex.glsl", "shaders/fragment.glsl")?;
 
-            let vertex_data = generate_terrian_vertices(100.0, 1009);
-            let mut vertex_array = renderer::VertexArray::new(&vertex_data, 8);
+            let height_map = assets.image(&terrain.height_map)?;
+            let normal_map = assets.image(&terrain.normal_map)?;
+            let samples = TerrainSamples::from_images(&height_map, &normal_map, TERRAIN_WIDTH);
+            let tree = Quadtree::new(&samples.heights);
 
-            let index_data = generate_terrian_ebo(1009);
-            let index_buffer = renderer::IndexBuffer::new(&index_data);
+            // there's always the root to draw while the rest streams in
+            let mut chunks = HashMap::new();
+            chunks.insert(ROOT, Chunk::new(&chunk_mesh(&samples, &tree, ROOT)));
+            let generator = ChunkGenerator::new(Arc::new(samples), tree);
 
-            vertex_array.add_vert_att_ptr(3);
-            vertex_array.add_vert_att_ptr(2);
-            vertex_array.add_vert_att_ptr(3);
+            let index_buffers = (0..16)
+                .map(|seams| renderer::IndexBuffer::new(&chunk_indices(seams)))
+                .collect();
 
-            let texture = Texture::new();
-            texture.set_wrap_settings();
-            texture.set_filter_settings();
-            texture.load();
-            program.set_uniform_int("texture0", 0);
+            let texture = assets.texture(&terrain.texture, &TextureOptions::default())?;
 
             gl::Enable(gl::DEPTH_TEST);
 
             return Ok(Self {
                 program,
-                vertex_array,
-                index_buffer,
                 texture,
                 light_position: glm::vec3(25.0, 25.0, 25.0),
+                tree,
+                generator,
+                chunks,
+                index_buffers,
+                drawn: vec![Leaf {
+                    key: ROOT,
+                    seams: 0,
+                }],
+                frame: 0,
             });
         }
     }
 
-    pub fn draw(
-        &self,
+    // uploads finished chunks and picks the leaves for where the camera is,
+    // once a frame before drawing
+    pub fn update(
+        &mut self,
         camera: &Camera,
     )
     {
-        unsafe {
-            gl::ClearColor(0.2, 0.3, 0.3, 0.7);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        self.frame += 1;
+        for (key, mesh) in self.generator.finished()
+        {
+            self.chunks.insert(key, unsafe { Chunk::new(&mesh) });
+        }
 
-            self.texture.activate();
+        let wanted = self.tree.select(camera.camera_position);
+        let mut ready = true;
+        for leaf in wanted.iter().chain(&self.drawn)
+        {
+            match self.chunks.get_mut(&leaf.key)
+            {
+                Some(chunk) => chunk.used = self.frame,
+                None =>
+                {
+                    ready = false;
+                    self.generator.request(leaf.key);
+                }
+            }
+        }
+        // a mix of old and new leaves could be more than a level apart
+        if ready
+        {
+            self.drawn = wanted;
+        }
 
-            let view = camera.get_view_matrix();
-            self.program.set_uniform_mat4("view", view);
+        if self.chunks.len() > CACHED_CHUNKS
+        {
+            let mut unused: Vec<_> = self
+                .chunks
+                .iter()
+                .filter(|(_, chunk)| chunk.used != self.frame)
+                .map(|(key, chunk)| (chunk.used, *key))
+                .collect();
+            unused.sort_unstable_by_key(|(used, _)| *used);
+            let excess = self.chunks.len() - CACHED_CHUNKS;
+            for (_, key) in unused.into_iter().take(excess)
+            {
+                self.chunks.remove(&key);
+            }
+        }
+    }
 
-            let projection =
-                glm::ext::perspective(glm::radians(camera.fov), 1920.0 / 1080.0, 0.1, 100.0);
-            self.program.set_uniform_mat4("projection", projection);
+    // view and projection come from the frame uniform buffer, the camera is
+    // only used to leave out chunks that are off screen
+    pub fn draw(
+        &self,
+        camera: &Camera,
+    )
+    {
+        unsafe {
+            self.program.bind();
+            self.texture.activate(0);
+            self.program.set_uniform_sampler("texture0", 0);
 
             #[rustfmt::skip]
             let model = glm::mat4(
@@ -96,7 +646,19 @@ impl TerrianRenderer
             );
             self.program.set_uniform_vec3("lightPos", moving_light);
 
-            renderer::draw(&self.vertex_array, &self.index_buffer, &self.program)
+            let frustum = Frustum::new(camera.get_projection_matrix() * camera.get_view_matrix());
+            for leaf in &self.drawn
+            {
+                let chunk = &self.chunks[&leaf.key];
+                if frustum.intersects_box(chunk.min, chunk.max)
+                {
+                    renderer::draw(
+                        &chunk.vertex_array,
+                        &self.index_buffers[leaf.seams as usize],
+                        &self.program,
+                    );
+                }
+            }
         }
     }
 
@@ -112,99 +674,323 @@ impl TerrianRenderer
     }
 }
 
-impl Default for TerrianRenderer
+impl Drop for TerrianRenderer
 {
-    fn default() -> Self
+    fn drop(&mut self)
     {
-        Self::new().unwrap()
+        unsafe {
+            for index_buffer in &self.index_buffers
+            {
+                gl::DeleteBuffers(1, &index_buffer.id);
+            }
+        }
     }
 }
 
-impl Drop for TerrianRenderer
+// the terrain's heights on the same grid as its vertices, for things that
+// stand on it
+pub struct HeightField
 {
-    fn drop(&mut self)
+    spacing: f32,
+    size: usize,
+    heights: Vec<f32>,
+}
+
+impl HeightField
+{
+    // a sample per pixel of the square the map's shorter side makes
+    pub fn from_image(
+        height_map: &DynamicImage,
+        width: f32,
+    ) -> Self
     {
-        unsafe {
-            gl::DeleteBuffers(1, &self.vertex_array.vbo);
-            gl::DeleteVertexArrays(1, &self.vertex_array.vao);
+        let image = height_map.to_luma16();
+        let size = image.width().min(image.height()) as usize;
+        let mut heights = Vec::with_capacity(size * size);
+        for row in 0..size as u32
+        {
+            for col in 0..size as u32
+            {
+                heights.push(image.get_pixel(col, row).0[0] as f32 * HEIGHT_SCALE);
+            }
+        }
+        Self {
+            spacing: width / size.saturating_sub(1).max(1) as f32,
+            size,
+            heights,
+        }
+    }
+
+    // blends the four nearest heights, None off the edge of the terrain
+    pub fn height_at(
+        &self,
+        x: f32,
+        z: f32,
+    ) -> Option<f32>
+    {
+        let (col, row) = (x / self.spacing, z / self.spacing);
+        let last = self.size.checked_sub(1)? as f32;
+        if !(0.0..=last).contains(&col) || !(0.0..=last).contains(&row)
+        {
+            return None;
         }
+        let (col0, row0) = (col.floor() as usize, row.floor() as usize);
+        let (col1, row1) = ((col0 + 1).min(self.size - 1), (row0 + 1).min(self.size - 1));
+        let (tx, tz) = (col.fract(), row.fract());
+        let height = |col: usize, row: usize| self.heights[row * self.size + col];
+        let near = height(col0, row0) + (height(col1, row0) - height(col0, row0)) * tx;
+        let far = height(col0, row1) + (height(col1, row1) - height(col0, row1)) * tx;
+        Some(near + (far - near) * tz)
     }
 }
 
-pub fn generate_terrian_vertices(
-    width: f32,
-    divisions: i32,
-) -> Vec<f32>
-{
-    let img = image::open("./resources/map/my_height_map.png")
-        .unwrap()
-        .into_luma16();
-    let normal_img = image::open("./resources/map/normal_map.png")
-        .unwrap()
-        .into_rgba8();
-    let mut output = vec![];
-    let triangle_side = width / divisions as f32;
-    for row in 0..divisions
+#[cfg(test)]
+mod test
+{
+    use std::{sync::Arc, time::Duration};
+
+    use glm::vec3;
+    use image::{DynamicImage, ImageBuffer, Luma, Rgba};
+
+    use super::{
+        chunk_indices, chunk_mesh, ChunkGenerator, HeightField, NodeKey, Quadtree, TerrainSamples,
+        CHUNK_CELLS, HEIGHT_SCALE,
+    };
+
+    #[test]
+    fn test_height_field()
+    {
+        // a 3x3 map rising along x, 2 units between samples
+        let image = ImageBuffer::from_fn(3, 3, |x, _| Luma([x as u16 * 4000]));
+        let field = HeightField::from_image(&DynamicImage::ImageLuma16(image), 4.0);
+        assert_eq!(field.height_at(0.0, 0.0), Some(0.0));
+        assert_eq!(field.height_at(4.0, 4.0), Some(2.0));
+        assert_eq!(field.height_at(1.0, 3.0), Some(0.5));
+        assert_eq!(field.height_at(-0.1, 0.0), None);
+        assert_eq!(field.height_at(0.0, 4.5), None);
+        assert_eq!(HEIGHT_SCALE * 4000.0, 1.0);
+    }
+
+    #[test]
+    fn test_large_height_map()
+    {
+        // every pixel is a vertex, the far edge is still the last column
+        let image = ImageBuffer::from_fn(1100, 1050, |x, _| Luma([x as u16]));
+        let field = HeightField::from_image(&DynamicImage::ImageLuma16(image), 100.0);
+        assert_eq!(field.size, 1050);
+        let far = field.height_at(100.0, 100.0).unwrap();
+        assert!((far - 1049.0 * HEIGHT_SCALE).abs() < 1e-6);
+        assert_eq!(field.height_at(100.1, 0.0), None);
+
+        let tree = Quadtree::new(&field);
+        assert_eq!(tree.cells, 1049);
+        assert!(CHUNK_CELLS << tree.depth >= tree.cells);
+    }
+
+    // a bumpy map of 120 cells a side, one unit apart, so the tree is two
+    // levels deep and its last nodes stick out past the edge
+    fn samples() -> TerrainSamples
     {
-        for col in 0..divisions + 1
+        let heights = ImageBuffer::from_fn(121, 121, |x, z| {
+            Luma([((x * 7 + z * 13) % 50) as u16 * 400])
+        });
+        let normals = ImageBuffer::from_fn(121, 121, |x, _| Rgba([x as u8, 255, 0, 255]));
+        TerrainSamples::from_images(
+            &DynamicImage::ImageLuma16(heights),
+            &DynamicImage::ImageRgba8(normals),
+            120.0,
+        )
+    }
+
+    #[test]
+    fn test_seams_leave_no_gaps()
+    {
+        let last = CHUNK_CELLS as i32;
+        let position = |index: i32| (index % (last + 1), index / (last + 1));
+        for seams in 0..16
         {
-            // vertex data
-            output.push(col as f32 * triangle_side);
-            if col >= divisions
+            let indices = chunk_indices(seams);
+            // every triangle faces the same way, and together they cover the
+            // whole node once
+            let mut area = 0;
+            for triangle in indices.chunks(3)
             {
-                let pixel = img.get_pixel(col as u32 - 1, row as u32).0[0];
-                output.push(pixel as f32 / 3000.0); // can we give this height?
+                let [a, b, c] = [0, 1, 2].map(|corner| position(triangle[corner]));
+                let doubled = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+                assert!(doubled > 0, "{:?} with seams {}", triangle, seams);
+                area += doubled;
             }
-            else
+            assert_eq!(area, 2 * last * last);
+
+            // no odd vertex on a seam edge is used
+            for index in indices
             {
-                let pixel = img.get_pixel(col as u32, row as u32).0[0];
-                output.push(pixel as f32 / 4000.0); // can we give this height?
+                let (col, row) = position(index);
+                let north = seams & super::NORTH != 0 && row == 0;
+                let south = seams & super::SOUTH != 0 && row == last;
+                let west = seams & super::WEST != 0 && col == 0;
+                let east = seams & super::EAST != 0 && col == last;
+                assert!(!((north || south) && col % 2 == 1));
+                assert!(!((west || east) && row % 2 == 1));
             }
-            output.push((row as f32 * triangle_side) as f32);
-
-            output.push((col as f32 * triangle_side) / width);
-            output.push((row as f32 * triangle_side) / width);
+        }
+    }
 
-            if col >= divisions
+    #[test]
+    fn test_selection_is_balanced()
+    {
+        let samples = samples();
+        let tree = Quadtree::new(&samples.heights);
+        assert_eq!(tree.depth, 2);
+        for eye in [
+            vec3(0.0, 0.0, 0.0),
+            vec3(60.0, 5.0, 60.0),
+            vec3(119.0, 1.0, 3.0),
+            vec3(-30.0, 20.0, 200.0),
+            vec3(60.0, 1000.0, 60.0),
+        ]
+        {
+            let leaves = tree.select(eye);
+            // the cells each leaf covers, as [x0, x1) by [z0, z1)
+            let bounds = |key: NodeKey| {
+                let (x, z) = tree.origin(key);
+                let span = CHUNK_CELLS * tree.step(key);
+                (x, x + span, z, z + span)
+            };
+            // every cell of the terrain is in exactly one leaf
+            for z in 0..tree.cells
             {
-                let pixel = normal_img.get_pixel((col - 1) as u32, row as u32);
-                let pixel = pixel.0;
-                output.push(pixel[0] as f32);
-                output.push(pixel[1] as f32);
-                output.push(pixel[2] as f32);
+                for x in 0..tree.cells
+                {
+                    let covering = leaves
+                        .iter()
+                        .filter(|leaf| {
+                            let (x0, x1, z0, z1) = bounds(leaf.key);
+                            (x0..x1).contains(&x) && (z0..z1).contains(&z)
+                        })
+                        .count();
+                    assert_eq!(covering, 1, "cell {} {} from {:?}", x, z, eye);
+                }
             }
-            else
+            // leaves sharing an edge are at most a level apart, and the finer
+            // one has a seam there
+            for a in &leaves
             {
-                let pixel = normal_img.get_pixel(col as u32, row as u32);
-                let pixel = pixel.0;
-                output.push(pixel[0] as f32);
-                output.push(pixel[1] as f32);
-                output.push(pixel[2] as f32);
+                for b in &leaves
+                {
+                    let (ax0, ax1, az0, az1) = bounds(a.key);
+                    let (bx0, bx1, bz0, bz1) = bounds(b.key);
+                    let overlap_x = ax0.max(bx0) < ax1.min(bx1);
+                    let overlap_z = az0.max(bz0) < az1.min(bz1);
+                    let (edge, touching) = if ax1 == bx0 && overlap_z
+                    {
+                        (super::EAST, true)
+                    }
+                    else if ax0 == bx1 && overlap_z
+                    {
+                        (super::WEST, true)
+                    }
+                    else if az1 == bz0 && overlap_x
+                    {
+                        (super::SOUTH, true)
+                    }
+                    else if az0 == bz1 && overlap_x
+                    {
+                        (super::NORTH, true)
+                    }
+                    else
+                    {
+                        (0, false)
+                    };
+                    if touching
+                    {
+                        assert!(a.key.depth.abs_diff(b.key.depth) <= 1, "{:?} {:?}", a, b);
+                        assert_eq!(a.seams & edge != 0, a.key.depth > b.key.depth);
+                    }
+                }
             }
         }
-    }
 
-    output
-}
+        // close up is full detail
+        let leaves = tree.select(vec3(10.0, 0.0, 10.0));
+        assert!(leaves
+            .iter()
+            .any(|leaf| leaf.key.depth == tree.depth && tree.origin(leaf.key) == (0, 0)));
+        // and far away one chunk does
+        assert_eq!(tree.select(vec3(0.0, 5000.0, 0.0)).len(), 1);
+    }
 
-pub fn generate_terrian_ebo(divisions: i32) -> Vec<i32>
-{
-    let mut output = vec![];
-    for row in 0..divisions - 1
+    #[test]
+    fn test_chunk_mesh()
     {
-        for col in 0..divisions - 1
-        {
-            let index = row * (divisions + 1) + col;
+        let samples = samples();
+        let tree = Quadtree::new(&samples.heights);
+        let vertex = |mesh: &[f32], col: u32, row: u32| {
+            let start = ((row * (CHUNK_CELLS + 1) + col) * 8) as usize;
+            mesh[start..start + 8].to_vec()
+        };
 
-            output.push(index);
-            output.push(index + (divisions + 1) + 1);
-            output.push(index + (divisions + 1));
+        // the same heights as the height field, at its sample positions
+        let key = NodeKey {
+            depth: 2,
+            x: 1,
+            z: 0,
+        };
+        let mesh = chunk_mesh(&samples, &tree, key);
+        let corner = vertex(&mesh.vertices, 0, 0);
+        assert_eq!(
+            corner[..3],
+            [32.0, samples.heights.height_at(32.0, 0.0).unwrap(), 0.0]
+        );
+        assert_eq!(corner[3..5], [32.0 / 120.0, 0.0]);
+        assert_eq!(corner[5..], [32.0, 255.0, 0.0]);
 
-            output.push(index);
-            output.push(index + 1);
-            output.push(index + (divisions + 1) + 1)
+        // neighbours share their edge vertices exactly
+        let left = chunk_mesh(&samples, &tree, NodeKey { x: 0, ..key });
+        for row in 0..=CHUNK_CELLS
+        {
+            assert_eq!(
+                vertex(&left.vertices, CHUNK_CELLS, row),
+                vertex(&mesh.vertices, 0, row)
+            );
         }
+
+        // the last node along x stops at the edge of the terrain
+        let edge = chunk_mesh(&samples, &tree, NodeKey { x: 3, ..key });
+        assert_eq!(edge.max.x, 120.0);
+        assert_eq!(vertex(&edge.vertices, CHUNK_CELLS, 0)[0], 120.0);
+        assert!(edge.min.y >= 0.0 && edge.max.y <= 49.0 * 400.0 * HEIGHT_SCALE);
     }
 
-    output
+    #[test]
+    fn test_generator()
+    {
+        let samples = Arc::new(samples());
+        let tree = Quadtree::new(&samples.heights);
+        let mut generator = ChunkGenerator::new(samples.clone(), tree);
+        let keys: Vec<_> = super::ROOT.children().into_iter().collect();
+        for key in &keys
+        {
+            generator.request(*key);
+            generator.request(*key);
+        }
+
+        let mut finished = vec![];
+        for _ in 0..500
+        {
+            finished.extend(generator.finished());
+            if finished.len() >= keys.len()
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // each only once, and as the main thread would have made it
+        assert_eq!(finished.len(), keys.len());
+        for (key, mesh) in finished
+        {
+            assert!(keys.contains(&key));
+            assert_eq!(mesh, chunk_mesh(&samples, &tree, key));
+        }
+    }
 }
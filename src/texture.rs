@@ -1,10 +1,110 @@
-use gl::types::GLuint;
-use image;
-use image::EncodableLayout;
+use gl::types::{GLenum, GLint, GLuint};
+use image::{DynamicImage, ImageError};
+use thiserror::Error;
+
+// from EXT_texture_filter_anisotropic, core since 4.6 so the 3.3 bindings
+// don't have them
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84fe;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84ff;
+
+#[derive(Debug, Error)]
+pub enum TextureError
+{
+    #[error("can't load texture {path}: {source}")]
+    Load
+    {
+        path: String, source: ImageError
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace
+{
+    // colors meant for the screen, gl converts them to linear when sampling.
+    // only looks right once the framebuffer does the conversion back.
+    Srgb,
+    // sampled as stored, for data like normals and heights
+    Linear,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextureOptions
+{
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    // 1.0 turns it off, clamped to what the driver supports
+    pub anisotropy: f32,
+    pub mipmaps: bool,
+    pub color_space: ColorSpace,
+}
+
+impl Default for TextureOptions
+{
+    fn default() -> Self
+    {
+        Self {
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::REPEAT,
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            anisotropy: 1.0,
+            mipmaps: true,
+            color_space: ColorSpace::Linear,
+        }
+    }
+}
+
+// how an image's pixels go to TexImage2D
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PixelFormat
+{
+    internal: GLenum,
+    format: GLenum,
+    kind: GLenum,
+    // gray images are stored in the red channel, and alpha in green
+    swizzle: Option<[GLenum; 4]>,
+}
+
+fn pixel_format(
+    image: &DynamicImage,
+    color_space: ColorSpace,
+) -> PixelFormat
+{
+    let srgb = color_space == ColorSpace::Srgb;
+    let gray = Some([gl::RED, gl::RED, gl::RED, gl::ONE]);
+    let gray_alpha = Some([gl::RED, gl::RED, gl::RED, gl::GREEN]);
+    let (internal, format, kind, swizzle) = match image
+    {
+        DynamicImage::ImageLuma8(_) => (gl::R8, gl::RED, gl::UNSIGNED_BYTE, gray),
+        DynamicImage::ImageLumaA8(_) => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE, gray_alpha),
+        DynamicImage::ImageRgb8(_) if srgb => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE, None),
+        DynamicImage::ImageRgb8(_) => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE, None),
+        DynamicImage::ImageLuma16(_) => (gl::R16, gl::RED, gl::UNSIGNED_SHORT, gray),
+        DynamicImage::ImageLumaA16(_) => (gl::RG16, gl::RG, gl::UNSIGNED_SHORT, gray_alpha),
+        // there are no 16 bit srgb formats, these stay linear
+        DynamicImage::ImageRgb16(_) => (gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT, None),
+        DynamicImage::ImageRgba16(_) => (gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT, None),
+        DynamicImage::ImageRgb32F(_) => (gl::RGB32F, gl::RGB, gl::FLOAT, None),
+        DynamicImage::ImageRgba32F(_) => (gl::RGBA32F, gl::RGBA, gl::FLOAT, None),
+        // rgba8, and anything newer image versions add is converted to it
+        _ if srgb => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE, None),
+        _ => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE, None),
+    };
+    PixelFormat {
+        internal,
+        format,
+        kind,
+        swizzle,
+    }
+}
 
 pub struct Texture
 {
     pub id: GLuint,
+    pub width: u32,
+    pub height: u32,
 }
 
 impl Drop for Texture
@@ -17,46 +117,122 @@ impl Drop for Texture
 
 impl Texture
 {
-    pub unsafe fn new() -> Self
+    pub unsafe fn from_path(
+        path: &str,
+        options: &TextureOptions,
+    ) -> Result<Self, TextureError>
     {
-        let mut id: GLuint = 0;
-        gl::GenTextures(1, &mut id);
-        Self { id }
+        let image = image::open(path).map_err(|source| TextureError::Load {
+            path: path.to_string(),
+            source,
+        })?;
+        Ok(Self::from_image(&image, options))
     }
 
-    pub unsafe fn load(&self)
+    pub unsafe fn from_image(
+        image: &DynamicImage,
+        options: &TextureOptions,
+    ) -> Self
     {
-        self.bind();
-
-        let img = image::open("./resources/map/desert_mountains.png").unwrap();
-        let bit_map = img.into_rgba8();
+        let mut id: GLuint = 0;
+        gl::GenTextures(1, &mut id);
+        let texture = Self {
+            id,
+            width: image.width(),
+            height: image.height(),
+        };
+        texture.bind();
 
+        let format = pixel_format(image, options.color_space);
+        let rgba;
+        let bytes = match format.internal
+        {
+            gl::RGBA8 | gl::SRGB8_ALPHA8 if image.as_rgba8().is_none() =>
+            {
+                rgba = image.to_rgba8();
+                rgba.as_raw().as_slice()
+            }
+            _ => image.as_bytes(),
+        };
+        // rows of rgb or gray pixels aren't always 4 byte aligned
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
         gl::TexImage2D(
             gl::TEXTURE_2D,
             0,
-            gl::RGBA as i32,
-            bit_map.width() as i32,
-            bit_map.height() as i32,
+            format.internal as GLint,
+            texture.width as i32,
+            texture.height as i32,
             0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            bit_map.as_bytes().as_ptr() as *const _,
+            format.format,
+            format.kind,
+            bytes.as_ptr() as *const _,
         );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        if let Some(swizzle) = format.swizzle
+        {
+            let swizzle = swizzle.map(|channel| channel as GLint);
+            gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+        }
+        if options.mipmaps
+        {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        texture.set_wrap(options.wrap_s, options.wrap_t);
+        // without mipmaps a mipmapped min filter would sample nothing
+        let min_filter = match (options.mipmaps, options.min_filter)
+        {
+            (false, gl::NEAREST_MIPMAP_NEAREST | gl::NEAREST_MIPMAP_LINEAR) => gl::NEAREST,
+            (false, gl::LINEAR_MIPMAP_NEAREST | gl::LINEAR_MIPMAP_LINEAR) => gl::LINEAR,
+            (_, filter) => filter,
+        };
+        texture.set_filter(min_filter, options.mag_filter);
+        texture.set_anisotropy(options.anisotropy);
+        texture
     }
 
-    pub unsafe fn set_wrap_settings(&self)
+    pub unsafe fn set_wrap(
+        &self,
+        s: GLenum,
+        t: GLenum,
+    )
     {
         self.bind();
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, s as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, t as i32);
     }
 
-    pub unsafe fn set_filter_settings(&self)
+    pub unsafe fn set_filter(
+        &self,
+        min: GLenum,
+        mag: GLenum,
+    )
     {
         self.bind();
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, min as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, mag as i32);
+    }
+
+    // does nothing on drivers without anisotropic filtering
+    pub unsafe fn set_anisotropy(
+        &self,
+        amount: f32,
+    )
+    {
+        let mut max = 0.0;
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+        if max < 1.0
+        {
+            // the query failed, clear the error it left
+            gl::GetError();
+            return;
+        }
+        self.bind();
+        gl::TexParameterf(
+            gl::TEXTURE_2D,
+            TEXTURE_MAX_ANISOTROPY,
+            amount.clamp(1.0, max),
+        );
     }
 
     pub unsafe fn bind(&self)
@@ -64,9 +240,52 @@ impl Texture
         gl::BindTexture(gl::TEXTURE_2D, self.id)
     }
 
-    pub unsafe fn activate(&self)
+    // unit 0 is TEXTURE0, the sampler uniform reading it gets the same number
+    pub unsafe fn activate(
+        &self,
+        unit: u32,
+    )
     {
-        gl::ActiveTexture(gl::TEXTURE0);
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
         self.bind();
     }
 }
+
+#[cfg(test)]
+mod test
+{
+    use image::{DynamicImage, GrayImage, RgbImage, Rgba32FImage, RgbaImage};
+
+    use super::{pixel_format, ColorSpace};
+
+    #[test]
+    fn test_pixel_formats()
+    {
+        let rgb = DynamicImage::ImageRgb8(RgbImage::new(3, 1));
+        assert_eq!(pixel_format(&rgb, ColorSpace::Srgb).internal, gl::SRGB8);
+        assert_eq!(pixel_format(&rgb, ColorSpace::Linear).internal, gl::RGB8);
+
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::new(1, 1));
+        assert_eq!(
+            pixel_format(&rgba, ColorSpace::Srgb).internal,
+            gl::SRGB8_ALPHA8
+        );
+
+        let gray = pixel_format(
+            &DynamicImage::ImageLuma8(GrayImage::new(1, 1)),
+            ColorSpace::Srgb,
+        );
+        assert_eq!((gray.internal, gray.format), (gl::R8, gl::RED));
+        assert_eq!(gray.swizzle, Some([gl::RED, gl::RED, gl::RED, gl::ONE]));
+
+        let wide = DynamicImage::ImageRgb16(image::ImageBuffer::new(1, 1));
+        let wide = pixel_format(&wide, ColorSpace::Srgb);
+        assert_eq!((wide.internal, wide.kind), (gl::RGB16, gl::UNSIGNED_SHORT));
+
+        let float = pixel_format(
+            &DynamicImage::ImageRgba32F(Rgba32FImage::new(1, 1)),
+            ColorSpace::Linear,
+        );
+        assert_eq!((float.internal, float.kind), (gl::RGBA32F, gl::FLOAT));
+    }
+}
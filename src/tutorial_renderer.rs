@@ -4,68 +4,59 @@ use gl::types::GLuint;
 use glm::Vec3;
 
 use crate::{
-    camera::Camera,
+    assets::{AssetError, Assets, Handle},
+    mesh::{self, Mesh, CUBE_OBJ},
     program::Program,
     renderer,
-    shader::{Shader, ShaderError},
 };
 
 pub struct TutorialRenderer
 {
-    program: Program,
-    lighting_program: Program,
-    vertex_array: renderer::VertexArray,
+    program: Handle<Program>,
+    lighting_program: Handle<Program>,
+    cube: Handle<Mesh>,
     light_vertex_array: renderer::VertexArray,
     light_position: Vec3,
 }
 
 impl TutorialRenderer
 {
-    pub fn new() -> Result<Self, ShaderError>
+    pub fn new(assets: &mut Assets) -> Result<Self, AssetError>
     {
         unsafe {
-            let vertex_shader = Shader::new(
-                "./resources/shaders/lighting/colors_vert.glsl",
-                gl::VERTEX_SHADER,
+            let program = assets.program(
+                "shaders/lighting/colors_vert.glsl",
+                "shaders/lighting/colors_frag.glsl",
             )?;
-            let fragment_shader = Shader::new(
-                "./resources/shaders/lighting/colors_frag.glsl",
-                gl::FRAGMENT_SHADER,
+            let lighting_program = assets.program(
+                "shaders/lighting/light_cube_vert.glsl",
+                "shaders/lighting/light_cube_frag.glsl",
             )?;
-            let program = Program::new(&[vertex_shader, fragment_shader])?;
+            let cube = assets.mesh(CUBE_OBJ)?;
 
-            let lighting_vertex_shader = Shader::new(
-                "./resources/shaders/lighting/light_cube_vert.glsl",
-                gl::VERTEX_SHADER,
-            )?;
-            let lighting_frag_shader = Shader::new(
-                "./resources/shaders/lighting/light_cube_frag.glsl",
-                gl::FRAGMENT_SHADER,
-            )?;
-            let lighting_program = Program::new(&[lighting_vertex_shader, lighting_frag_shader])?;
-
-            let mut vertex_array = renderer::VertexArray::new(&VERTEX_DATA, 6);
-            vertex_array.add_vert_att_ptr(3);
-
-            let mut light_vertex_array = renderer::VertexArray::new_with_vbo(vertex_array.vbo, 6);
+            // the light cube shares the vertex and index buffers
+            let mut light_vertex_array =
+                renderer::VertexArray::new_with_vbo(cube.vertex_array.vbo, mesh::STRIDE);
+            gl::BindBuffer(gl::ARRAY_BUFFER, cube.vertex_array.vbo);
             light_vertex_array.add_vert_att_ptr(3);
             light_vertex_array.add_vert_att_ptr(3);
+            light_vertex_array.add_vert_att_ptr(2);
+            cube.index_buffer.bind();
 
             gl::Enable(gl::DEPTH_TEST);
             return Ok(Self {
                 program,
                 lighting_program,
-                vertex_array,
+                cube,
                 light_vertex_array,
                 light_position: glm::vec3(1.2, 1.0, 2.0),
             });
         }
     }
 
-    pub fn draw(
-        &self,
-        camera: &Camera,
-    )
+    // view, projection and the camera position come from the frame uniform
+    // buffer
+    pub fn draw(&self)
     {
         unsafe {
             let time = SystemTime::now();
@@ -84,21 +75,12 @@ impl TutorialRenderer
             gl::ClearColor(0.2, 0.3, 0.3, 0.7);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
-            gl::UseProgram(self.program.id);
+            self.program.bind();
             self.program
                 .set_uniform_vec3("objectColor", glm::vec3(1.0, 0.5, 0.31));
             self.program
                 .set_uniform_vec3("lightColor", glm::vec3(1.0, 1.0, 1.0));
             self.program.set_uniform_vec3("lightPos", moving_light);
-            self.program
-                .set_uniform_vec3("viewPos", camera.camera_position);
-
-            let view = camera.get_view_matrix();
-            self.program.set_uniform_mat4("view", view);
-
-            let projection =
-                glm::ext::perspective(glm::radians(camera.fov), 1920.0 / 1080.0, 0.1, 100.0);
-            self.program.set_uniform_mat4("projection", projection);
 
             #[rustfmt::skip]
             let model = glm::mat4(
@@ -109,13 +91,9 @@ impl TutorialRenderer
             );
             self.program.set_uniform_mat4("model", model);
 
-            gl::BindVertexArray(self.vertex_array.vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            self.cube.draw(&self.program);
 
-            gl::UseProgram(self.lighting_program.id);
-            self.lighting_program.set_uniform_mat4("view", view);
-            self.lighting_program
-                .set_uniform_mat4("projection", projection);
+            self.lighting_program.bind();
 
             #[rustfmt::skip]
             let mut model = glm::mat4(
@@ -128,8 +106,11 @@ impl TutorialRenderer
             model = glm::ext::scale(&model, glm::vec3(0.2, 0.2, 0.2));
             self.lighting_program.set_uniform_mat4("model", model);
 
-            gl::BindVertexArray(self.light_vertex_array.vao);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            renderer::draw(
+                &self.light_vertex_array,
+                &self.cube.index_buffer,
+                &self.lighting_program,
+            );
         }
     }
 
@@ -144,48 +125,3 @@ impl TutorialRenderer
         }
     }
 }
-
-#[rustfmt::skip]
-const VERTEX_DATA: [f32; 216] = [
-    -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-     0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-    -0.5,  0.5, -0.5,  0.0,  0.0, -1.0,
-    -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,
-
-    -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-     0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-    -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,
-    -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,
-
-    -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,
-    -0.5,  0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,
-    -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,
-    -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,
-
-     0.5,  0.5,  0.5,  1.0,  0.0,  0.0,
-     0.5,  0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5, -0.5,  1.0,  0.0,  0.0,
-     0.5, -0.5,  0.5,  1.0,  0.0,  0.0,
-     0.5,  0.5,  0.5,  1.0,  0.0,  0.0,
-
-    -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-     0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-    -0.5, -0.5,  0.5,  0.0, -1.0,  0.0,
-    -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,
-
-    -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-     0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-    -0.5,  0.5,  0.5,  0.0,  1.0,  0.0,
-    -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,
-];
@@ -0,0 +1,372 @@
+use gl::types::{GLsizeiptr, GLuint};
+use glm::{Mat3, Mat4, Vec2, Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    components::{Light, LightKind},
+};
+
+// binding point of the Frame block in shaders/common/frame.glsl
+pub const FRAME_BINDING: GLuint = 0;
+// must match MAX_LIGHTS in shaders/common/frame.glsl
+pub const MAX_LIGHTS: usize = 8;
+// blocks that Program::new points at their binding point when it finds them
+pub const BLOCK_BINDINGS: &[(&str, GLuint)] = &[("Frame", FRAME_BINDING)];
+
+// packs values with the std140 rules, in the order a uniform block declares
+// them. each write returns the offset it was put at.
+#[derive(Debug, Default)]
+pub struct Std140
+{
+    bytes: Vec<u8>,
+}
+
+impl Std140
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    fn align(
+        &mut self,
+        alignment: usize,
+    ) -> usize
+    {
+        self.bytes
+            .resize(self.bytes.len().next_multiple_of(alignment), 0);
+        self.bytes.len()
+    }
+
+    fn push(
+        &mut self,
+        alignment: usize,
+        values: &[f32],
+    ) -> usize
+    {
+        let offset = self.align(alignment);
+        for value in values
+        {
+            self.bytes.extend_from_slice(&value.to_ne_bytes());
+        }
+        offset
+    }
+
+    pub fn float(
+        &mut self,
+        value: f32,
+    ) -> usize
+    {
+        self.push(4, &[value])
+    }
+
+    pub fn int(
+        &mut self,
+        value: i32,
+    ) -> usize
+    {
+        let offset = self.align(4);
+        self.bytes.extend_from_slice(&value.to_ne_bytes());
+        offset
+    }
+
+    pub fn vec2(
+        &mut self,
+        value: Vec2,
+    ) -> usize
+    {
+        self.push(8, value.as_array())
+    }
+
+    // aligned like a vec4, but a float after it can use the last 4 bytes
+    pub fn vec3(
+        &mut self,
+        value: Vec3,
+    ) -> usize
+    {
+        self.push(16, value.as_array())
+    }
+
+    pub fn vec4(
+        &mut self,
+        value: Vec4,
+    ) -> usize
+    {
+        self.push(16, value.as_array())
+    }
+
+    // each column takes a vec4
+    pub fn mat3(
+        &mut self,
+        value: Mat3,
+    ) -> usize
+    {
+        let offset = self.align(16);
+        for column in value.as_array()
+        {
+            self.push(16, column.as_array());
+        }
+        self.align(16);
+        offset
+    }
+
+    pub fn mat4(
+        &mut self,
+        value: Mat4,
+    ) -> usize
+    {
+        let offset = self.align(16);
+        for column in value.as_array()
+        {
+            self.push(16, column.as_array());
+        }
+        offset
+    }
+
+    // every element of a scalar array takes a vec4
+    pub fn float_array(
+        &mut self,
+        values: &[f32],
+    ) -> usize
+    {
+        let offset = self.align(16);
+        for value in values
+        {
+            self.push(16, &[*value]);
+            self.align(16);
+        }
+        offset
+    }
+
+    // structs, and arrays of them, start and end on 16 bytes. write the
+    // members between begin_struct and end_struct.
+    pub fn begin_struct(&mut self) -> usize
+    {
+        self.align(16)
+    }
+
+    pub fn end_struct(&mut self)
+    {
+        self.align(16);
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.bytes.len()
+    }
+
+    // the block's size is rounded up to 16 bytes
+    pub fn finish(mut self) -> Vec<u8>
+    {
+        self.align(16);
+        self.bytes
+    }
+}
+
+pub struct UniformBuffer
+{
+    id: GLuint,
+    binding: GLuint,
+}
+
+impl Drop for UniformBuffer
+{
+    fn drop(&mut self)
+    {
+        unsafe { gl::DeleteBuffers(1, &self.id) }
+    }
+}
+
+impl UniformBuffer
+{
+    pub unsafe fn new(binding: GLuint) -> Self
+    {
+        let mut id = 0;
+        gl::GenBuffers(1, &mut id);
+        Self { id, binding }
+    }
+
+    // replaces the contents and binds the buffer to its binding point, where
+    // every program whose block uses that point reads it
+    pub unsafe fn upload(
+        &self,
+        bytes: &[u8],
+    )
+    {
+        gl::BindBuffer(gl::UNIFORM_BUFFER, self.id);
+        gl::BufferData(
+            gl::UNIFORM_BUFFER,
+            bytes.len() as GLsizeiptr,
+            bytes.as_ptr() as *const _,
+            gl::DYNAMIC_DRAW,
+        );
+        gl::BindBufferBase(gl::UNIFORM_BUFFER, self.binding, self.id);
+    }
+}
+
+// everything the Frame block holds, uploaded once before drawing
+pub struct FrameData<'a>
+{
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub camera_position: Vec3,
+    // seconds since the start
+    pub time: f32,
+    // the ones past MAX_LIGHTS are left out
+    pub lights: &'a [Light],
+}
+
+impl<'a> FrameData<'a>
+{
+    pub fn new(
+        camera: &Camera,
+        time: f32,
+        lights: &'a [Light],
+    ) -> Self
+    {
+        Self {
+            view: camera.get_view_matrix(),
+            projection: camera.get_projection_matrix(),
+            camera_position: camera.camera_position,
+            time,
+            lights,
+        }
+    }
+
+    pub fn std140(&self) -> Vec<u8>
+    {
+        let lights = &self.lights[..self.lights.len().min(MAX_LIGHTS)];
+        let mut block = Std140::new();
+        block.mat4(self.view);
+        block.mat4(self.projection);
+        block.vec3(self.camera_position);
+        block.float(self.time);
+        block.int(lights.len() as i32);
+        for index in 0..MAX_LIGHTS
+        {
+            // unused slots are zeroed
+            let (position, color) = match lights.get(index)
+            {
+                Some(light) => light_vectors(light),
+                None => (glm::vec4(0.0, 0.0, 0.0, 0.0), glm::vec4(0.0, 0.0, 0.0, 0.0)),
+            };
+            block.begin_struct();
+            block.vec4(position);
+            block.vec4(color);
+            block.end_struct();
+        }
+        block.finish()
+    }
+}
+
+// points have w 1 and directional lights w 0, intensity goes in the color's w
+fn light_vectors(light: &Light) -> (Vec4, Vec4)
+{
+    let position = match light.kind
+    {
+        LightKind::Point { position } => position.extend(1.0),
+        LightKind::Directional { direction } => direction.extend(0.0),
+    };
+    (position, light.color.extend(light.intensity))
+}
+
+#[cfg(test)]
+mod test
+{
+    use std::fs;
+
+    use glm::{Mat3, Mat4};
+
+    use super::{FrameData, Std140, MAX_LIGHTS};
+    use crate::components::{Light, LightKind};
+
+    #[test]
+    fn test_std140_offsets()
+    {
+        let mut block = Std140::new();
+        assert_eq!(block.float(1.0), 0);
+        // vec3 rounds up to 16, and the float after it fills its last 4 bytes
+        assert_eq!(block.vec3(glm::vec3(1.0, 2.0, 3.0)), 16);
+        assert_eq!(block.float(4.0), 28);
+        assert_eq!(block.vec2(glm::vec2(1.0, 2.0)), 32);
+        assert_eq!(block.int(3), 40);
+        assert_eq!(block.vec4(glm::vec4(1.0, 2.0, 3.0, 4.0)), 48);
+        // mat3 columns each take 16 bytes
+        let identity = Mat3::new(
+            glm::vec3(1.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            glm::vec3(0.0, 0.0, 1.0),
+        );
+        assert_eq!(block.mat3(identity), 64);
+        assert_eq!(block.float(5.0), 112);
+        assert_eq!(
+            block.mat4(Mat4::new(
+                glm::vec4(1.0, 0.0, 0.0, 0.0),
+                glm::vec4(0.0, 1.0, 0.0, 0.0),
+                glm::vec4(0.0, 0.0, 1.0, 0.0),
+                glm::vec4(0.0, 0.0, 0.0, 1.0),
+            )),
+            128
+        );
+        // scalar array elements have a 16 byte stride
+        assert_eq!(block.float_array(&[1.0, 2.0]), 192);
+        assert_eq!(block.len(), 224);
+        assert_eq!(block.begin_struct(), 224);
+        block.float(6.0);
+        block.end_struct();
+        assert_eq!(block.float(7.0), 240);
+        assert_eq!(block.finish().len(), 256);
+    }
+
+    fn float_at(
+        bytes: &[u8],
+        offset: usize,
+    ) -> f32
+    {
+        f32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_frame_layout()
+    {
+        let identity = Mat4::new(
+            glm::vec4(1.0, 0.0, 0.0, 0.0),
+            glm::vec4(0.0, 1.0, 0.0, 0.0),
+            glm::vec4(0.0, 0.0, 1.0, 0.0),
+            glm::vec4(0.0, 0.0, 0.0, 1.0),
+        );
+        let lights = [Light {
+            kind: LightKind::Directional {
+                direction: glm::vec3(0.0, -1.0, 0.0),
+            },
+            color: glm::vec3(1.0, 0.5, 0.25),
+            intensity: 2.0,
+        }];
+        let frame = FrameData {
+            view: identity,
+            projection: identity * 2.0,
+            camera_position: glm::vec3(1.0, 2.0, 3.0),
+            time: 9.5,
+            lights: &lights,
+        };
+        let bytes = frame.std140();
+        assert_eq!(bytes.len(), 160 + MAX_LIGHTS * 32);
+        assert_eq!(float_at(&bytes, 64), 2.0);
+        assert_eq!(float_at(&bytes, 128 + 8), 3.0);
+        assert_eq!(float_at(&bytes, 140), 9.5);
+        assert_eq!(i32::from_ne_bytes(bytes[144..148].try_into().unwrap()), 1);
+        // lights[0].position, then lights[0].color
+        assert_eq!(float_at(&bytes, 164), -1.0);
+        assert_eq!(float_at(&bytes, 172), 0.0);
+        assert_eq!(float_at(&bytes, 180), 0.5);
+        assert_eq!(float_at(&bytes, 188), 2.0);
+        assert!(bytes[192..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn test_frame_block_matches_shader()
+    {
+        let glsl = fs::read_to_string("./resources/shaders/common/frame.glsl").unwrap();
+        assert!(glsl.contains(&format!("#define MAX_LIGHTS {}", MAX_LIGHTS)));
+    }
+}
@@ -11,12 +11,17 @@ use glutin_winit::{DisplayBuilder, GlWindow};
 use raw_window_handle::HasRawWindowHandle;
 use winit::{
     dpi::PhysicalSize,
-    event::{DeviceEvent, WindowEvent},
+    event::{DeviceEvent, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::{Window, WindowBuilder},
+    window::{CursorGrabMode, Window, WindowBuilder},
 };
 
-use crate::{camera::Camera, colliding_renderer::CubeRenderer};
+use crate::{
+    camera::Camera, camera_controller::CameraController, colliding_renderer::CubeRenderer,
+    input::Input,
+};
+
+pub const WINDOW_TITLE: &str = "We using rust now baby!";
 
 pub fn build_gl_state(
     event_loop: &EventLoop<()>
@@ -24,8 +29,8 @@ pub fn build_gl_state(
 {
     let window_builder = Some(
         WindowBuilder::new()
-            .with_min_inner_size(PhysicalSize::new(1920, 1080))
-            .with_title("We using rust now baby!"),
+            .with_inner_size(PhysicalSize::new(1920, 1080))
+            .with_title(WINDOW_TITLE),
     );
 
     let template = ConfigTemplateBuilder::new()
@@ -67,10 +72,7 @@ pub fn build_gl_state(
     });
 
     let window = window.take().unwrap();
-    let _ = window
-        .set_cursor_grab(winit::window::CursorGrabMode::Confined)
-        .unwrap();
-    let _ = window.set_cursor_visible(false);
+    grab_cursor(&window, true);
 
     let attrs = window.build_surface_attributes(<_>::default());
     let gl_surface = unsafe {
@@ -94,39 +96,46 @@ pub fn build_gl_state(
     state
 }
 
+// a grabbed cursor is hidden and kept in the window, so mouse motion turns the
+// camera. platforms that can't confine it just hide it.
+pub fn grab_cursor(
+    window: &Window,
+    grab: bool,
+)
+{
+    let mode = match grab
+    {
+        true => CursorGrabMode::Confined,
+        false => CursorGrabMode::None,
+    };
+    let _ = window.set_cursor_grab(mode);
+    window.set_cursor_visible(!grab);
+}
+
 pub fn track_user_input(
     event: DeviceEvent,
     camera: &mut Camera,
-    now_keys: &mut [bool; 255],
+    controller: &mut dyn CameraController,
+    input: &mut Input,
 )
 {
     match event
     {
         winit::event::DeviceEvent::MouseMotion { delta } =>
         {
-            camera.handle_mouse_input(delta.0 as f32, delta.1 as f32);
+            controller.mouse_motion(camera, delta.0 as f32, delta.1 as f32);
         }
-        winit::event::DeviceEvent::MouseWheel { .. } =>
-        {}
-        winit::event::DeviceEvent::Key(keyboard_input) =>
+        winit::event::DeviceEvent::MouseWheel { delta } =>
         {
-            if let Some(key_code) = keyboard_input.virtual_keycode
+            // touchpads scroll in pixels, roughly 20 to a line
+            let lines = match delta
             {
-                match keyboard_input.state
-                {
-                    winit::event::ElementState::Pressed =>
-                    {
-                        now_keys[key_code as usize] = true;
-                    }
-                    winit::event::ElementState::Released =>
-                    {
-                        now_keys[key_code as usize] = false;
-                    }
-                }
-            }
+                MouseScrollDelta::LineDelta(_, y) => y,
+                MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+            };
+            controller.mouse_wheel(camera, lines);
         }
-        _ =>
-        {}
+        event => input.device_event(&event),
     }
 }
 
@@ -135,24 +144,16 @@ pub fn handle_window_event(
     control_flow: &mut ControlFlow,
     state: &Option<(PossiblyCurrentContext, Surface<WindowSurface>, Window)>,
     renderer: &CubeRenderer,
+    camera: &mut Camera,
 )
 {
     match event
     {
-        WindowEvent::Resized(size) =>
+        WindowEvent::Resized(size) => resize(size, state, renderer, camera),
+        // the window keeps its logical size, so the pixel size changes
+        WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
         {
-            if size.width != 0 && size.height != 0
-            {
-                if let Some((gl_context, gl_surface, _)) = &state
-                {
-                    gl_surface.resize(
-                        gl_context,
-                        NonZeroU32::new(size.width).unwrap(),
-                        NonZeroU32::new(size.height).unwrap(),
-                    );
-                    renderer.resize(size.width as i32, size.height as i32);
-                }
-            }
+            resize(*new_inner_size, state, renderer, camera)
         }
         WindowEvent::CloseRequested =>
         {
@@ -162,3 +163,25 @@ pub fn handle_window_event(
         {}
     }
 }
+
+fn resize(
+    size: PhysicalSize<u32>,
+    state: &Option<(PossiblyCurrentContext, Surface<WindowSurface>, Window)>,
+    renderer: &CubeRenderer,
+    camera: &mut Camera,
+)
+{
+    if size.width != 0 && size.height != 0
+    {
+        if let Some((gl_context, gl_surface, _)) = &state
+        {
+            gl_surface.resize(
+                gl_context,
+                NonZeroU32::new(size.width).unwrap(),
+                NonZeroU32::new(size.height).unwrap(),
+            );
+            renderer.resize(size.width as i32, size.height as i32);
+        }
+        camera.resize(size.width, size.height);
+    }
+}